@@ -51,7 +51,7 @@
         "ssvnode" = inputs.ethereum-nix.packages.${system}.ssvnode;
         "teku" = inputs.ethereum-nix.packages.${system}.teku;
         # Main
-        "backend" = pkgs.callPackage ./packages/backend { inherit json2nix; };
+        "backend" = pkgs.callPackage ./packages/backend { };
         "default" = config.packages.backend;
         "frontend" = pkgs.callPackage ./webui { };
         "homestakeros-backend" = self.nixosConfigurations.homestakeros-backend.config.system.build.kexecTree;
@@ -93,7 +93,6 @@
           yarn
           yarn2nix
           # Cargo test deps
-          json2nix
           nix
         ];
         languages.rust.enable = true;
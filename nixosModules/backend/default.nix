@@ -13,6 +13,51 @@ in
         description = "The port on which the backend will listen.";
       };
 
+      stateDir = lib.mkOption {
+        type = lib.types.path;
+        default = "/var/lib/homestakeros-backend";
+        description = "Directory where builds and the build index are kept across restarts.";
+      };
+
+      maxBuilds = lib.mkOption {
+        type = lib.types.ints.positive;
+        default = 1;
+        description = "Maximum number of builds to run at the same time; further builds are queued.";
+      };
+
+      signingKey = lib.mkOption {
+        type = lib.types.nullOr lib.types.str;
+        default = "${cfg.stateDir}/signing.key";
+        defaultText = lib.literalExpression ''"''${config.services.homestakeros-backend.stateDir}/signing.key"'';
+        description = "File holding the key that build manifests are signed with; generated if missing. Set to null to disable signing.";
+      };
+
+      schemaFile = lib.mkOption {
+        type = lib.types.nullOr lib.types.path;
+        default = null;
+        description = "Options schema of the homestakeros module, as printed by 'nix eval --json .#schema'. Evaluated from the build flake at startup if null.";
+      };
+
+      retention = {
+        maxAgeDays = lib.mkOption {
+          type = lib.types.nullOr lib.types.ints.positive;
+          default = null;
+          description = "Remove builds that finished more than this many days ago.";
+        };
+
+        maxTotalSizeMB = lib.mkOption {
+          type = lib.types.nullOr lib.types.ints.positive;
+          default = null;
+          description = "Remove the oldest builds while the builds take up more than this many megabytes.";
+        };
+
+        keepPerHost = lib.mkOption {
+          type = lib.types.nullOr lib.types.ints.positive;
+          default = null;
+          description = "Keep only this many of the newest builds of each hostname.";
+        };
+      };
+
       reverseProxy = lib.mkOption {
         type = lib.types.enum [ "none" "nginx" ];
         default = "none";
@@ -33,7 +78,18 @@ in
       after = [ "network.target" ];
       wantedBy = [ "multi-user.target" ];
       serviceConfig = {
-        ExecStart = "${pkgs.backend}/bin/backend --port ${toString cfg.port}";
+        ExecStart = lib.concatStringsSep " " ([
+          "${pkgs.backend}/bin/backend"
+          "--port ${toString cfg.port}"
+          "--state-dir ${cfg.stateDir}"
+          "--max-builds ${toString cfg.maxBuilds}"
+        ]
+        ++ lib.optional (cfg.signingKey != null) "--signing-key ${cfg.signingKey}"
+        ++ lib.optional (cfg.schemaFile != null) "--schema ${cfg.schemaFile}"
+        ++ lib.optional (cfg.reverseProxy == "nginx") "--trusted-proxy 127.0.0.1"
+        ++ lib.optional (cfg.retention.maxAgeDays != null) "--max-age-days ${toString cfg.retention.maxAgeDays}"
+        ++ lib.optional (cfg.retention.maxTotalSizeMB != null) "--max-total-size-mb ${toString cfg.retention.maxTotalSizeMB}"
+        ++ lib.optional (cfg.retention.keepPerHost != null) "--keep-per-host ${toString cfg.retention.keepPerHost}");
         Restart = "always";
         RestartSec = "5";
       };
@@ -46,6 +102,9 @@ in
         locations."/" = {
           proxyPass = "http://127.0.0.1:${toString cfg.port}";
           extraConfig = ''
+            # Name the client for fair scheduling; headers sent by the client are not passed on.
+            proxy_set_header X-Forwarded-For $remote_addr;
+            proxy_set_header Forwarded "";
             proxy_connect_timeout 3600s;
             proxy_send_timeout 3600s;
             proxy_read_timeout 3600s;
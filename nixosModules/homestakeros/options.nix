@@ -71,6 +71,22 @@
             '';
             example = [ "some-system.service" ];
           };
+          partOf = mkOption {
+            type = types.listOf types.str;
+            default = [ ];
+            description = ''
+              If the specified units are stopped or restarted, then this unit is stopped or restarted as well.
+            '';
+            example = [ "local-fs.target" ];
+          };
+          unitConfig = mkOption {
+            type = types.attrsOf types.str;
+            default = { };
+            description = ''
+              Each attribute in this set specifies an option in the [Unit] section of the unit.
+            '';
+            example = { DefaultDependencies = "no"; };
+          };
         };
       });
       default = { };
@@ -1,7 +1,6 @@
 { rustPlatform
 , pkgs
 , lib
-, json2nix
 , nix
 }:
 let
@@ -28,7 +27,7 @@ rustPlatform.buildRustPackage rec {
 
   postInstall = ''
     wrapProgram $out/bin/${pname} \
-      --prefix PATH : ${lib.makeBinPath [ nix json2nix ]}
+      --prefix PATH : ${lib.makeBinPath [ nix ]}
   '';
 
   # Tests require access to a /nix/ and a nix daemon; we run them at pre-commit instead
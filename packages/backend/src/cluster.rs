@@ -0,0 +1,164 @@
+use crate::options_schema::OptionsSchema;
+use crate::schema_types::Config;
+use crate::validate_config;
+use crate::validation::{parse_config, prefix_pointers, push_pointer, ValidationError};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Several hosts that are built together into one flake.
+#[derive(Debug)]
+pub struct Cluster {
+    /// Name given to the cluster, if submitted as `{"name": ..., "hosts": [...]}`.
+    pub name: Option<String>,
+    pub hosts: Vec<Config>,
+    /// JSON pointer to the list of hosts in the submitted document.
+    hosts_pointer: String,
+}
+
+impl Cluster {
+    /// JSON pointer to the host with the given index in the submitted document.
+    #[must_use]
+    pub fn host_pointer(&self, index: usize) -> String {
+        push_pointer(&self.hosts_pointer, &index.to_string())
+    }
+
+    /// The hosts other than the one with the given index.
+    #[must_use]
+    pub fn others(&self, index: usize) -> Vec<&Config> {
+        self.hosts
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, host)| host)
+            .collect()
+    }
+}
+
+/// Parses a cluster from JSON: either a list of configs, or an object with an optional `name`
+/// and the configs under `hosts`.
+///
+/// Each config is parsed like a single submitted config, and its problems point into the
+/// submitted document, e.g. `/hosts/1/localization/hostname`.
+///
+/// # Errors
+///
+/// Returns the problems found if the payload is not valid JSON, has neither shape, or any of
+/// the configs does not parse.
+pub fn parse_cluster(
+    body: &str,
+    schema: Option<&OptionsSchema>,
+) -> Result<Cluster, Vec<ValidationError>> {
+    let document: Value = serde_json::from_str(body).map_err(|e| {
+        vec![ValidationError::new(
+            "",
+            "invalid_json",
+            &format!("Invalid JSON: {e}"),
+        )]
+    })?;
+    let (name, hosts, hosts_pointer) = match document {
+        Value::Array(hosts) => (None, hosts, String::new()),
+        Value::Object(mut object) => {
+            let name = match object.remove("name") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(name)) if is_cluster_name(&name) => Some(name),
+                Some(_) => {
+                    return Err(vec![ValidationError::new(
+                        "/name",
+                        "invalid_value",
+                        "The cluster 'name' must be a non-empty string of letters, digits, \
+                         '.', '_' and '-' that does not start with '.'",
+                    )])
+                }
+            };
+            let Some(Value::Array(hosts)) = object.remove("hosts") else {
+                return Err(vec![ValidationError::new(
+                    "/hosts",
+                    "required",
+                    "The cluster must list its configs under 'hosts'",
+                )]);
+            };
+            if let Some(key) = object.keys().next() {
+                return Err(vec![ValidationError::new(
+                    &push_pointer("", key),
+                    "unknown_field",
+                    &format!("The cluster has no field '{key}'"),
+                )]);
+            }
+            (name, hosts, "/hosts".to_string())
+        }
+        _ => {
+            return Err(vec![ValidationError::new(
+                "",
+                "invalid_type",
+                "The cluster must be a list of configs or an object with 'hosts'",
+            )])
+        }
+    };
+    if hosts.is_empty() {
+        return Err(vec![ValidationError::new(
+            &hosts_pointer,
+            "required",
+            "The cluster must contain at least one config",
+        )]);
+    }
+
+    let mut configs = Vec::new();
+    let mut errors = Vec::new();
+    for (i, host) in hosts.iter().enumerate() {
+        let prefix = push_pointer(&hosts_pointer, &i.to_string());
+        match parse_config(&host.to_string(), schema) {
+            Ok(config) => configs.push(config),
+            Err(host_errors) => errors.extend(prefix_pointers(&prefix, host_errors)),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Cluster {
+        name,
+        hosts: configs,
+        hosts_pointer,
+    })
+}
+
+/// Whether a cluster name can name files, such as the git repository of the cluster.
+fn is_cluster_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Validates every host of a cluster, and that no two hosts share a hostname, which would put
+/// them in the same `nixosConfigurations` entry.
+///
+/// # Errors
+///
+/// Returns every problem found, pointing into the submitted document.
+pub fn validate_cluster(cluster: &Cluster) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    let mut hostnames: HashMap<&str, usize> = HashMap::new();
+    for (i, host) in cluster.hosts.iter().enumerate() {
+        let prefix = cluster.host_pointer(i);
+        if let Err(host_errors) = validate_config(host) {
+            errors.extend(prefix_pointers(&prefix, host_errors));
+        }
+        let hostname = host.localization.hostname.as_str();
+        if let Some(other) = hostnames.insert(hostname, i) {
+            errors.push(ValidationError::new(
+                &format!("{prefix}/localization/hostname"),
+                "duplicate_hostname",
+                &format!(
+                    "The hostname '{hostname}' is also used by the host at '{}'",
+                    cluster.host_pointer(other)
+                ),
+            ));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,313 @@
+use crate::default_nix;
+use crate::nix_expr::to_nix_pretty;
+use crate::options_schema::OptionsSchema;
+use crate::schema_types::Config;
+use crate::validation::{parse_config, prefix_pointers, push_pointer, ValidationError};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use similar::TextDiff;
+use std::fmt;
+
+/// Sections of the config whose members are services with an `enable` switch.
+const SERVICE_SECTIONS: &[&str] = &["addons", "consensus", "execution"];
+
+/// Fields that hold the URL of a service.
+const ENDPOINT_FIELDS: &[&str] = &["endpoint", "execEndpoint"];
+
+/// What kind of change was made, in the order changes are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// A client or addon was switched on.
+    Enabled,
+    /// A client or addon was switched off.
+    Disabled,
+    /// The URL of a service changed.
+    EndpointChanged,
+    MountAdded,
+    MountRemoved,
+    /// An item was added to the `extraOptions` of a service.
+    OptionAdded,
+    /// An item was removed from the `extraOptions` of a service.
+    OptionRemoved,
+    /// Any other value, or list item, was set.
+    Added,
+    /// Any other value, or list item, was unset.
+    Removed,
+    /// Any other value was changed.
+    Changed,
+}
+
+/// A single difference between two configs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// JSON pointer to the changed value; for list items, to the list, and for services that
+    /// were switched on or off, to the service.
+    pub pointer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// What changes between two configs of a host.
+#[derive(Debug, Serialize)]
+pub struct ConfigDiff {
+    /// The changes, grouped by kind and otherwise in document order.
+    pub changes: Vec<Change>,
+    /// Unified diff of the generated `default.nix`; empty if the file stays the same.
+    pub default_nix: String,
+}
+
+impl ConfigDiff {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Compares two configs, e.g. the deployed config of a node and the one about to be built.
+///
+/// A value that is unset is treated like a value that is absent, and lists are compared as
+/// collections, so that reordering `extraOptions` or `authorizedKeys` is not reported.
+///
+/// # Errors
+///
+/// Returns an error if a config cannot be serialized.
+pub fn diff_configs(before: &Config, after: &Config) -> serde_json::Result<ConfigDiff> {
+    let old = before.module_options()?;
+    let new = after.module_options()?;
+    let mut changes = Vec::new();
+    diff_values(&mut changes, "", Some(&old), Some(&new));
+    changes.sort_by_key(|change| change.kind);
+
+    let old_nix = default_nix(&to_nix_pretty(&old));
+    let new_nix = default_nix(&to_nix_pretty(&new));
+    let default_nix = TextDiff::from_lines(&old_nix, &new_nix)
+        .unified_diff()
+        .header("a/default.nix", "b/default.nix")
+        .to_string();
+    Ok(ConfigDiff {
+        changes,
+        default_nix,
+    })
+}
+
+/// Parses the two configs of a diff request, `{"before": ..., "after": ...}`.
+///
+/// Each config is parsed like a submitted config, and its problems point into the request, e.g.
+/// `/after/localization/hostname`.
+///
+/// # Errors
+///
+/// Returns the problems found if the payload is not valid JSON, lacks a config, or either
+/// config does not parse.
+pub fn parse_diff_request(
+    body: &str,
+    schema: Option<&OptionsSchema>,
+) -> Result<(Config, Config), Vec<ValidationError>> {
+    let mut document: Value = serde_json::from_str(body).map_err(|e| {
+        vec![ValidationError::new(
+            "",
+            "invalid_json",
+            &format!("Invalid JSON: {e}"),
+        )]
+    })?;
+    let mut errors = Vec::new();
+    let mut parse = |key: &str| {
+        let pointer = push_pointer("", key);
+        let Some(config) = document.get_mut(key).map(Value::take) else {
+            errors.push(ValidationError::new(
+                &pointer,
+                "required",
+                &format!("The request must contain the '{key}' config"),
+            ));
+            return None;
+        };
+        parse_config(&config.to_string(), schema)
+            .map_err(|e| errors.extend(prefix_pointers(&pointer, e)))
+            .ok()
+    };
+    let before = parse("before");
+    let after = parse("after");
+    match (before, after) {
+        (Some(before), Some(after)) => Ok((before, after)),
+        _ => Err(errors),
+    }
+}
+
+fn diff_values(
+    changes: &mut Vec<Change>,
+    pointer: &str,
+    before: Option<&Value>,
+    after: Option<&Value>,
+) {
+    let before = before.filter(|value| !value.is_null());
+    let after = after.filter(|value| !value.is_null());
+    if before == after {
+        return;
+    }
+    let tokens: Vec<&str> = pointer.split('/').skip(1).collect();
+    let change = |kind, before: Option<&Value>, after: Option<&Value>| Change {
+        kind,
+        pointer: pointer.to_string(),
+        before: before.cloned(),
+        after: after.cloned(),
+    };
+
+    match tokens.as_slice() {
+        ["mounts", _] if before.is_none() => {
+            changes.push(change(ChangeKind::MountAdded, None, after));
+            return;
+        }
+        ["mounts", _] if after.is_none() => {
+            changes.push(change(ChangeKind::MountRemoved, before, None));
+            return;
+        }
+        [section, _, "enable"] if SERVICE_SECTIONS.contains(section) => {
+            let was_on = before == Some(&Value::Bool(true));
+            let is_on = after == Some(&Value::Bool(true));
+            if was_on != is_on {
+                let kind = if is_on {
+                    ChangeKind::Enabled
+                } else {
+                    ChangeKind::Disabled
+                };
+                let (service, _) = pointer.rsplit_once('/').unwrap_or_default();
+                changes.push(Change {
+                    kind,
+                    pointer: service.to_string(),
+                    before: None,
+                    after: None,
+                });
+            }
+            return;
+        }
+        [.., field] if ENDPOINT_FIELDS.contains(field) && before.is_some() && after.is_some() => {
+            changes.push(change(ChangeKind::EndpointChanged, before, after));
+            return;
+        }
+        _ => {}
+    }
+
+    match (before, after) {
+        (Some(Value::Object(_)) | None, Some(Value::Object(_)) | None) => {
+            let empty = Map::new();
+            let old = before.and_then(Value::as_object).unwrap_or(&empty);
+            let new = after.and_then(Value::as_object).unwrap_or(&empty);
+            for key in old.keys() {
+                diff_values(
+                    changes,
+                    &push_pointer(pointer, key),
+                    old.get(key),
+                    new.get(key),
+                );
+            }
+            for key in new.keys().filter(|key| !old.contains_key(*key)) {
+                diff_values(changes, &push_pointer(pointer, key), None, new.get(key));
+            }
+        }
+        (Some(Value::Array(_)) | None, Some(Value::Array(_)) | None) => {
+            let old = before
+                .and_then(Value::as_array)
+                .map_or(&[][..], Vec::as_slice);
+            let new = after
+                .and_then(Value::as_array)
+                .map_or(&[][..], Vec::as_slice);
+            let (added, removed) = if tokens.last() == Some(&"extraOptions") {
+                (ChangeKind::OptionAdded, ChangeKind::OptionRemoved)
+            } else {
+                (ChangeKind::Added, ChangeKind::Removed)
+            };
+            for item in missing_from(old, new) {
+                changes.push(change(removed, Some(item), None));
+            }
+            for item in missing_from(new, old) {
+                changes.push(change(added, None, Some(item)));
+            }
+        }
+        (None, _) => changes.push(change(ChangeKind::Added, None, after)),
+        (_, None) => changes.push(change(ChangeKind::Removed, before, None)),
+        _ => changes.push(change(ChangeKind::Changed, before, after)),
+    }
+}
+
+/// The items of `items` that `other` does not have, counting duplicates.
+fn missing_from<'a>(items: &'a [Value], other: &[Value]) -> Vec<&'a Value> {
+    let mut unmatched: Vec<&Value> = other.iter().collect();
+    items
+        .iter()
+        .filter(|item| match unmatched.iter().position(|o| o == item) {
+            Some(index) => {
+                unmatched.swap_remove(index);
+                false
+            }
+            None => true,
+        })
+        .collect()
+}
+
+/// Dotted path of a JSON pointer, e.g. `consensus.lighthouse.endpoint`.
+fn dotted(pointer: &str) -> String {
+    pointer
+        .split('/')
+        .skip(1)
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+fn show(value: Option<&Value>) -> String {
+    value.map_or_else(String::new, Value::to_string)
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = dotted(&self.pointer);
+        let before = show(self.before.as_ref());
+        let after = show(self.after.as_ref());
+        match self.kind {
+            ChangeKind::Enabled => write!(f, "+ {path} enabled"),
+            ChangeKind::Disabled => write!(f, "- {path} disabled"),
+            ChangeKind::MountAdded | ChangeKind::MountRemoved => {
+                let (sign, verb, mount) = match self.kind {
+                    ChangeKind::MountAdded => ('+', "added", &self.after),
+                    _ => ('-', "removed", &self.before),
+                };
+                write!(f, "{sign} {path} {verb}")?;
+                let field = |name| {
+                    mount
+                        .as_ref()
+                        .and_then(|m| m.get(name))
+                        .map(Value::to_string)
+                };
+                if let (Some(what), Some(mount_point)) = (field("what"), field("where")) {
+                    write!(f, ": {what} on {mount_point}")?;
+                }
+                Ok(())
+            }
+            ChangeKind::OptionAdded | ChangeKind::Added => write!(f, "+ {path}: {after}"),
+            ChangeKind::OptionRemoved | ChangeKind::Removed => write!(f, "- {path}: {before}"),
+            ChangeKind::EndpointChanged | ChangeKind::Changed => {
+                write!(f, "~ {path}: {before} -> {after}")
+            }
+        }
+    }
+}
+
+/// Lists the changes one per line, followed by the diff of `default.nix`.
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        if !self.default_nix.is_empty() {
+            write!(f, "\n{}", self.default_nix)?;
+        }
+        Ok(())
+    }
+}
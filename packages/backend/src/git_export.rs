@@ -0,0 +1,207 @@
+use crate::targets::BuildTarget;
+use crate::workspace::join_inside;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, PoisonError};
+
+/// Branch that the builds are committed to.
+pub const BRANCH: &str = "main";
+
+/// File name of the bundle of a repository's history among the artifacts of a build.
+pub const BUNDLE_FILE: &str = "nixConfig.bundle";
+
+/// Identity that the commits are made with.
+const COMMITTER_NAME: &str = "HomestakerOS";
+const COMMITTER_EMAIL: &str = "homestakeros@localhost";
+
+/// Whose builds a repository keeps the history of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepoOwner<'a> {
+    Host(&'a str),
+    /// A named cluster, whose hosts all build the same flake.
+    Cluster(&'a str),
+}
+
+/// What a commit records about the build that produced it.
+#[derive(Debug, Clone, Copy)]
+pub struct ExportedBuild<'a> {
+    pub build_id: &'a str,
+    pub hostname: &'a str,
+    pub config_hash: &'a str,
+    pub target: BuildTarget,
+}
+
+/// Bare git repositories that keep the history of the `nixConfig` flake of builds, one per
+/// hostname and one per named cluster.
+pub struct GitExport {
+    repos_dir: PathBuf,
+    /// Builds of the hosts of a cluster run in parallel but share a repository.
+    lock: Mutex<()>,
+}
+
+impl GitExport {
+    /// Keeps the repositories in `repos_dir`, which is created if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created.
+    pub fn new(repos_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(repos_dir)
+            .with_context(|| format!("Failed to create repositories directory at {repos_dir:?}"))?;
+        Ok(GitExport {
+            repos_dir: repos_dir.to_path_buf(),
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Path of the repository of a host or cluster, which may not exist yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name would lead outside the repositories directory.
+    pub fn repo_path(&self, owner: RepoOwner) -> Result<PathBuf> {
+        let (kind, name) = match owner {
+            RepoOwner::Host(hostname) => ("hosts", hostname),
+            RepoOwner::Cluster(name) => ("clusters", name),
+        };
+        join_inside(&self.repos_dir, &[kind, &format!("{name}.git")])
+    }
+
+    /// Commits the contents of `nix_config_dir` on top of the owner's history, creating the
+    /// repository on first use, and writes a bundle of the whole history to `bundle_path`.
+    ///
+    /// The commit message carries the build id and config hash as trailers. Returns the hash of
+    /// the commit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if git cannot be run or any of its steps fails.
+    pub fn export(
+        &self,
+        owner: RepoOwner,
+        nix_config_dir: &Path,
+        build: &ExportedBuild,
+        bundle_path: &Path,
+    ) -> Result<String> {
+        let repo = self.repo_path(owner)?;
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if !repo.join("HEAD").is_file() {
+            fs::create_dir_all(&repo)
+                .with_context(|| format!("Failed to create repository at {repo:?}"))?;
+            run(git().args(["init", "--bare", "--quiet"]).arg(&repo), "init")?;
+            let head = format!("refs/heads/{BRANCH}");
+            run(
+                git_in(&repo).args(["symbolic-ref", "HEAD", &head]),
+                "symbolic-ref",
+            )?;
+        }
+        // Git runs inside the flake, so its paths must not be relative.
+        let repo = repo
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {repo:?}"))?;
+        let nix_config_dir = nix_config_dir
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {nix_config_dir:?}"))?;
+
+        // Stage the flake in a scratch index, so that files removed since the previous build
+        // are removed from the tree as well.
+        let index_dir =
+            tempfile::tempdir().context("Failed to create a directory for the index")?;
+        run(
+            git_in(&repo)
+                .env("GIT_INDEX_FILE", index_dir.path().join("index"))
+                .arg("--work-tree")
+                .arg(&nix_config_dir)
+                .args(["add", "--all", "--force", "."])
+                .current_dir(&nix_config_dir),
+            "add",
+        )?;
+        let tree = run(
+            git_in(&repo)
+                .env("GIT_INDEX_FILE", index_dir.path().join("index"))
+                .arg("write-tree"),
+            "write-tree",
+        )?;
+
+        let branch = format!("refs/heads/{BRANCH}");
+        let parent = run(
+            git_in(&repo).args([
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{branch}^{{commit}}"),
+            ]),
+            "rev-parse",
+        )
+        .ok();
+        let mut commit_tree = git_in(&repo);
+        commit_tree.args(["commit-tree", &tree, "-m", &commit_message(build)]);
+        if let Some(parent) = &parent {
+            commit_tree.args(["-p", parent]);
+        }
+        let commit = run(&mut commit_tree, "commit-tree")?;
+        // Moving the branch only from the commit it was on keeps a lost update from going
+        // unnoticed.
+        let old = parent.as_deref().unwrap_or("");
+        run(
+            git_in(&repo).args(["update-ref", &branch, &commit, old]),
+            "update-ref",
+        )?;
+
+        // Bundling HEAD as well lets a clone of the bundle check out the branch.
+        run(
+            git_in(&repo)
+                .args(["bundle", "create", "--quiet"])
+                .arg(bundle_path)
+                .args(["HEAD", BRANCH]),
+            "bundle create",
+        )?;
+        Ok(commit)
+    }
+}
+
+/// Message of the commit of a build.
+fn commit_message(build: &ExportedBuild) -> String {
+    format!(
+        "Build {} of {}\n\nBuild-Id: {}\nConfig-Hash: {}\nTarget: {}\n",
+        build.build_id, build.hostname, build.build_id, build.config_hash, build.target
+    )
+}
+
+/// A git command that ignores the configuration of the machine and its users, so that hooks,
+/// signing or templates set up there do not apply to the backend's repositories.
+fn git() -> Command {
+    let mut command = Command::new("git");
+    command
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_AUTHOR_NAME", COMMITTER_NAME)
+        .env("GIT_AUTHOR_EMAIL", COMMITTER_EMAIL)
+        .env("GIT_COMMITTER_NAME", COMMITTER_NAME)
+        .env("GIT_COMMITTER_EMAIL", COMMITTER_EMAIL);
+    command
+}
+
+/// A git command on the repository at `repo`.
+fn git_in(repo: &Path) -> Command {
+    let mut command = git();
+    command.arg("--git-dir").arg(repo);
+    command
+}
+
+/// Runs a git command and returns its trimmed output.
+fn run(command: &mut Command, what: &str) -> Result<String> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to execute git {what}"))?;
+    if !output.status.success() {
+        bail!(
+            "git {what} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
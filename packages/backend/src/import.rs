@@ -0,0 +1,186 @@
+use crate::flake_hostnames;
+use crate::options_schema::OptionsSchema;
+use crate::schema_types::Config;
+use crate::validation::{parse_config, validate_hostname, ValidationError};
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tar::Archive;
+
+/// Upper bound on the size of an uploaded flake tarball, in bytes.
+pub const MAX_TARBALL_SIZE: usize = 64 * 1024 * 1024;
+
+/// Upper bound on the total size of the files unpacked from a flake tarball, in bytes.
+pub const MAX_UNPACKED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Upper bound on the number of entries in a flake tarball.
+pub const MAX_TARBALL_ENTRIES: usize = 10_000;
+
+/// The homestakeros config of one host of an imported flake.
+#[derive(Debug, Serialize)]
+pub struct ImportedHost {
+    pub hostname: String,
+    /// The config, unless it could not be evaluated or does not fit the config format.
+    pub config: Option<Config>,
+    /// Why the config could not be imported, with pointers into the evaluated config.
+    pub errors: Vec<ValidationError>,
+}
+
+impl ImportedHost {
+    fn failed(hostname: String, errors: Vec<ValidationError>) -> Self {
+        ImportedHost {
+            hostname,
+            config: None,
+            errors,
+        }
+    }
+}
+
+/// Reads the homestakeros config of every host in a flake, the way `update-json` does.
+///
+/// Each config is evaluated with `nix eval` and parsed like a submitted config, so a host whose
+/// config does not fit is reported with its problems rather than failing the import. A
+/// `trusted` flake is evaluated with `--impure --accept-flake-config`; uploaded flakes are not,
+/// so that they can neither read the host's environment nor change the nix settings.
+///
+/// # Errors
+///
+/// Returns an error if the hosts of the flake cannot be listed.
+pub fn import_flake(
+    flake_dir: &Path,
+    schema: Option<&OptionsSchema>,
+    trusted: bool,
+) -> Result<Vec<ImportedHost>> {
+    let hostnames = flake_hostnames(flake_dir)
+        .with_context(|| format!("Failed to list the hosts of the flake in {flake_dir:?}"))?;
+    Ok(hostnames
+        .into_iter()
+        .map(|hostname| import_host(flake_dir, hostname, schema, trusted))
+        .collect())
+}
+
+fn import_host(
+    flake_dir: &Path,
+    hostname: String,
+    schema: Option<&OptionsSchema>,
+    trusted: bool,
+) -> ImportedHost {
+    // The name goes into the attribute path, and builds need it to be a valid hostname anyway.
+    if let Err(e) = validate_hostname(&hostname) {
+        let error = ValidationError::new("", "invalid_hostname", &e);
+        return ImportedHost::failed(hostname, vec![error]);
+    }
+    let json = match eval_host_config(flake_dir, &hostname, trusted) {
+        Ok(json) => json,
+        Err(e) => {
+            let message = format!("Failed to evaluate the config of '{hostname}': {e:#}");
+            let error = ValidationError::new("", "eval_failed", message.trim_end());
+            return ImportedHost::failed(hostname, vec![error]);
+        }
+    };
+    match parse_config(&json, schema) {
+        Ok(config) => ImportedHost {
+            hostname,
+            config: Some(config),
+            errors: Vec::new(),
+        },
+        Err(errors) => ImportedHost::failed(hostname, errors),
+    }
+}
+
+/// Evaluates `nixosConfigurations.<hostname>.config.homestakeros` to JSON.
+fn eval_host_config(flake_dir: &Path, hostname: &str, trusted: bool) -> Result<String> {
+    let mut command = Command::new("nix");
+    command
+        .arg("eval")
+        .arg("--json")
+        .arg(format!(
+            ".#nixosConfigurations.\"{hostname}\".config.homestakeros"
+        ))
+        .arg("--extra-experimental-features")
+        .arg("nix-command flakes")
+        .arg("--no-warn-dirty");
+    if trusted {
+        command.arg("--impure").arg("--accept-flake-config");
+    }
+    let output = command
+        .current_dir(flake_dir)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| "Failed to execute nix eval for the config")?;
+    if !output.status.success() {
+        return Err(anyhow!(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Resolves a flake path received over the network, which is only allowed if it lies inside one
+/// of the `roots` the operator configured. Returns the canonical path of the flake.
+///
+/// # Errors
+///
+/// Returns an error if the path does not exist or lies outside every root.
+pub fn allowed_flake_dir(path: &Path, roots: &[PathBuf]) -> Result<PathBuf> {
+    let flake_dir = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve {path:?}"))?;
+    if !roots.iter().any(|root| flake_dir.starts_with(root)) {
+        bail!("{path:?} is not inside a directory that flakes may be imported from");
+    }
+    Ok(flake_dir)
+}
+
+/// Unpacks an uploaded flake, a tar archive that may be gzip-compressed, into `dir`.
+///
+/// Returns the directory that holds the flake's `flake.nix`: the top level of the archive, or
+/// its only directory, as in archives made with `git archive --prefix` or downloaded from a
+/// forge.
+///
+/// # Errors
+///
+/// Returns an error if the archive cannot be unpacked, exceeds [`MAX_UNPACKED_SIZE`] or
+/// [`MAX_TARBALL_ENTRIES`], or does not contain a flake.
+pub fn unpack_flake(tarball: &[u8], dir: &Path) -> Result<PathBuf> {
+    let reader: Box<dyn Read + '_> = if tarball.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(tarball))
+    } else {
+        Box::new(tarball)
+    };
+    // Unpack entry by entry, checking the sizes in the headers before any data is written, so
+    // that a small compressed upload cannot fill the disk.
+    let mut archive = Archive::new(reader);
+    let entries = archive
+        .entries()
+        .context("Failed to unpack the flake tarball")?;
+    let mut unpacked_size: u64 = 0;
+    for (count, entry) in entries.enumerate() {
+        let mut entry = entry.context("Failed to unpack the flake tarball")?;
+        if count >= MAX_TARBALL_ENTRIES {
+            bail!("The tarball has more than {MAX_TARBALL_ENTRIES} entries");
+        }
+        unpacked_size = unpacked_size.saturating_add(entry.header().size().unwrap_or(u64::MAX));
+        if unpacked_size > MAX_UNPACKED_SIZE {
+            bail!("The tarball unpacks to more than {MAX_UNPACKED_SIZE} bytes");
+        }
+        entry
+            .unpack_in(dir)
+            .context("Failed to unpack the flake tarball")?;
+    }
+
+    if dir.join("flake.nix").is_file() {
+        return Ok(dir.to_path_buf());
+    }
+    let entries: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read {dir:?}"))?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    match entries.as_slice() {
+        [only] if only.is_dir() && only.join("flake.nix").is_file() => Ok(only.clone()),
+        _ => bail!("The tarball does not contain a flake.nix at its top level"),
+    }
+}
@@ -0,0 +1,437 @@
+use crate::targets::BuildTarget;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+/// Lifecycle state of a build job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildStatus {
+    Queued,
+    Evaluating,
+    Building,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl BuildStatus {
+    /// Whether the build has reached a final state.
+    #[must_use]
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            BuildStatus::Succeeded | BuildStatus::Failed | BuildStatus::Cancelled
+        )
+    }
+}
+
+/// A snapshot of a build job as reported by the status API and stored in the build index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildJob {
+    pub build_id: String,
+    pub hostname: String,
+    pub config_hash: String,
+    /// Hash of the config together with the flake it is built with.
+    #[serde(default)]
+    pub cache_key: String,
+    /// What the build produces; builds from before targets were selectable are kexec builds.
+    #[serde(default)]
+    pub target: BuildTarget,
+    /// Name of the cluster the host was built with, if it was submitted as a named cluster.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    /// Hash of the commit that recorded the build's flake, if builds are exported to git.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    /// Seconds since the Unix epoch.
+    pub updated_at: u64,
+    pub status: BuildStatus,
+    pub artifacts: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BuildJob {
+    /// Create a new job in the queued state.
+    #[must_use]
+    pub fn new(
+        build_id: &str,
+        hostname: &str,
+        config_hash: &str,
+        cache_key: &str,
+        target: BuildTarget,
+    ) -> Self {
+        let now = unix_time();
+        BuildJob {
+            build_id: build_id.to_string(),
+            hostname: hostname.to_string(),
+            config_hash: config_hash.to_string(),
+            cache_key: cache_key.to_string(),
+            target,
+            cluster: None,
+            commit: None,
+            created_at: now,
+            updated_at: now,
+            status: BuildStatus::Queued,
+            artifacts: Vec::new(),
+            error: None,
+        }
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+#[must_use]
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Number of log lines kept per job for replaying to late subscribers.
+pub const MAX_LOG_LINES: usize = 10_000;
+
+/// Number of bytes of log lines kept per job for replaying to late subscribers.
+pub const MAX_LOG_BYTES: usize = 4 * 1024 * 1024;
+
+/// A job together with its log, the log subscribers and its running process.
+struct JobEntry {
+    job: BuildJob,
+    /// The latest lines of the log, within [`MAX_LOG_LINES`] and [`MAX_LOG_BYTES`].
+    log: VecDeque<String>,
+    log_bytes: usize,
+    /// Number of lines dropped from the front of the log.
+    dropped_lines: usize,
+    subscribers: Vec<UnboundedSender<String>>,
+    pid: Option<u32>,
+    cancel_requested: bool,
+}
+
+impl JobEntry {
+    fn new(job: BuildJob) -> Self {
+        JobEntry {
+            job,
+            log: VecDeque::new(),
+            log_bytes: 0,
+            dropped_lines: 0,
+            subscribers: Vec::new(),
+            pid: None,
+            cancel_requested: false,
+        }
+    }
+
+    fn push_log(&mut self, line: &str) {
+        self.log.push_back(line.to_string());
+        self.log_bytes += line.len();
+        while self.log.len() > MAX_LOG_LINES || self.log_bytes > MAX_LOG_BYTES {
+            let Some(dropped) = self.log.pop_front() else {
+                break;
+            };
+            self.log_bytes -= dropped.len();
+            self.dropped_lines += 1;
+        }
+    }
+
+    /// The log to replay, starting with a note of how many earlier lines were dropped.
+    fn replay(&self) -> Vec<String> {
+        let note = (self.dropped_lines > 0)
+            .then(|| format!("[{} earlier log lines were dropped]", self.dropped_lines));
+        note.into_iter().chain(self.log.iter().cloned()).collect()
+    }
+}
+
+/// Outcome of a cancellation request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CancelRequest {
+    /// The build was marked for cancellation; the process group to terminate, if one is running.
+    Requested(Option<u32>),
+    /// The build has already finished.
+    Finished(BuildStatus),
+    /// No build with the given id exists.
+    NotFound,
+}
+
+/// Registry of build jobs, shared between the HTTP handlers and the build executor.
+///
+/// When opened with an index file, every change is written through to disk so that the builds
+/// can be served again after a restart.
+#[derive(Default)]
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, JobEntry>>,
+    index_path: Option<PathBuf>,
+}
+
+impl JobStore {
+    /// Create an empty, in-memory job store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open a job store backed by the index file at `index_path`, loading any existing builds.
+    ///
+    /// Builds that were still running when the index was last written can no longer finish, so
+    /// they are marked as failed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index exists but cannot be read or parsed.
+    pub fn open(index_path: &Path) -> Result<Self> {
+        let mut jobs = HashMap::new();
+        if index_path.exists() {
+            let content = fs::read_to_string(index_path)
+                .with_context(|| format!("Failed to read build index {index_path:?}"))?;
+            let index: Vec<BuildJob> = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse build index {index_path:?}"))?;
+            for mut job in index {
+                if !job.status.is_finished() {
+                    job.status = BuildStatus::Failed;
+                    job.error = Some("Interrupted by a backend restart".to_string());
+                    job.updated_at = unix_time();
+                }
+                jobs.insert(job.build_id.clone(), JobEntry::new(job));
+            }
+        }
+        let store = JobStore {
+            jobs: Mutex::new(jobs),
+            index_path: Some(index_path.to_path_buf()),
+        };
+        store.save(&store.lock());
+        Ok(store)
+    }
+
+    /// Register a new job.
+    pub fn insert(&self, job: BuildJob) {
+        let mut jobs = self.lock();
+        jobs.insert(job.build_id.clone(), JobEntry::new(job));
+        self.save(&jobs);
+    }
+
+    /// Register a new job unless a build with the same cache key is queued, running or has
+    /// succeeded, in which case that build is returned instead and nothing is registered.
+    ///
+    /// A build that is being cancelled is not reused, as it will not produce artifacts.
+    #[must_use]
+    pub fn insert_unless_cached(&self, job: BuildJob) -> Option<BuildJob> {
+        let mut jobs = self.lock();
+        let cached = jobs
+            .values()
+            .filter(|entry| !entry.cancel_requested)
+            .map(|entry| &entry.job)
+            .filter(|other| {
+                !job.cache_key.is_empty()
+                    && other.cache_key == job.cache_key
+                    && matches!(
+                        other.status,
+                        BuildStatus::Queued
+                            | BuildStatus::Evaluating
+                            | BuildStatus::Building
+                            | BuildStatus::Succeeded
+                    )
+            })
+            .max_by_key(|other| other.created_at);
+        if let Some(cached) = cached {
+            return Some(cached.clone());
+        }
+        jobs.insert(job.build_id.clone(), JobEntry::new(job));
+        self.save(&jobs);
+        None
+    }
+
+    /// Return a snapshot of the job with the given id.
+    #[must_use]
+    pub fn get(&self, build_id: &str) -> Option<BuildJob> {
+        self.lock().get(build_id).map(|entry| entry.job.clone())
+    }
+
+    /// Remove a job from the store.
+    pub fn remove(&self, build_id: &str) -> Option<BuildJob> {
+        let mut jobs = self.lock();
+        let entry = jobs.remove(build_id)?;
+        self.save(&jobs);
+        Some(entry.job)
+    }
+
+    /// Remove a job from the store if it still matches `unchanged`, checked under the same lock,
+    /// so that no other request can pick the job up in between.
+    pub fn remove_if(
+        &self,
+        build_id: &str,
+        unchanged: impl FnOnce(&BuildJob) -> bool,
+    ) -> Option<BuildJob> {
+        let mut jobs = self.lock();
+        if !unchanged(&jobs.get(build_id)?.job) {
+            return None;
+        }
+        let entry = jobs.remove(build_id)?;
+        self.save(&jobs);
+        Some(entry.job)
+    }
+
+    /// Return snapshots of all jobs, newest first.
+    #[must_use]
+    pub fn list(&self) -> Vec<BuildJob> {
+        let mut jobs: Vec<BuildJob> = self.lock().values().map(|e| e.job.clone()).collect();
+        jobs.sort_by(|a, b| {
+            b.created_at
+                .cmp(&a.created_at)
+                .then_with(|| a.build_id.cmp(&b.build_id))
+        });
+        jobs
+    }
+
+    /// Append a line to the job's log and forward it to the current subscribers.
+    pub fn append_log(&self, build_id: &str, line: &str) {
+        if let Some(entry) = self.lock().get_mut(build_id) {
+            entry.push_log(line);
+            entry
+                .subscribers
+                .retain(|tx| tx.send(line.to_string()).is_ok());
+        }
+    }
+
+    /// Subscribe to the job's log.
+    ///
+    /// Returns the log lines produced so far and a receiver for the lines that follow. The
+    /// receiver is closed once the job has finished. Only the latest lines are kept, so a long
+    /// log is replayed from a note of how many lines were dropped.
+    #[must_use]
+    pub fn subscribe(&self, build_id: &str) -> Option<(Vec<String>, UnboundedReceiver<String>)> {
+        let mut jobs = self.lock();
+        let entry = jobs.get_mut(build_id)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        if !entry.job.status.is_finished() {
+            entry.subscribers.push(tx);
+        }
+        Some((entry.replay(), rx))
+    }
+
+    /// Record the process group of the job's running nix process, or clear it with `None`.
+    ///
+    /// Returns whether cancellation was requested in the meantime, in which case the caller
+    /// should terminate the process it just started.
+    pub fn set_pid(&self, build_id: &str, pid: Option<u32>) -> bool {
+        let mut jobs = self.lock();
+        let Some(entry) = jobs.get_mut(build_id) else {
+            return false;
+        };
+        entry.pid = pid;
+        entry.cancel_requested
+    }
+
+    /// Ask for a job to be cancelled.
+    pub fn request_cancel(&self, build_id: &str) -> CancelRequest {
+        let mut jobs = self.lock();
+        let Some(entry) = jobs.get_mut(build_id) else {
+            return CancelRequest::NotFound;
+        };
+        if entry.job.status.is_finished() {
+            return CancelRequest::Finished(entry.job.status);
+        }
+        entry.cancel_requested = true;
+        CancelRequest::Requested(entry.pid)
+    }
+
+    /// Whether cancellation has been requested for a job.
+    #[must_use]
+    pub fn is_cancel_requested(&self, build_id: &str) -> bool {
+        self.lock()
+            .get(build_id)
+            .is_some_and(|entry| entry.cancel_requested)
+    }
+
+    /// Move a job to the given status.
+    pub fn set_status(&self, build_id: &str, status: BuildStatus) {
+        self.update(build_id, |job| job.status = status);
+    }
+
+    /// Record the commit that the job's flake was exported as.
+    pub fn set_commit(&self, build_id: &str, commit: &str) {
+        self.update(build_id, |job| job.commit = Some(commit.to_string()));
+    }
+
+    /// Mark a job as succeeded with the given artifacts.
+    pub fn succeed(&self, build_id: &str, artifacts: Vec<Value>) {
+        self.finish(build_id, |job| {
+            job.status = BuildStatus::Succeeded;
+            job.artifacts = artifacts;
+        });
+    }
+
+    /// Mark a job as failed with the given error message.
+    pub fn fail(&self, build_id: &str, error: &str) {
+        self.finish(build_id, |job| {
+            job.status = BuildStatus::Failed;
+            job.error = Some(error.to_string());
+        });
+    }
+
+    /// Mark a job as cancelled.
+    pub fn cancel(&self, build_id: &str) {
+        self.finish(build_id, |job| {
+            job.status = BuildStatus::Cancelled;
+            job.error = Some("Cancelled by request".to_string());
+        });
+    }
+
+    fn update<F: FnOnce(&mut BuildJob)>(&self, build_id: &str, f: F) {
+        let mut jobs = self.lock();
+        if let Some(entry) = jobs.get_mut(build_id) {
+            f(&mut entry.job);
+            entry.job.updated_at = unix_time();
+            self.save(&jobs);
+        }
+    }
+
+    /// Update a job into a final state and close its log streams.
+    fn finish<F: FnOnce(&mut BuildJob)>(&self, build_id: &str, f: F) {
+        let mut jobs = self.lock();
+        if let Some(entry) = jobs.get_mut(build_id) {
+            f(&mut entry.job);
+            entry.job.updated_at = unix_time();
+            entry.subscribers.clear();
+            entry.pid = None;
+            self.save(&jobs);
+        }
+    }
+
+    /// Write the index file, if any. Failures are logged rather than failing the build.
+    fn save(&self, jobs: &HashMap<String, JobEntry>) {
+        let Some(index_path) = &self.index_path else {
+            return;
+        };
+        let mut index: Vec<&BuildJob> = jobs.values().map(|e| &e.job).collect();
+        index.sort_by(|a, b| {
+            a.created_at
+                .cmp(&b.created_at)
+                .then_with(|| a.build_id.cmp(&b.build_id))
+        });
+        if let Err(e) = write_index(index_path, &index) {
+            eprintln!("Warning: Failed to write build index: {e:?}");
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, JobEntry>> {
+        // A panic while holding the lock leaves the map itself intact, so keep serving it.
+        self.jobs
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Atomically replace the index file with the given jobs.
+fn write_index(index_path: &Path, index: &[&BuildJob]) -> Result<()> {
+    let json = serde_json::to_string_pretty(index).context("Failed to serialize build index")?;
+    let tmp_path = index_path.with_extension("json.tmp");
+    fs::write(&tmp_path, json).with_context(|| format!("Failed to write {tmp_path:?}"))?;
+    fs::rename(&tmp_path, index_path)
+        .with_context(|| format!("Failed to move {tmp_path:?} to {index_path:?}"))
+}
@@ -1,104 +1,122 @@
+pub mod cluster;
+pub mod diff;
+pub mod git_export;
+pub mod import;
+pub mod jobs;
+pub mod migrations;
+pub mod mounts;
+pub mod nix_expr;
+pub mod nix_parser;
+pub mod options_schema;
+pub mod ports;
+pub mod retention;
+pub mod scheduler;
 pub mod schema_types;
+pub mod signing;
+pub mod ssh_keys;
+pub mod targets;
+pub mod timezones;
+pub mod validation;
 pub mod workspace;
 
 use crate::schema_types::Config;
+use crate::targets::BuildTarget;
+use crate::validation::{push_pointer, ValidationError};
 use actix_web::HttpResponse;
 use anyhow::{anyhow, Context, Result};
 use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
 use std::fs;
-use std::io::{BufReader, Read, Write};
+use std::io::{BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
 use std::path::Path;
-use std::process::{Command as StdCommand, Stdio};
+use std::process::{Child, Command as StdCommand, Stdio};
 use tar::Builder;
 
-/// Runs the `json2nix` command by piping in the JSON string and returns the command's stdout.
+/// The contents of a host's default.nix, with the provided Nix expression as the homestakeros
+/// config.
 ///
-/// # Errors
-///
-/// Returns an error if spawning the process, writing to its stdin, or waiting for its output fails.
-pub fn run_json2nix(json_str: &str) -> Result<String> {
-    let mut child = StdCommand::new("json2nix")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .with_context(|| "Failed to spawn json2nix process")?;
-
-    {
-        let stdin = child
-            .stdin
-            .as_mut()
-            .ok_or_else(|| anyhow!("Failed to open stdin for json2nix"))?;
-        stdin
-            .write_all(json_str.as_bytes())
-            .with_context(|| "Failed to write to stdin of json2nix")?;
-    }
-    let output = child
-        .wait_with_output()
-        .with_context(|| "Command execution failed")?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        return Err(anyhow!(stderr));
-    }
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+/// Continuation lines of the expression are indented to match its place in the module.
+#[must_use]
+pub fn default_nix(nix_expr: &str) -> String {
+    format!(
+        "{{ pkgs, lib, config, ... }}:\n{{\n  homestakeros = {};\n}}\n",
+        nix_expr.replace('\n', "\n  ")
+    )
 }
 
-/// Writes the default.nix file with the provided json2nix output.
+/// Writes the default.nix file with the provided Nix expression as the homestakeros config.
 ///
 /// # Errors
 ///
 /// Returns an error if writing to the file fails.
-pub fn write_default_nix(hostname_dir: &Path, json2nix_output: &str) -> std::io::Result<()> {
-    let nix_boilerplate =
-        String::from("{ pkgs, lib, config, ... }: { homestakeros = ") + json2nix_output + "; }";
+pub fn write_default_nix(hostname_dir: &Path, nix_expr: &str) -> std::io::Result<()> {
     let default_nix_path = hostname_dir.join("default.nix");
-    fs::write(default_nix_path, nix_boilerplate.as_bytes())
+    fs::write(default_nix_path, default_nix(nix_expr).as_bytes())
 }
 
-/// Runs the `nix build` command and returns an error if it fails.
+/// Runs the `nix build` command for a build target and returns an error if it fails.
+///
+/// Only the files on the target's whitelist are copied from the build result to `output_dir`.
+///
+/// The build result is linked at `out_link_path`, which acts as the nix GC root of the build for
+/// as long as the link exists. Nix is started in its own process group, whose id is passed to `on_spawn` so that the build
+/// can be stopped with [`terminate_process_group`]. Each line of nix's log output is passed to
+/// `on_log` as soon as it is produced.
 ///
 /// # Errors
 ///
-/// Returns an error if executing the command fails or if the build does not succeed.
-pub fn run_nix_build(
+/// Returns an error if the hostname is not a valid hostname, if executing the command fails or
+/// if the build does not succeed.
+pub fn run_nix_build<S: FnOnce(u32), F: FnMut(&str)>(
     nix_config_dir: &Path,
     hostname: &str,
+    target: BuildTarget,
+    out_link_path: &Path,
     output_dir: &Path,
-    whitelist: &[&str],
+    on_spawn: S,
+    mut on_log: F,
 ) -> Result<()> {
+    // The hostname ends up in the flake reference, so never pass anything but a plain label.
+    validation::validate_hostname(hostname).map_err(|e| anyhow!(e))?;
     let nix_config_dir_str = nix_config_dir.display().to_string();
     let build_arg = format!(
-        "path:{nix_config_dir_str}#nixosConfigurations.{hostname}.config.system.build.kexecTree"
+        "path:{nix_config_dir_str}#{}",
+        target.flake_attribute(hostname)
     );
-    let out_link_path = nix_config_dir.join("result");
+    let whitelist = target.whitelist();
 
-    let output = StdCommand::new("nix")
+    let mut child = StdCommand::new("nix")
         .arg("build")
         .arg(build_arg)
         .arg("--out-link")
-        .arg(&out_link_path)
+        .arg(out_link_path)
+        .arg("--print-build-logs")
         .arg("--extra-experimental-features")
         .arg("nix-command")
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()
+        .process_group(0)
+        .spawn()
         .with_context(|| "Failed to execute nix build")?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        return Err(anyhow!(stderr));
+    on_spawn(child.id());
+
+    let output = read_build_output(&mut child, &mut on_log);
+    if output.is_err() {
+        // Neither leave nix running nor leave it unreaped.
+        if let Err(e) = terminate_process_group(child.id()) {
+            println!("Failed to terminate nix process group {}: {e}", child.id());
+        }
     }
-    println!(
-        "Nix build stdout: {}",
-        String::from_utf8_lossy(&output.stdout)
-    );
-    println!(
-        "Nix build stderr: {}",
-        String::from_utf8_lossy(&output.stderr)
-    );
+    let status = child.wait().with_context(|| "Command execution failed")?;
+    let (stdout, stderr_buf) = output?;
+    if !status.success() {
+        return Err(anyhow!(stderr_buf));
+    }
+    println!("Nix build stdout: {stdout}");
 
     // Copy whitelisted files from the build output to the output directory
-    for entry in fs::read_dir(&out_link_path)
+    for entry in fs::read_dir(out_link_path)
         .with_context(|| format!("Failed to read out_link dir: {out_link_path:?}"))?
     {
         let entry = entry.with_context(|| "Failed to get directory entry")?;
@@ -132,6 +150,70 @@ pub fn run_nix_build(
     Ok(())
 }
 
+/// Streams the stderr of a nix build to `on_log` line by line and returns its stdout and stderr.
+///
+/// Output that is not UTF-8 is read lossily, so that a stray byte in a build log cannot fail the
+/// build.
+fn read_build_output<F: FnMut(&str)>(
+    child: &mut Child,
+    on_log: &mut F,
+) -> Result<(String, String)> {
+    // Drain stdout on its own thread so that neither pipe can fill up and block nix.
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Failed to open stdout for nix build"))?;
+    let stdout_reader = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        BufReader::new(stdout).read_to_end(&mut buf).map(|_| buf)
+    });
+
+    // Nix writes its progress and the build logs to stderr.
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow!("Failed to open stderr for nix build"))?;
+    let mut reader = BufReader::new(stderr);
+    let mut stderr_buf = String::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = reader
+            .read_until(b'\n', &mut line)
+            .with_context(|| "Failed to read nix build output")?;
+        if read == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+        on_log(text);
+        stderr_buf.push_str(text);
+        stderr_buf.push('\n');
+    }
+
+    let stdout = stdout_reader
+        .join()
+        .map_err(|_| anyhow!("Failed to read nix build stdout"))?
+        .with_context(|| "Failed to read nix build stdout")?;
+    Ok((String::from_utf8_lossy(&stdout).into_owned(), stderr_buf))
+}
+
+/// Sends SIGTERM to every process in the given process group.
+///
+/// # Errors
+///
+/// Returns an error if the signal cannot be delivered, e.g. because the group no longer exists.
+pub fn terminate_process_group(pgid: u32) -> std::io::Result<()> {
+    let pgid = i32::try_from(pgid)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: kill(2) only takes plain integers and has no memory-safety preconditions.
+    if unsafe { libc::kill(-pgid, libc::SIGTERM) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
 /// Computes the SHA-256 hash of a file.
 ///
 /// # Errors
@@ -152,12 +234,89 @@ pub fn compute_sha256<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
-/// Processes build artifacts.
+/// Computes the SHA-256 hash of the canonical JSON form of a config.
+///
+/// Object keys are sorted, so configs that differ only in key order hash the same.
+///
+/// # Errors
+///
+/// Returns an error if the config cannot be serialized.
+pub fn config_hash(config: &Config) -> Result<String> {
+    let canonical = serde_json::to_value(config)
+        .with_context(|| "Failed to serialize config")?
+        .to_string();
+    Ok(format!("{:x}", Sha256::digest(canonical.as_bytes())))
+}
+
+/// Computes the key under which the build of a config is cached.
+///
+/// The key covers the canonical config, the build target, the flake it is built with and, when the
+/// flake inputs are pinned, the lock file.
+///
+/// # Errors
+///
+/// Returns an error if the config cannot be serialized.
+pub fn cache_key(
+    config: &Config,
+    target: BuildTarget,
+    flake_nix: &str,
+    flake_lock: Option<&str>,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(config_hash(config)?.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(target.name().as_bytes());
+    hasher.update(b"\0");
+    hasher.update(flake_nix.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(flake_lock.unwrap_or_default().as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Computes the key under which the build of one host of a cluster is cached.
+///
+/// The other hosts are rendered into the same flake, and so into the host's `nixConfig.tar`, so
+/// the key covers their configs as well, in any order. Without other hosts, the key is the
+/// [`cache_key`] of the host.
+///
+/// # Errors
+///
+/// Returns an error if a config cannot be serialized.
+pub fn cluster_cache_key(
+    config: &Config,
+    others: &[&Config],
+    target: BuildTarget,
+    flake_nix: &str,
+    flake_lock: Option<&str>,
+) -> Result<String> {
+    let key = cache_key(config, target, flake_nix, flake_lock)?;
+    if others.is_empty() {
+        return Ok(key);
+    }
+    let mut hashes = others
+        .iter()
+        .map(|other| config_hash(other))
+        .collect::<Result<Vec<String>>>()?;
+    hashes.sort();
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_bytes());
+    for hash in hashes {
+        hasher.update(b"\0");
+        hasher.update(hash.as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Processes build artifacts, tagging each with the target it was built for.
 ///
 /// # Errors
 ///
 /// Returns an error if reading the output directory fails or if a directory entry cannot be processed.
-pub fn process_artifacts(output_dir: &Path, build_id: &str) -> Result<Vec<Value>> {
+pub fn process_artifacts(
+    output_dir: &Path,
+    build_id: &str,
+    target: BuildTarget,
+) -> Result<Vec<Value>> {
     let mut artifacts_info = Vec::new();
     for entry in fs::read_dir(output_dir)
         .with_context(|| format!("Failed to read output_dir: {output_dir:?}"))?
@@ -179,7 +338,8 @@ pub fn process_artifacts(output_dir: &Path, build_id: &str) -> Result<Vec<Value>
             artifacts_info.push(json!({
                 "file": filename,
                 "sha256": sha,
-                "download_url": download_url
+                "download_url": download_url,
+                "target": target
             }));
         }
     }
@@ -197,24 +357,82 @@ pub fn handle_error<E: std::fmt::Display>(desc: &str, error: E) -> HttpResponse
     }))
 }
 
+/// Returns an HTTP 422 response listing the problems with a submitted config.
+///
+/// Each problem carries a JSON pointer to the offending field, so that clients can highlight it.
+/// The messages are also joined into `error`, matching the shape of [`handle_error`].
+pub fn handle_validation_errors(desc: &str, errors: &[ValidationError]) -> HttpResponse {
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    println!("{desc}: {}", messages.join("; "));
+    HttpResponse::UnprocessableEntity().json(json!({
+        "status": "error",
+        "message": desc,
+        "error": messages.join("; "),
+        "errors": errors
+    }))
+}
+
 /// Validates the configuration to ensure it meets required criteria.
 ///
+/// Besides the required fields, the rules that span several fields are checked; see
+/// [`validation::semantic_errors`].
+///
 /// # Errors
 ///
-/// Returns an error if the configuration is invalid for any reason
-pub fn validate_config(config: &Config) -> Result<(), String> {
+/// Returns every problem found if the configuration is invalid for any reason
+pub fn validate_config(config: &Config) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
     if config.localization.hostname.trim().is_empty() {
-        return Err("The 'localization.hostname' must not be empty".into());
+        errors.push(ValidationError::new(
+            "/localization/hostname",
+            "required",
+            "The 'localization.hostname' must not be empty",
+        ));
+    } else if let Err(e) = validation::validate_hostname(&config.localization.hostname) {
+        errors.push(ValidationError::new(
+            "/localization/hostname",
+            "invalid_hostname",
+            &e,
+        ));
+    }
+    if let Some(timezone) = &config.localization.timezone {
+        if let Err(e) = timezones::validate_timezone(timezone) {
+            errors.push(ValidationError::new(
+                "/localization/timezone",
+                "invalid_timezone",
+                &e,
+            ));
+        }
     }
     if config.ssh.authorized_keys.is_empty() {
-        return Err("The 'ssh.authorizedKeys' must contain at least one key".into());
+        errors.push(ValidationError::new(
+            "/ssh/authorizedKeys",
+            "required",
+            "The 'ssh.authorizedKeys' must contain at least one key",
+        ));
     }
-    for key in &config.ssh.authorized_keys {
+    for (i, key) in config.ssh.authorized_keys.iter().enumerate() {
+        let pointer = push_pointer("/ssh/authorizedKeys", &i.to_string());
         if key.trim().is_empty() {
-            return Err("The 'ssh.authorizedKeys' must not contain an empty key".into());
+            errors.push(ValidationError::new(
+                &pointer,
+                "empty",
+                "The 'ssh.authorizedKeys' must not contain an empty key",
+            ));
+        } else if let Err(e) = ssh_keys::parse_authorized_key(key) {
+            errors.push(ValidationError::new(
+                &pointer,
+                "invalid_ssh_key",
+                &format!("The 'ssh.authorizedKeys' entry {i} cannot be used: {e}"),
+            ));
         }
     }
-    Ok(())
+    errors.extend(validation::semantic_errors(config));
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
 }
 
 /// Create a tar archive from a source directory.
@@ -258,12 +476,12 @@ pub fn write_json_to_file<P: AsRef<Path>>(path: P, json_str: &str) -> std::io::R
     fs::write(path, formatted_json.as_bytes())
 }
 
-/// Updates the hostnames.json file with the list of configured hostnames.
+/// Lists the hosts of the `nixosConfigurations` of the flake in `nix_config_dir`.
 ///
 /// # Errors
 ///
-/// Returns an error if the command fails or writing to the file fails.
-pub fn update_hostnames(output_path: &Path, nix_config_dir: &Path) -> Result<()> {
+/// Returns an error if the command fails or does not print a list of names.
+pub fn flake_hostnames(nix_config_dir: &Path) -> Result<Vec<String>> {
     let output = StdCommand::new("nix")
         .arg("eval")
         .arg("--json")
@@ -281,7 +499,17 @@ pub fn update_hostnames(output_path: &Path, nix_config_dir: &Path) -> Result<()>
         let stderr = String::from_utf8_lossy(&output.stderr).to_string();
         return Err(anyhow!(stderr));
     }
-    let json_str = String::from_utf8_lossy(&output.stdout).to_string();
+    serde_json::from_slice(&output.stdout).with_context(|| "Failed to parse the hostnames")
+}
+
+/// Updates the hostnames.json file with the list of configured hostnames.
+///
+/// # Errors
+///
+/// Returns an error if the command fails or writing to the file fails.
+pub fn update_hostnames(output_path: &Path, nix_config_dir: &Path) -> Result<()> {
+    let hostnames = flake_hostnames(nix_config_dir)?;
+    let json_str = serde_json::to_string(&hostnames)?;
 
     // Create parent directories if they don't exist.
     if let Some(parent) = output_path.parent() {
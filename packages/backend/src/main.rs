@@ -1,41 +1,153 @@
 use actix_cors::Cors;
 use actix_files::Files;
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use actix_web::http::header;
+use actix_web::{web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder};
+use anyhow::Context;
 use clap::{Arg, Command};
-use serde_json::json;
+use futures_util::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
 use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
 
-use backend::schema_types::Config;
-use backend::workspace::Workspace;
+use backend::cluster::{parse_cluster, validate_cluster};
+use backend::diff::{diff_configs, parse_diff_request};
+use backend::git_export::{ExportedBuild, GitExport, RepoOwner, BUNDLE_FILE};
+use backend::import::{
+    allowed_flake_dir, import_flake, unpack_flake, ImportedHost, MAX_TARBALL_SIZE,
+};
+use backend::jobs::{unix_time, BuildJob, BuildStatus, CancelRequest, JobStore};
+use backend::migrations::{migrate, migrate_file, Migrated};
+use backend::nix_expr::to_nix_pretty;
+use backend::nix_parser::parse_default_nix;
+use backend::options_schema::OptionsSchema;
+use backend::retention::{collect_garbage, RetentionPolicy};
+use backend::scheduler::{client_address, Scheduler};
+use backend::schema_types::{config_json_schema, Config};
+use backend::signing::{write_manifest, SigningKey};
+use backend::ssh_keys::{parse_authorized_key, AuthorizedKey};
+use backend::targets::BuildTarget;
+use backend::timezones::{timezone_names, IANA_TZDB_VERSION};
+use backend::validation::{parse_config, ValidationError};
+use backend::workspace::{Build, Workspace};
 use backend::{
-    create_tarball, handle_error, process_artifacts, run_json2nix, run_nix_build, update_hostnames,
-    update_schema, validate_config, write_default_nix, write_json_to_file,
+    cache_key, cluster_cache_key, config_hash, create_tarball, handle_error,
+    handle_validation_errors, process_artifacts, run_nix_build, terminate_process_group,
+    update_hostnames, update_schema, validate_config, write_default_nix, write_json_to_file,
 };
 
 // Embed the flake files at compile time.
 const FLAKE_NIX: &str = include_str!("static/flake.nix");
 
-// A static array of allowed filenames in the nix build output.
-const WHITELIST: &[&str] = &["bzImage", "initrd.zst", "kexec-boot"];
+/// Evaluates the options schema of the module that builds use, in a scratch copy of the flake.
+fn evaluate_schema(flake_lock: Option<&str>) -> anyhow::Result<OptionsSchema> {
+    let dir = tempfile::tempdir().context("Failed to create a directory for the flake")?;
+    fs::write(dir.path().join("flake.nix"), FLAKE_NIX).context("Failed to write flake.nix")?;
+    if let Some(flake_lock) = flake_lock {
+        fs::write(dir.path().join("flake.lock"), flake_lock)
+            .context("Failed to write flake.lock")?;
+    }
+    let schema_path = dir.path().join("options.json");
+    update_schema(&schema_path, dir.path()).context("Failed to evaluate the schema")?;
+    OptionsSchema::load(&schema_path)
+}
 
 /// Application state.
 struct AppState {
     workspace: Workspace,
-    base_url: String,
+    jobs: Arc<JobStore>,
+    scheduler: Arc<Scheduler>,
+    flake_lock: Option<String>,
+    signing_key: Option<SigningKey>,
+    /// Repositories that builds are committed to, if enabled.
+    git_export: Option<GitExport>,
+    /// Canonical directories that `/import` may read flakes from by path.
+    import_roots: Vec<PathBuf>,
+    /// Reverse proxies whose `Forwarded` and `X-Forwarded-For` headers name the client.
+    trusted_proxies: Vec<IpAddr>,
+    /// Options of the homestakeros module, once known; configs are checked against them.
+    schema: RwLock<Option<Arc<OptionsSchema>>>,
+}
+
+impl AppState {
+    /// The client that a request counts against when builds are scheduled fairly: the peer
+    /// address, or the nearest hop before the trusted proxies that forwarded the request.
+    fn client(&self, req: &HttpRequest) -> String {
+        let Some(peer) = req.peer_addr().map(|addr| addr.ip()) else {
+            return "unknown".to_string();
+        };
+        let joined = |name| {
+            let values: Vec<&str> = req
+                .headers()
+                .get_all(name)
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            (!values.is_empty()).then(|| values.join(","))
+        };
+        client_address(
+            peer,
+            joined(header::FORWARDED).as_deref(),
+            joined(header::X_FORWARDED_FOR).as_deref(),
+            &self.trusted_proxies,
+        )
+    }
+
+    fn schema(&self) -> Option<Arc<OptionsSchema>> {
+        self.schema
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn set_schema(&self, schema: OptionsSchema) {
+        *self.schema.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(schema));
+    }
 }
 
 async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(json!({ "status": "ok" }))
 }
 
-/// Accepts strongly typed JSON and then processes it.
-async fn nixos_config(req_body: String, data: web::Data<AppState>) -> impl Responder {
-    // Parse the request body manually; we can catch errors ourselves.
-    let config: Config = match serde_json::from_str(&req_body) {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            return handle_error("Failed to parse JSON", e);
+/// Query parameters of a build request.
+#[derive(Deserialize)]
+struct BuildQuery {
+    /// Name of the build target; defaults to a kexec build.
+    target: Option<String>,
+}
+
+impl BuildQuery {
+    /// The requested build target, or the response to a request for an unknown one.
+    fn build_target(&self) -> Result<BuildTarget, HttpResponse> {
+        match self.target.as_deref().map(str::parse).transpose() {
+            Ok(target) => Ok(target.unwrap_or_default()),
+            Err(e) => Err(HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": "Invalid build target",
+                "error": e.to_string()
+            }))),
         }
+    }
+}
+
+/// Accepts strongly typed JSON, queues a build for it and returns the build id.
+async fn nixos_config(
+    req: HttpRequest,
+    query: web::Query<BuildQuery>,
+    req_body: String,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let target = match query.build_target() {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    // Parse the request body manually; we can report every problem ourselves.
+    let config: Config = match parse_config(&req_body, data.schema().as_deref()) {
+        Ok(cfg) => cfg,
+        Err(errors) => return handle_validation_errors("Failed to parse JSON", &errors),
     };
 
     // Serialize the typed config into a JSON string.
@@ -46,9 +158,9 @@ async fn nixos_config(req_body: String, data: web::Data<AppState>) -> impl Respo
         }
     };
 
-    // Validate that required fields are not empty.
-    if let Err(e) = validate_config(&config) {
-        return handle_error("Failed to validate JSON", e);
+    // Validate required fields and the rules between them.
+    if let Err(errors) = validate_config(&config) {
+        return handle_validation_errors("Failed to validate JSON", &errors);
     }
 
     // Print the input JSON string.
@@ -57,80 +169,672 @@ async fn nixos_config(req_body: String, data: web::Data<AppState>) -> impl Respo
     // Extract hostname from the config.
     let hostname = config.localization.hostname.clone();
 
+    // Report who will have access, so that users can check the fingerprints.
+    let authorized_keys: Vec<AuthorizedKey> = config
+        .ssh
+        .authorized_keys
+        .iter()
+        .filter_map(|key| parse_authorized_key(key).ok())
+        .collect();
+
+    // Identical configs built for the same target with the same flake share one build.
+    let config_hash = match config_hash(&config) {
+        Ok(hash) => hash,
+        Err(e) => return handle_error("Failed to hash config", e),
+    };
+    let cache_key = match cache_key(&config, target, FLAKE_NIX, data.flake_lock.as_deref()) {
+        Ok(key) => key,
+        Err(e) => return handle_error("Failed to compute cache key", e),
+    };
+
     // Create a unique build workspace.
     let workspace = match data.workspace.new_build_workspace(&hostname) {
         Ok(ws) => ws,
         Err(e) => return handle_error("Failed to create workspace", e),
     };
+    let build_id = workspace.uuid.clone();
+    let job = BuildJob::new(&build_id, &hostname, &config_hash, &cache_key, target);
+    if let Some(cached) = data.jobs.insert_unless_cached(job) {
+        println!("Reusing build {} for {hostname}", cached.build_id);
+        workspace.discard();
+        return cached_build_response(&cached, &authorized_keys);
+    }
 
-    // Run json2nix.
-    let json2nix_output = match run_json2nix(&json_str) {
-        Ok(out) => out,
-        Err(e) => return handle_error("Failed to run json2nix", e),
-    };
+    // Queue the build; the workspace is cleaned up when it is dropped.
+    let client = data.client(&req);
+    let state = data.clone();
+    data.scheduler.submit(
+        &client,
+        &build_id,
+        Box::new(move || {
+            let result = run_build(&workspace, &config, &[], None, target, &state);
+            finish_build(workspace, result, &state);
+        }),
+    );
 
-    // Output the original JSON to default.json
-    let default_json_path = workspace.hostname_dir.join("default.json");
-    if let Err(e) = write_json_to_file(&default_json_path, &json_str) {
-        return handle_error("Failed to write default.json file", e);
+    HttpResponse::Accepted().json(json!({
+        "status": "ok",
+        "build_id": build_id,
+        "target": target,
+        "status_url": format!("/builds/{build_id}/status"),
+        "authorized_keys": authorized_keys
+    }))
+}
+
+/// Responds to a request that was deduplicated against an existing build.
+///
+/// A finished build returns its artifacts straight away; one that is still in progress is
+/// reported like a freshly queued build.
+fn cached_build_response(cached: &BuildJob, authorized_keys: &[AuthorizedKey]) -> HttpResponse {
+    let build_id = &cached.build_id;
+    let body = json!({
+        "status": "ok",
+        "build_id": build_id,
+        "target": cached.target,
+        "status_url": format!("/builds/{build_id}/status"),
+        "cached": true,
+        "artifacts": cached.artifacts,
+        "commit": cached.commit,
+        "authorized_keys": authorized_keys
+    });
+    if cached.status.is_finished() {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::Accepted().json(body)
+    }
+}
+
+/// Records the outcome of a build that ran on the scheduler.
+fn finish_build(workspace: Build, result: anyhow::Result<Vec<Value>>, state: &AppState) {
+    let build_id = workspace.uuid.clone();
+    match result {
+        Ok(artifacts) => {
+            println!("Build {build_id} completed.");
+            state.jobs.succeed(&build_id, artifacts);
+        }
+        Err(_) if state.jobs.is_cancel_requested(&build_id) => {
+            println!("Build {build_id} cancelled.");
+            state.jobs.cancel(&build_id);
+            workspace.discard();
+        }
+        Err(e) => {
+            println!("Build {build_id} failed: {e:#}");
+            state.jobs.fail(&build_id, &format!("{e:#}"));
+        }
     }
+}
+
+/// Writes a host's config to `default.json` and `default.nix` in its directory of the flake.
+fn write_host(host_dir: &Path, config: &Config) -> anyhow::Result<()> {
+    // Render the config as a Nix expression.
+    let json_str = serde_json::to_string(config).context("Failed to serialize JSON")?;
+    let module_options = config
+        .module_options()
+        .context("Failed to serialize JSON")?;
+    let nix_expr = to_nix_pretty(&module_options);
+
+    // Output the original JSON to default.json
+    let default_json_path = host_dir.join("default.json");
+    write_json_to_file(&default_json_path, &json_str)
+        .context("Failed to write default.json file")?;
 
     // Prepend boilerplate and write default.nix.
-    if let Err(e) = write_default_nix(&workspace.hostname_dir, &json2nix_output) {
-        return handle_error("Failed to write default.nix file", e);
+    write_default_nix(host_dir, &nix_expr).context("Failed to write default.nix file")
+}
+
+/// Runs the evaluation and build stages for a queued build and returns its artifacts.
+///
+/// The `others` are the remaining hosts of a cluster: they are rendered into the same flake, so
+/// that its `nixConfig.tar` deploys the whole cluster, but only `config` is built. If git export
+/// is enabled, the flake is committed to the repository of the named `cluster`, or else of the
+/// host.
+fn run_build(
+    workspace: &Build,
+    config: &Config,
+    others: &[&Config],
+    cluster: Option<&str>,
+    target: BuildTarget,
+    state: &AppState,
+) -> anyhow::Result<Vec<Value>> {
+    let jobs = &state.jobs;
+    let build_id = &workspace.uuid;
+    let hostname = &config.localization.hostname;
+    ensure_not_cancelled(jobs, build_id)?;
+    jobs.set_status(build_id, BuildStatus::Evaluating);
+
+    write_host(&workspace.hostname_dir, config)?;
+    for other in others {
+        let other_hostname = &other.localization.hostname;
+        let host_dir = workspace.host_dir(other_hostname)?;
+        write_host(&host_dir, other)
+            .with_context(|| format!("Failed to write the config of {other_hostname}"))?;
     }
 
     // Write the embedded flake file.
     let flake_nix_path = workspace.nix_config_dir.join("flake.nix");
-    if let Err(e) = fs::write(&flake_nix_path, FLAKE_NIX) {
-        return handle_error("Failed to write flake.nix", e);
+    fs::write(&flake_nix_path, FLAKE_NIX).context("Failed to write flake.nix")?;
+
+    // Pin the flake inputs, if a lock file was configured.
+    if let Some(flake_lock) = &state.flake_lock {
+        let flake_lock_path = workspace.nix_config_dir.join("flake.lock");
+        fs::write(&flake_lock_path, flake_lock).context("Failed to write flake.lock")?;
     }
 
     // Fetch hostnames.json.
+    ensure_not_cancelled(jobs, build_id)?;
     let hostnames_output = workspace
         .nix_config_dir
         .join("nixosConfigurations/hostnames.json");
-    if let Err(e) = update_hostnames(&hostnames_output, &workspace.nix_config_dir) {
-        return handle_error("Failed to write hostnames.json", e);
-    }
+    update_hostnames(&hostnames_output, &workspace.nix_config_dir)
+        .context("Failed to write hostnames.json")?;
 
     // Fetch options.json.
+    ensure_not_cancelled(jobs, build_id)?;
     let schema_output = workspace
         .nix_config_dir
         .join("nixosModules/homestakeros/options.json");
-    if let Err(e) = update_schema(&schema_output, &workspace.nix_config_dir) {
-        return handle_error("Failed to write options.json", e);
-    }
+    update_schema(&schema_output, &workspace.nix_config_dir)
+        .context("Failed to write options.json")?;
 
-    // Retrieve the build id and pre-created output directory.
-    let build_id = &workspace.uuid;
-    let output_dir = workspace.output_dir.clone();
+    // Keep checking configs against the options of the module that is actually built.
+    match OptionsSchema::load(&schema_output) {
+        Ok(schema) => state.set_schema(schema),
+        Err(e) => println!("Failed to load the module schema: {e:#}"),
+    }
 
     // Create nixConfig.tar.
-    if let Err(e) = create_tarball(&workspace.nix_config_dir, &output_dir, "nixConfig.tar") {
-        return handle_error("Failed to create nixConfig.tar", e);
-    }
+    let output_dir = &workspace.output_dir;
+    create_tarball(&workspace.nix_config_dir, output_dir, "nixConfig.tar")
+        .context("Failed to create nixConfig.tar")?;
 
     // Run nix build.
-    if let Err(e) = run_nix_build(&workspace.nix_config_dir, &hostname, &output_dir, WHITELIST) {
-        return handle_error("Failed to run nix build", e);
+    ensure_not_cancelled(jobs, build_id)?;
+    jobs.set_status(build_id, BuildStatus::Building);
+    let result = run_nix_build(
+        &workspace.nix_config_dir,
+        hostname,
+        target,
+        &workspace.gc_root,
+        output_dir,
+        |pid| {
+            // A cancellation that arrived while nix was starting up is applied right away.
+            if jobs.set_pid(build_id, Some(pid)) {
+                stop_build(pid);
+            }
+        },
+        |line| jobs.append_log(build_id, line),
+    );
+    jobs.set_pid(build_id, None);
+    result.context("Failed to run nix build")?;
+    println!("Nix build of {target} completed.");
+
+    // Record the flake in the history of the host or cluster.
+    if let Some(git_export) = &state.git_export {
+        let owner = cluster.map_or(RepoOwner::Host(hostname), RepoOwner::Cluster);
+        let config_hash = config_hash(config).context("Failed to hash config")?;
+        let build = ExportedBuild {
+            build_id,
+            hostname,
+            config_hash: &config_hash,
+            target,
+        };
+        let bundle_path = output_dir.join(BUNDLE_FILE);
+        let commit = git_export
+            .export(owner, &workspace.nix_config_dir, &build, &bundle_path)
+            .context("Failed to commit nixConfig")?;
+        println!("Committed build {build_id} as {commit}.");
+        jobs.set_commit(build_id, &commit);
     }
-    println!("Nix build completed.");
+
+    // Write the (signed) manifest of the artifacts.
+    write_manifest(output_dir, build_id, hostname, state.signing_key.as_ref())
+        .context("Failed to write manifest")?;
 
     // Process all files from the output directory.
-    let artifacts_info = match process_artifacts(&output_dir, build_id) {
-        Ok(info) => info,
-        Err(e) => return handle_error("Failed to process artifacts", e),
+    process_artifacts(output_dir, build_id, target).context("Failed to process artifacts")
+}
+
+/// Accepts several configs, as a list or as `{"name": ..., "hosts": [...]}`, and queues a build
+/// of each host from one flake that contains them all.
+///
+/// The hosts are built in parallel as far as the scheduler allows. Every host's `nixConfig.tar`
+/// holds the whole cluster; `nix_config_url` points at the one of the first host.
+async fn cluster_config(
+    req: HttpRequest,
+    query: web::Query<BuildQuery>,
+    req_body: String,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let target = match query.build_target() {
+        Ok(target) => target,
+        Err(response) => return response,
+    };
+
+    let cluster = match parse_cluster(&req_body, data.schema().as_deref()) {
+        Ok(cluster) => cluster,
+        Err(errors) => return handle_validation_errors("Failed to parse JSON", &errors),
+    };
+    if let Err(errors) = validate_cluster(&cluster) {
+        return handle_validation_errors("Failed to validate JSON", &errors);
+    }
+    let cluster = Arc::new(cluster);
+    let client = data.client(&req);
+
+    // Prepare every host before registering any build, so that a failure leaves nothing behind.
+    let mut prepared = Vec::new();
+    for (index, config) in cluster.hosts.iter().enumerate() {
+        let hostname = config.localization.hostname.clone();
+        let config_hash = match config_hash(config) {
+            Ok(hash) => hash,
+            Err(e) => return handle_error("Failed to hash config", e),
+        };
+        let cache_key = match cluster_cache_key(
+            config,
+            &cluster.others(index),
+            target,
+            FLAKE_NIX,
+            data.flake_lock.as_deref(),
+        ) {
+            Ok(key) => key,
+            Err(e) => return handle_error("Failed to compute cache key", e),
+        };
+        prepared.push((hostname, config_hash, cache_key));
+    }
+    let mut workspaces = Vec::new();
+    for (hostname, _, _) in &prepared {
+        match data.workspace.new_build_workspace(hostname) {
+            Ok(ws) => workspaces.push(ws),
+            Err(e) => {
+                workspaces.into_iter().for_each(Build::discard);
+                return handle_error("Failed to create workspace", e);
+            }
+        }
+    }
+
+    let mut hosts = Vec::new();
+    let mut pending = false;
+    for (index, ((hostname, config_hash, cache_key), workspace)) in
+        prepared.into_iter().zip(workspaces).enumerate()
+    {
+        let build_id = workspace.uuid.clone();
+        let mut job = BuildJob::new(&build_id, &hostname, &config_hash, &cache_key, target);
+        job.cluster.clone_from(&cluster.name);
+        if let Some(cached) = data.jobs.insert_unless_cached(job) {
+            println!("Reusing build {} for {hostname}", cached.build_id);
+            workspace.discard();
+            pending |= !cached.status.is_finished();
+            hosts.push(json!({
+                "hostname": hostname,
+                "build_id": cached.build_id,
+                "status_url": format!("/builds/{}/status", cached.build_id),
+                "cached": true,
+                "artifacts": cached.artifacts,
+                "commit": cached.commit
+            }));
+            continue;
+        }
+
+        let state = data.clone();
+        let cluster = Arc::clone(&cluster);
+        data.scheduler.submit(
+            &client,
+            &build_id,
+            Box::new(move || {
+                let config = &cluster.hosts[index];
+                let others = cluster.others(index);
+                let name = cluster.name.as_deref();
+                let result = run_build(&workspace, config, &others, name, target, &state);
+                finish_build(workspace, result, &state);
+            }),
+        );
+        pending = true;
+        hosts.push(json!({
+            "hostname": hostname,
+            "build_id": build_id,
+            "status_url": format!("/builds/{build_id}/status"),
+            "cached": false,
+            "artifacts": []
+        }));
+    }
+    println!(
+        "Queued a cluster of {} hosts: {}",
+        hosts.len(),
+        cluster.name.as_deref().unwrap_or("unnamed")
+    );
+
+    let nix_config_url = format!(
+        "/builds/{}/nixConfig.tar",
+        hosts[0]["build_id"].as_str().unwrap_or_default()
+    );
+    let body = json!({
+        "status": "ok",
+        "cluster": cluster.name,
+        "target": target,
+        "hosts": hosts,
+        "nix_config_url": nix_config_url
+    });
+    if pending {
+        HttpResponse::Accepted().json(body)
+    } else {
+        HttpResponse::Ok().json(body)
+    }
+}
+
+/// Returns an error if cancellation of the build has been requested.
+fn ensure_not_cancelled(jobs: &JobStore, build_id: &str) -> anyhow::Result<()> {
+    if jobs.is_cancel_requested(build_id) {
+        anyhow::bail!("Build was cancelled");
+    }
+    Ok(())
+}
+
+/// Terminates the nix process group of a build.
+fn stop_build(pid: u32) {
+    if let Err(e) = terminate_process_group(pid) {
+        println!("Failed to terminate nix process group {pid}: {e}");
+    }
+}
+
+/// Cancels a queued or running build.
+///
+/// The build's nix process group is terminated, and the build is marked as cancelled and cleaned
+/// up by the executor once the process has exited.
+async fn cancel_build(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let build_id = path.into_inner();
+    match data.jobs.request_cancel(&build_id) {
+        CancelRequest::Requested(pid) => {
+            if let Some(pid) = pid {
+                stop_build(pid);
+            }
+            // A queued build is started right away so that it notices the cancellation and
+            // cleans up after itself.
+            if let Some(task) = data.scheduler.remove(&build_id) {
+                actix_web::rt::task::spawn_blocking(task);
+            }
+            HttpResponse::Accepted().json(json!({
+                "status": "ok",
+                "build_id": build_id,
+                "status_url": format!("/builds/{build_id}/status")
+            }))
+        }
+        CancelRequest::Finished(status) => HttpResponse::Conflict().json(json!({
+            "status": "error",
+            "message": "Build already finished",
+            "error": format!("Build '{build_id}' has already finished"),
+            "build_status": status
+        })),
+        CancelRequest::NotFound => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Build not found",
+            "error": format!("No build with id '{build_id}'")
+        })),
+    }
+}
+
+/// Reports the status of a build, its position in the queue while it waits and, once it has
+/// succeeded, its artifacts and the commit of its flake if builds are exported to git.
+///
+/// Served both as `/builds/{id}` and `/builds/{id}/status`.
+async fn build_status(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let build_id = path.into_inner();
+    match data.jobs.get(&build_id) {
+        Some(job) => {
+            let mut body = json!(job);
+            if let Some(position) = data.scheduler.position(&build_id) {
+                body["queue_position"] = json!(position);
+            }
+            HttpResponse::Ok().json(body)
+        }
+        None => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Build not found",
+            "error": format!("No build with id '{build_id}'")
+        })),
+    }
+}
+
+/// Upgrades a config document of an older format version and returns it with the changes made.
+async fn migrate_config(req_body: String) -> impl Responder {
+    let mut document: Value = match serde_json::from_str(&req_body) {
+        Ok(document) => document,
+        Err(e) => {
+            let error = ValidationError::new("", "invalid_json", &format!("Invalid JSON: {e}"));
+            return handle_validation_errors("Failed to parse JSON", &[error]);
+        }
+    };
+    match migrate(&mut document) {
+        Ok(migrated) => HttpResponse::Ok().json(json!({
+            "status": "ok",
+            "from_version": migrated.from_version,
+            "to_version": migrated.to_version,
+            "changes": migrated.changes,
+            "config": document
+        })),
+        Err(error) => handle_validation_errors("Failed to migrate config", &[error]),
+    }
+}
+
+/// Compares two configs, `{"before": ..., "after": ...}`, and returns what would change, both
+/// structured and rendered as text, along with the diff of the generated `default.nix`.
+async fn diff_config(req_body: String, data: web::Data<AppState>) -> impl Responder {
+    let (before, after) = match parse_diff_request(&req_body, data.schema().as_deref()) {
+        Ok(configs) => configs,
+        Err(errors) => return handle_validation_errors("Failed to parse JSON", &errors),
+    };
+    match diff_configs(&before, &after) {
+        Ok(diff) => HttpResponse::Ok().json(json!({
+            "status": "ok",
+            "changes": diff.changes,
+            "default_nix": diff.default_nix,
+            "text": diff.to_string()
+        })),
+        Err(e) => handle_error("Failed to compare configs", e),
+    }
+}
+
+/// Reads the config of a host's `default.nix`, generated or edited by hand, without evaluating
+/// it, so that it can be loaded into the UI.
+async fn parse_nix_config(req_body: String, data: web::Data<AppState>) -> impl Responder {
+    match parse_default_nix(&req_body, data.schema().as_deref()) {
+        Ok(config) => HttpResponse::Ok().json(json!({ "status": "ok", "config": config })),
+        Err(errors) => handle_validation_errors("Failed to read default.nix", &errors),
+    }
+}
+
+/// Reads a config file for a subcommand, either JSON or a host's `default.nix`, reporting every
+/// problem with its location.
+fn read_config(path: &str, schema: Option<&OptionsSchema>) -> anyhow::Result<Config> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+    let parsed = if Path::new(path).extension().is_some_and(|ext| ext == "nix") {
+        parse_default_nix(&text, schema)
+    } else {
+        parse_config(&text, schema)
+    };
+    parsed.map_err(|errors| {
+        let problems: Vec<String> = errors
+            .iter()
+            .map(|e| format!("  {}: {}", e.pointer, e.message))
+            .collect();
+        anyhow::anyhow!("{path} is not a valid config:\n{}", problems.join("\n"))
+    })
+}
+
+/// Body of an import request that names a flake on the backend's machine.
+#[derive(Deserialize)]
+struct ImportRequest {
+    path: String,
+}
+
+/// Reads the homestakeros configs of the hosts of an existing flake.
+///
+/// The flake is either uploaded as a tar archive, optionally gzip-compressed, or named by a JSON
+/// body `{"path": ...}` if it is inside one of the directories given with `--import-root`.
+/// Either way it is evaluated without `--impure` or `--accept-flake-config`, which only the
+/// `import` subcommand passes. Hosts whose config does not fit are listed with their problems.
+async fn import_config(
+    req: HttpRequest,
+    body: web::Bytes,
+    data: web::Data<AppState>,
+) -> impl Responder {
+    let upload_dir = match tempfile::tempdir() {
+        Ok(dir) => dir,
+        Err(e) => return handle_error("Failed to create a directory for the flake", e),
+    };
+    let flake_dir = if req.content_type() == "application/json" {
+        let request = match serde_json::from_slice::<ImportRequest>(&body) {
+            Ok(request) => request,
+            Err(e) => {
+                return HttpResponse::BadRequest().json(json!({
+                    "status": "error",
+                    "message": "Invalid import request",
+                    "error": e.to_string()
+                }))
+            }
+        };
+        match allowed_flake_dir(Path::new(&request.path), &data.import_roots) {
+            Ok(flake_dir) => flake_dir,
+            Err(e) => {
+                return HttpResponse::Forbidden().json(json!({
+                    "status": "error",
+                    "message": "Import from this path is not allowed",
+                    "error": format!("{e:#}")
+                }))
+            }
+        }
+    } else {
+        let dir = upload_dir.path().to_path_buf();
+        match web::block(move || unpack_flake(&body, &dir)).await {
+            Ok(Ok(flake_dir)) => flake_dir,
+            Ok(Err(e)) => {
+                return HttpResponse::BadRequest().json(json!({
+                    "status": "error",
+                    "message": "Invalid flake tarball",
+                    "error": format!("{e:#}")
+                }))
+            }
+            Err(e) => return handle_error("Failed to unpack the flake", e),
+        }
     };
 
-    // Return artifacts in JSON
+    let schema = data.schema();
+    let imported = web::block(move || import_flake(&flake_dir, schema.as_deref(), false)).await;
+    drop(upload_dir);
+    match imported {
+        Ok(Ok(hosts)) => HttpResponse::Ok().json(json!({ "status": "ok", "hosts": hosts })),
+        Ok(Err(e)) => handle_error("Failed to import the flake", format!("{e:#}")),
+        Err(e) => handle_error("Failed to import the flake", e),
+    }
+}
+
+/// Prints the imported hosts as JSON and each problem on stderr. Returns whether every host
+/// was imported.
+fn print_import(hosts: &[ImportedHost]) -> anyhow::Result<bool> {
+    println!("{}", serde_json::to_string_pretty(hosts)?);
+    for host in hosts {
+        for error in &host.errors {
+            eprintln!("{}: {} ({})", host.hostname, error.message, error.pointer);
+        }
+    }
+    Ok(hosts.iter().all(|host| host.errors.is_empty()))
+}
+
+/// Prints what upgrading a file did.
+fn print_migration(path: &str, migrated: &Migrated) {
+    if migrated.is_unchanged() {
+        println!("{path}: up to date (version {})", migrated.to_version);
+        return;
+    }
+    println!(
+        "{path}: version {} -> {}",
+        migrated.from_version, migrated.to_version
+    );
+    for change in &migrated.changes {
+        println!("  {}: {}", change.pointer, change.message);
+    }
+}
+
+/// Lists the build targets and the files each of them produces.
+async fn list_targets() -> impl Responder {
+    let targets: Vec<Value> = BuildTarget::ALL
+        .iter()
+        .map(|target| json!({ "name": target, "files": target.whitelist() }))
+        .collect();
+    HttpResponse::Ok().json(json!({ "status": "ok", "targets": targets }))
+}
+
+/// Lists the time zones that `localization.timezone` accepts, for the UI to offer a picker.
+async fn list_timezones() -> impl Responder {
     HttpResponse::Ok().json(json!({
         "status": "ok",
-        "build_id": build_id,
-        "artifacts": artifacts_info
+        "version": IANA_TZDB_VERSION,
+        "timezones": timezone_names()
     }))
 }
 
+/// Serves the JSON Schema of the config format, so that it can be referenced as `$schema`.
+async fn json_schema() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/schema+json")
+        .json(config_json_schema())
+}
+
+/// Serves the minisign public key that build manifests are signed with.
+async fn signing_public_key(data: web::Data<AppState>) -> impl Responder {
+    match &data.signing_key {
+        Some(key) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(key.public_key()),
+        None => HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Signing is disabled",
+            "error": "The backend was started without a signing key"
+        })),
+    }
+}
+
+/// Lists all known builds, newest first.
+async fn list_builds(data: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "builds": data.jobs.list()
+    }))
+}
+
+/// Streams the nix log of a build as Server-Sent Events.
+///
+/// Lines logged before the client connected are replayed first. An `end` event is sent once the
+/// build has finished.
+async fn build_logs(path: web::Path<String>, data: web::Data<AppState>) -> impl Responder {
+    let build_id = path.into_inner();
+    let Some((replay, rx)) = data.jobs.subscribe(&build_id) else {
+        return HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": "Build not found",
+            "error": format!("No build with id '{build_id}'")
+        }));
+    };
+
+    let replay = stream::iter(replay.into_iter().map(|line| sse_event("log", &line)));
+    let live = stream::unfold(Some(rx), |rx| async move {
+        let mut rx = rx?;
+        match rx.recv().await {
+            Some(line) => Some((sse_event("log", &line), Some(rx))),
+            None => Some((sse_event("end", ""), None)),
+        }
+    });
+    let events = replay.chain(live).map(Ok::<_, actix_web::Error>);
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+/// Formats a single Server-Sent Event.
+fn sse_event(event: &str, data: &str) -> web::Bytes {
+    web::Bytes::from(format!("event: {event}\ndata: {data}\n\n"))
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let matches = Command::new("HomestakerOS")
@@ -153,8 +857,219 @@ async fn main() -> std::io::Result<()> {
                 .default_value("8081")
                 .help("Port to bind the server"),
         )
+        .arg(
+            Arg::new("state-dir")
+                .short('s')
+                .long("state-dir")
+                .value_name("DIR")
+                .help("Directory for builds and the build index; a temporary directory is used if omitted"),
+        )
+        .arg(
+            Arg::new("max-builds")
+                .short('j')
+                .long("max-builds")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .help("Maximum number of builds to run at the same time"),
+        )
+        .arg(
+            Arg::new("max-age-days")
+                .long("max-age-days")
+                .value_name("DAYS")
+                .value_parser(clap::value_parser!(u64))
+                .help("Remove builds that finished more than this many days ago"),
+        )
+        .arg(
+            Arg::new("max-total-size-mb")
+                .long("max-total-size-mb")
+                .value_name("MB")
+                .value_parser(clap::value_parser!(u64))
+                .help("Remove the oldest builds while the builds directory exceeds this size"),
+        )
+        .arg(
+            Arg::new("keep-per-host")
+                .long("keep-per-host")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Keep only the newest N builds of each hostname"),
+        )
+        .arg(
+            Arg::new("gc-interval-minutes")
+                .long("gc-interval-minutes")
+                .value_name("MINUTES")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("60")
+                .help("How often to apply the retention limits"),
+        )
+        .arg(
+            Arg::new("signing-key")
+                .long("signing-key")
+                .value_name("FILE")
+                .help("Ed25519 key for signing build manifests; generated if the file does not exist"),
+        )
+        .arg(
+            Arg::new("schema")
+                .long("schema")
+                .value_name("FILE")
+                .help("Options schema of the homestakeros module, as printed by 'nix eval --json .#schema'; evaluated from the build flake if omitted"),
+        )
+        .arg(
+            Arg::new("flake-lock")
+                .long("flake-lock")
+                .value_name("FILE")
+                .help("Lock file pinning the inputs of the build flake"),
+        )
+        .arg(
+            Arg::new("git-export")
+                .long("git-export")
+                .action(clap::ArgAction::SetTrue)
+                .help("Commit the nixConfig of every build to a bare git repository per hostname or named cluster in the state directory, and add a git bundle of it to the artifacts"),
+        )
+        .arg(
+            Arg::new("trusted-proxy")
+                .long("trusted-proxy")
+                .value_name("ADDR")
+                .action(clap::ArgAction::Append)
+                .help("Address of a reverse proxy whose Forwarded and X-Forwarded-For headers name the client for fair scheduling; may be repeated"),
+        )
+        .arg(
+            Arg::new("import-root")
+                .long("import-root")
+                .value_name("DIR")
+                .action(clap::ArgAction::Append)
+                .help("Directory whose flakes POST /import may read by path; may be repeated. Without it, flakes can only be uploaded"),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Print the JSON Schema of the config format and exit"),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Upgrade config files to the current format version, rewriting them in place")
+                .arg(
+                    Arg::new("files")
+                        .value_name("FILE")
+                        .num_args(1..)
+                        .required(true)
+                        .help("Config files, such as default.json"),
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Only report; exit with an error if any file needs upgrading"),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Print what changes between two config files")
+                .arg(
+                    Arg::new("before")
+                        .value_name("BEFORE")
+                        .required(true)
+                        .help("The current config, such as the default.json or default.nix of a deployed host"),
+                )
+                .arg(
+                    Arg::new("after")
+                        .value_name("AFTER")
+                        .required(true)
+                        .help("The new config"),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Print the homestakeros configs of the hosts of an existing flake as JSON")
+                .arg(
+                    Arg::new("flake")
+                        .value_name("FLAKE")
+                        .required(true)
+                        .help("Directory of the flake, or a tar archive of it"),
+                ),
+        )
         .get_matches();
 
+    if matches.subcommand_matches("schema").is_some() {
+        let schema = serde_json::to_string_pretty(&config_json_schema())?;
+        println!("{schema}");
+        return Ok(());
+    }
+
+    if let Some(migrate_matches) = matches.subcommand_matches("migrate") {
+        let check = migrate_matches.get_flag("check");
+        let mut outdated = false;
+        let mut failed = false;
+        for path in migrate_matches.get_many::<String>("files").unwrap() {
+            match migrate_file(Path::new(path), !check) {
+                Ok(migrated) => {
+                    outdated |= !migrated.is_unchanged();
+                    print_migration(path, &migrated);
+                }
+                Err(e) => {
+                    failed = true;
+                    eprintln!("{e:#}");
+                }
+            }
+        }
+        if failed || (check && outdated) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let cli_schema = || {
+        matches.get_one::<String>("schema").map(|path| {
+            OptionsSchema::load(Path::new(path))
+                .unwrap_or_else(|e| panic!("Failed to load schema: {e:#}"))
+        })
+    };
+
+    if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        let schema = cli_schema();
+        let read = |name| {
+            read_config(
+                diff_matches.get_one::<String>(name).unwrap(),
+                schema.as_ref(),
+            )
+        };
+        let diff = read("before").and_then(|before| {
+            let after = read("after")?;
+            Ok(diff_configs(&before, &after)?)
+        });
+        match diff {
+            Ok(diff) => {
+                print!("{diff}");
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(import_matches) = matches.subcommand_matches("import") {
+        let flake = Path::new(import_matches.get_one::<String>("flake").unwrap());
+        let schema = cli_schema();
+        let upload_dir = tempfile::tempdir()?;
+        let imported = if flake.is_dir() {
+            import_flake(flake, schema.as_ref(), true)
+        } else {
+            fs::read(flake)
+                .with_context(|| format!("Failed to read {flake:?}"))
+                .and_then(|tarball| unpack_flake(&tarball, upload_dir.path()))
+                .and_then(|flake_dir| import_flake(&flake_dir, schema.as_ref(), true))
+        };
+        match imported.and_then(|hosts| print_import(&hosts)) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("{e:#}");
+                std::process::exit(1);
+            }
+        }
+    }
+
     let addr = matches.get_one::<String>("addr").unwrap();
     let port = matches.get_one::<String>("port").unwrap();
     let base_url = "http://".to_string() + addr + ":" + port;
@@ -162,30 +1077,165 @@ async fn main() -> std::io::Result<()> {
     println!("Running on: {base_url}");
 
     // Create a Workspace singleton.
-    let workspace = Workspace::new().expect("Failed to create workspace");
-    println!(
-        "Using temporary directory: {}",
-        workspace.base_dir.path().display()
-    );
+    let workspace = match matches.get_one::<String>("state-dir") {
+        Some(state_dir) => {
+            let workspace = Workspace::with_state_dir(Path::new(state_dir))
+                .expect("Failed to create workspace");
+            println!("Using state directory: {}", workspace.base_dir.display());
+            workspace
+        }
+        None => {
+            let workspace = Workspace::new().expect("Failed to create workspace");
+            println!(
+                "Using temporary directory: {}",
+                workspace.base_dir.display()
+            );
+            workspace
+        }
+    };
+
+    // Load the builds from previous runs.
+    let jobs =
+        Arc::new(JobStore::open(&workspace.index_path()).expect("Failed to open build index"));
+
+    // Read the lock file for the build flake, if any.
+    let flake_lock = matches.get_one::<String>("flake-lock").map(|path| {
+        fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {path}: {e}"))
+    });
+
+    // Load the key for signing build manifests, if any.
+    let signing_key = matches.get_one::<String>("signing-key").map(|path| {
+        let key = SigningKey::load_or_generate(Path::new(path))
+            .unwrap_or_else(|e| panic!("Failed to load signing key: {e:#}"));
+        println!("Signing build manifests with key {}", key.key_id());
+        key
+    });
+
+    // Set up the repositories that builds are committed to, if enabled.
+    let git_export = matches.get_flag("git-export").then(|| {
+        let repos_dir = workspace.repos_dir();
+        println!(
+            "Committing builds to the repositories in {}",
+            repos_dir.display()
+        );
+        GitExport::new(&repos_dir).unwrap_or_else(|e| panic!("Failed to set up git export: {e:#}"))
+    });
+
+    // Resolve the directories that flakes may be imported from by path.
+    let import_roots: Vec<PathBuf> = matches
+        .get_many::<String>("import-root")
+        .unwrap_or_default()
+        .map(|dir| {
+            Path::new(dir)
+                .canonicalize()
+                .unwrap_or_else(|e| panic!("Failed to resolve import root {dir}: {e}"))
+        })
+        .collect();
 
+    // Parse the addresses of the proxies that may name the client of a request.
+    let trusted_proxies: Vec<IpAddr> = matches
+        .get_many::<String>("trusted-proxy")
+        .unwrap_or_default()
+        .map(|addr| {
+            addr.parse()
+                .unwrap_or_else(|e| panic!("Invalid trusted proxy address {addr}: {e}"))
+        })
+        .collect();
+
+    // A build whose task panicked would otherwise never leave its state.
+    let max_builds = *matches.get_one::<usize>("max-builds").unwrap();
+    let panicked_jobs = Arc::clone(&jobs);
+    let scheduler = Scheduler::start(max_builds, move |build_id| {
+        panicked_jobs.fail(build_id, "The build crashed");
+    });
     let app_state = web::Data::new(AppState {
         workspace,
-        base_url,
+        jobs,
+        scheduler,
+        flake_lock,
+        signing_key,
+        git_export,
+        import_roots,
+        trusted_proxies,
+        schema: RwLock::new(None),
     });
 
+    // Load the options schema that configs are checked against.
+    match matches.get_one::<String>("schema") {
+        Some(path) => {
+            let schema = OptionsSchema::load(Path::new(path))
+                .unwrap_or_else(|e| panic!("Failed to load schema: {e:#}"));
+            app_state.set_schema(schema);
+        }
+        None => {
+            // Evaluating takes a while, so serve requests meanwhile; until then, options the
+            // config types do not know about are rejected.
+            let state = app_state.clone();
+            std::thread::spawn(move || match evaluate_schema(state.flake_lock.as_deref()) {
+                Ok(schema) => {
+                    println!("Evaluated the options schema of the build flake");
+                    state.set_schema(schema);
+                }
+                Err(e) => println!("Failed to evaluate the options schema: {e:#}"),
+            });
+        }
+    }
+
+    // Periodically remove builds that fall outside the retention limits.
+    let policy = RetentionPolicy {
+        max_age: matches
+            .get_one::<u64>("max-age-days")
+            .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        max_total_size: matches
+            .get_one::<u64>("max-total-size-mb")
+            .map(|mb| mb * 1024 * 1024),
+        keep_per_host: matches.get_one::<usize>("keep-per-host").copied(),
+    };
+    if policy.is_enabled() {
+        let interval =
+            Duration::from_secs(matches.get_one::<u64>("gc-interval-minutes").unwrap() * 60);
+        let state = app_state.clone();
+        std::thread::spawn(move || loop {
+            match collect_garbage(&state.workspace, &state.jobs, &policy, unix_time()) {
+                Ok(removed) if !removed.is_empty() => {
+                    println!("Removed expired builds: {}", removed.join(", "));
+                }
+                Ok(_) => {}
+                Err(e) => println!("Failed to collect garbage: {e:#}"),
+            }
+            std::thread::sleep(interval);
+        });
+    }
+
     HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
             .wrap(Cors::permissive())
             .route("/", web::get().to(health_check))
             .route("/nixosConfig", web::post().to(nixos_config))
+            .route("/clusters", web::post().to(cluster_config))
+            .route("/targets", web::get().to(list_targets))
+            .route("/timezones", web::get().to(list_timezones))
+            .route("/schema", web::get().to(json_schema))
+            .route("/migrate", web::post().to(migrate_config))
+            .route("/diff", web::post().to(diff_config))
+            .route("/parse-nix", web::post().to(parse_nix_config))
             .service(
-                Files::new(
-                    "/builds",
-                    app_state.workspace.base_dir.path().join("builds"),
-                )
-                .prefer_utf8(true)
-                .show_files_listing(),
+                web::resource("/import")
+                    .app_data(web::PayloadConfig::new(MAX_TARBALL_SIZE))
+                    .route(web::post().to(import_config)),
+            )
+            .route("/signing-key", web::get().to(signing_public_key))
+            .route("/builds", web::get().to(list_builds))
+            .route("/builds/{id}", web::get().to(build_status))
+            .route("/builds/{id}", web::delete().to(cancel_build))
+            .route("/builds/{id}/cancel", web::post().to(cancel_build))
+            .route("/builds/{id}/status", web::get().to(build_status))
+            .route("/builds/{id}/logs", web::get().to(build_logs))
+            .service(
+                Files::new("/builds", app_state.workspace.base_dir.join("builds"))
+                    .prefer_utf8(true)
+                    .show_files_listing(),
             )
     })
     .bind(addr.to_string() + ":" + port)?
@@ -0,0 +1,171 @@
+use crate::validation::{push_pointer, ValidationError};
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+/// A step that upgrades config documents from one version to the next.
+struct Migration {
+    /// The version that the step upgrades from; it produces version `from + 1`.
+    from: u64,
+    apply: fn(&mut Map<String, Value>, &mut Vec<Change>),
+}
+
+/// The migrations, in order. Documents without a `version` are version 0.
+const MIGRATIONS: &[Migration] = &[Migration {
+    from: 0,
+    apply: rename_mount_unit_keys,
+}];
+
+/// The version of the config format that [`crate::schema_types::Config`] describes.
+pub const CURRENT_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// One change made to a document while upgrading it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// JSON pointer to the changed value in the upgraded document.
+    pub pointer: String,
+    /// Human-readable description of the change.
+    pub message: String,
+}
+
+/// What upgrading a document did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Migrated {
+    pub from_version: u64,
+    pub to_version: u64,
+    pub changes: Vec<Change>,
+}
+
+impl Migrated {
+    /// Whether the document was already current.
+    #[must_use]
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Upgrades a config document to [`CURRENT_VERSION`] in place, reporting every change.
+///
+/// # Errors
+///
+/// Returns a problem pointing at `/version` if the document is not an object, has a version
+/// that is not a non-negative integer, or is newer than this backend understands.
+pub fn migrate(document: &mut Value) -> Result<Migrated, ValidationError> {
+    let Some(object) = document.as_object_mut() else {
+        return Err(ValidationError::new(
+            "",
+            "invalid_type",
+            "The config must be a JSON object",
+        ));
+    };
+    let from_version = match object.get("version") {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| {
+            ValidationError::new(
+                "/version",
+                "invalid_version",
+                &format!("The 'version' must be a non-negative integer, but is {version}"),
+            )
+        })?,
+    };
+    if from_version > CURRENT_VERSION {
+        return Err(ValidationError::new(
+            "/version",
+            "unsupported_version",
+            &format!(
+                "The config has version {from_version}, but this backend supports up to version \
+                 {CURRENT_VERSION}"
+            ),
+        ));
+    }
+
+    let mut changes = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.from >= from_version) {
+        (migration.apply)(object, &mut changes);
+    }
+    if from_version < CURRENT_VERSION {
+        object.insert("version".to_string(), Value::from(CURRENT_VERSION));
+        changes.push(Change {
+            pointer: "/version".to_string(),
+            message: format!("Upgraded from version {from_version} to {CURRENT_VERSION}"),
+        });
+    }
+    Ok(Migrated {
+        from_version,
+        to_version: CURRENT_VERSION,
+        changes,
+    })
+}
+
+/// Upgrades the config document in a file, rewriting the file if anything changed and `write`
+/// is set.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, parsed, upgraded or written.
+pub fn migrate_file(path: &Path, write: bool) -> Result<Migrated> {
+    let json = fs::read_to_string(path).with_context(|| format!("Failed to read {path:?}"))?;
+    let mut document: Value =
+        serde_json::from_str(&json).with_context(|| format!("Failed to parse {path:?}"))?;
+    let migrated = migrate(&mut document).map_err(|e| anyhow!("{path:?}: {}", e.message))?;
+    if write && !migrated.is_unchanged() {
+        let json = serde_json::to_string_pretty(&document)
+            .with_context(|| format!("Failed to serialize {path:?}"))?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, json + "\n")
+            .with_context(|| format!("Failed to write {tmp_path:?}"))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to move {tmp_path:?} to {path:?}"))?;
+    }
+    Ok(migrated)
+}
+
+/// Version 0 to 1: mount keys that were spelled in snake case take the names of the NixOS
+/// `systemd.mounts` options, which the module passes them on to. A `unit_config` of
+/// `Key=Value` lines becomes the set of [Unit] entries that `unitConfig` takes.
+fn rename_mount_unit_keys(document: &mut Map<String, Value>, changes: &mut Vec<Change>) {
+    let Some(mounts) = document.get_mut("mounts").and_then(Value::as_object_mut) else {
+        return;
+    };
+    for (name, mount) in mounts.iter_mut() {
+        let Some(mount) = mount.as_object_mut() else {
+            continue;
+        };
+        for (old, new) in [("unit_config", "unitConfig"), ("part_of", "partOf")] {
+            // A document that already has both keys is left for validation to reject.
+            if mount.contains_key(new) {
+                continue;
+            }
+            if let Some(value) = mount.remove(old) {
+                let mut message = format!("Renamed 'mounts.{name}.{old}' to 'mounts.{name}.{new}'");
+                let value = match value.as_str().and_then(unit_entries) {
+                    Some(entries) if new == "unitConfig" => {
+                        message.push_str(", one entry per line");
+                        entries
+                    }
+                    _ => value,
+                };
+                mount.insert(new.to_string(), value);
+                let pointer = push_pointer(&push_pointer("/mounts", name), new);
+                changes.push(Change { pointer, message });
+            }
+        }
+    }
+}
+
+/// Reads `Key=Value` lines, ignoring blank ones, as an object. Returns `None` if a line is not
+/// an entry, leaving the value for validation to reject.
+fn unit_entries(text: &str) -> Option<Value> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            (!key.is_empty()).then(|| (key.to_string(), Value::from(value.trim())))
+        })
+        .collect::<Option<Map<String, Value>>>()
+        .map(Value::Object)
+}
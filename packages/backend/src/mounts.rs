@@ -0,0 +1,255 @@
+use crate::schema_types::Mount;
+use crate::validation::{push_pointer, ValidationError};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// File systems that take no device, so any `what` is accepted.
+const VIRTUAL_FS_TYPES: &[&str] = &[
+    "tmpfs", "ramfs", "overlay", "proc", "sysfs", "devtmpfs", "none",
+];
+
+/// File systems whose `what` is a remote share such as `host:/export` or `//host/share`.
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "sshfs",
+    "9p",
+    "virtiofs",
+    "glusterfs",
+    "ceph",
+];
+
+/// Block device file systems that NixOS can mount.
+const BLOCK_FS_TYPES: &[&str] = &[
+    "auto", "ext2", "ext3", "ext4", "xfs", "btrfs", "bcachefs", "f2fs", "zfs", "vfat", "exfat",
+    "ntfs", "ntfs3", "iso9660", "udf", "squashfs", "erofs",
+];
+
+/// Prefixes of `what` that name a block device by its attributes.
+const DEVICE_TAGS: &[&str] = &["UUID=", "LABEL=", "PARTUUID=", "PARTLABEL=", "ID="];
+
+/// Targets that every NixOS system has; see systemd.special(7).
+const SPECIAL_TARGETS: &[&str] = &[
+    "basic.target",
+    "default.target",
+    "emergency.target",
+    "getty.target",
+    "graphical.target",
+    "local-fs-pre.target",
+    "local-fs.target",
+    "multi-user.target",
+    "network-online.target",
+    "network-pre.target",
+    "network.target",
+    "nss-lookup.target",
+    "nss-user-lookup.target",
+    "remote-fs-pre.target",
+    "remote-fs.target",
+    "rescue.target",
+    "shutdown.target",
+    "sockets.target",
+    "swap.target",
+    "sysinit.target",
+    "time-sync.target",
+    "timers.target",
+    "umount.target",
+];
+
+/// Services of the NixOS base system that the homestakeros module does not define.
+const BASE_SERVICES: &[&str] = &[
+    "firewall.service",
+    "nix-daemon.service",
+    "sshd.service",
+    "systemd-networkd.service",
+    "systemd-resolved.service",
+    "systemd-timesyncd.service",
+];
+
+/// Escapes a path the way `systemd-escape --path` does, e.g. `/mnt/eth-data` to
+/// `mnt-eth\x2ddata`.
+#[must_use]
+pub fn escape_path(path: &str) -> String {
+    let trimmed: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    if trimmed.is_empty() {
+        return "-".to_string();
+    }
+    let mut escaped = String::new();
+    for (i, byte) in trimmed.join("/").bytes().enumerate() {
+        match byte {
+            b'/' => escaped.push('-'),
+            b'.' if i == 0 => escaped.push_str("\\x2e"),
+            b if b.is_ascii_alphanumeric() || matches!(b, b':' | b'_' | b'.') => {
+                escaped.push(char::from(b));
+            }
+            b => {
+                let _ = write!(escaped, "\\x{b:02x}");
+            }
+        }
+    }
+    escaped
+}
+
+/// The name of the systemd mount unit for a mount point, e.g. `mnt-eth.mount`.
+#[must_use]
+pub fn mount_unit_name(mount_point: &str) -> String {
+    format!("{}.mount", escape_path(mount_point))
+}
+
+/// Checks the enabled mounts, one problem per offending value.
+///
+/// `services` are the service units that the generated system defines besides those of the
+/// base system; dependencies on other services, targets or mounts are reported as unknown
+/// units. Dependencies on other kinds of units, such as devices, are not checked.
+#[must_use]
+pub fn mount_errors(
+    mounts: Option<&HashMap<String, Mount>>,
+    services: &[String],
+) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let Some(mounts) = mounts else {
+        return errors;
+    };
+    let mut enabled: Vec<(&String, &Mount)> = mounts.iter().filter(|(_, m)| m.enable).collect();
+    enabled.sort_by_key(|(name, _)| *name);
+
+    let mount_units: Vec<String> = enabled
+        .iter()
+        .map(|(_, m)| mount_unit_name(&m.mount_point))
+        .collect();
+    let known_unit = |unit: &str| {
+        let checked = [".service", ".target", ".mount"]
+            .iter()
+            .any(|suffix| unit.ends_with(suffix));
+        !checked
+            || SPECIAL_TARGETS.contains(&unit)
+            || BASE_SERVICES.contains(&unit)
+            || services.iter().any(|s| s == unit)
+            || mount_units.iter().any(|m| m == unit)
+    };
+
+    let mut mount_points: HashMap<String, &str> = HashMap::new();
+    for (name, mount) in enabled {
+        let pointer = push_pointer("/mounts", name);
+        let path = format!("mounts.{name}");
+
+        if mount.mount_point.starts_with('/') {
+            // Escaping also normalizes, so `/mnt/eth/` and `/mnt//eth` are the same point.
+            if let Some(other) = mount_points.insert(escape_path(&mount.mount_point), name) {
+                errors.push(ValidationError::new(
+                    &push_pointer(&pointer, "where"),
+                    "duplicate_mount_point",
+                    &format!(
+                        "The '{path}.where' '{}' is also the mount point of 'mounts.{other}'",
+                        mount.mount_point
+                    ),
+                ));
+            }
+        } else {
+            errors.push(ValidationError::new(
+                &push_pointer(&pointer, "where"),
+                "relative_mount_point",
+                &format!(
+                    "The '{path}.where' '{}' must be an absolute path",
+                    mount.mount_point
+                ),
+            ));
+        }
+
+        let fs_type = mount.mount_type.as_str();
+        let known_type = VIRTUAL_FS_TYPES.contains(&fs_type)
+            || NETWORK_FS_TYPES.contains(&fs_type)
+            || BLOCK_FS_TYPES.contains(&fs_type)
+            || fs_type.starts_with("fuse.");
+        if !known_type {
+            errors.push(ValidationError::new(
+                &push_pointer(&pointer, "type"),
+                "unknown_fs_type",
+                &format!("The '{path}.type' '{fs_type}' is not a known file system type"),
+            ));
+        }
+
+        if let Some(problem) = source_problem(fs_type, &mount.what) {
+            errors.push(ValidationError::new(
+                &push_pointer(&pointer, "what"),
+                "invalid_mount_source",
+                &format!("The '{path}.what' '{}' {problem}", mount.what),
+            ));
+        }
+
+        if let Some(unit_name) = &mount.name {
+            let expected = mount_unit_name(&mount.mount_point);
+            if *unit_name != expected {
+                errors.push(ValidationError::new(
+                    &push_pointer(&pointer, "name"),
+                    "mount_unit_name_mismatch",
+                    &format!(
+                        "The '{path}.name' '{unit_name}' does not match the mount point '{}', \
+                         which systemd requires to be named '{expected}'",
+                        mount.mount_point
+                    ),
+                ));
+            }
+        }
+
+        for (key, units) in dependencies(mount) {
+            for (i, unit) in units.iter().enumerate() {
+                if !known_unit(unit) {
+                    errors.push(ValidationError::new(
+                        &push_pointer(&push_pointer(&pointer, key), &i.to_string()),
+                        "unknown_unit",
+                        &format!(
+                            "The '{path}.{key}' refers to '{unit}', which is not a unit of the \
+                             generated system"
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    errors
+}
+
+/// Why `what` cannot be mounted as a file system of type `fs_type`, if it cannot.
+fn source_problem(fs_type: &str, what: &str) -> Option<&'static str> {
+    if VIRTUAL_FS_TYPES.contains(&fs_type) {
+        return None;
+    }
+    if what.trim().is_empty() {
+        return Some("must not be empty");
+    }
+    if NETWORK_FS_TYPES.contains(&fs_type) || fs_type.starts_with("fuse.") {
+        return None;
+    }
+    if let Some(tag) = DEVICE_TAGS.iter().find(|tag| what.starts_with(**tag)) {
+        return (what.len() == tag.len()).then_some("names a device by an empty value");
+    }
+    if what.starts_with('/') {
+        return None;
+    }
+    Some("must be a device path, or a UUID=, LABEL=, PARTUUID= or PARTLABEL= device tag")
+}
+
+/// The unit lists of a mount, with the key they are set under.
+fn dependencies(mount: &Mount) -> Vec<(&'static str, &Vec<String>)> {
+    [
+        ("wantedBy", &mount.wanted_by),
+        ("before", &mount.before),
+        ("after", &mount.after),
+        ("wants", &mount.wants),
+        ("requires", &mount.requires),
+        ("requiredBy", &mount.required_by),
+        ("requisite", &mount.requisite),
+        ("bindsTo", &mount.binds_to),
+        ("partOf", &mount.part_of),
+        ("upholds", &mount.upholds),
+        ("upheldBy", &mount.upheld_by),
+        ("conflicts", &mount.conflicts),
+        ("onSuccess", &mount.on_success),
+        ("onFailure", &mount.on_failure),
+    ]
+    .into_iter()
+    .filter_map(|(key, units)| Some((key, units.as_ref()?)))
+    .collect()
+}
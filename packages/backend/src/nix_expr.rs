@@ -0,0 +1,175 @@
+use serde_json::{Map, Number, Value};
+
+/// Words that cannot be used as bare attribute names.
+const KEYWORDS: &[&str] = &[
+    "assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with",
+];
+
+/// Renders a JSON value as a Nix expression.
+///
+/// The output mirrors what `nix-instantiate --eval` prints for `builtins.fromJSON`: attribute sets
+/// are written with their keys in sorted order, and strings are escaped so that quotes,
+/// backslashes and `${` are never interpreted by Nix.
+#[must_use]
+pub fn to_nix(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+/// Renders a JSON value as a Nix expression laid out with one attribute or list item per line,
+/// indented by two spaces.
+///
+/// Apart from the layout, the output is that of [`to_nix`]. Strings never span lines, so the
+/// generated files can be compared line by line.
+#[must_use]
+pub fn to_nix_pretty(value: &Value) -> String {
+    let mut out = String::new();
+    write_pretty(&mut out, value, 0);
+    out
+}
+
+fn write_pretty(out: &mut String, value: &Value, depth: usize) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.push_str("[\n");
+            for item in items {
+                indent(out, depth + 1);
+                if is_negative(item) {
+                    out.push('(');
+                    write_value(out, item);
+                    out.push(')');
+                } else {
+                    write_pretty(out, item, depth + 1);
+                }
+                out.push('\n');
+            }
+            indent(out, depth);
+            out.push(']');
+        }
+        Value::Object(attrs) if !attrs.is_empty() => {
+            out.push_str("{\n");
+            for key in sorted_keys(attrs) {
+                indent(out, depth + 1);
+                write_attr_name(out, key);
+                out.push_str(" = ");
+                write_pretty(out, &attrs[key], depth + 1);
+                out.push_str(";\n");
+            }
+            indent(out, depth);
+            out.push('}');
+        }
+        _ => write_value(out, value),
+    }
+}
+
+fn indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&format_number(n)),
+        Value::String(s) => write_string(out, s),
+        Value::Array(items) => write_list(out, items),
+        Value::Object(attrs) => write_attrs(out, attrs),
+    }
+}
+
+fn write_list(out: &mut String, items: &[Value]) {
+    out.push('[');
+    for item in items {
+        out.push(' ');
+        let negative = is_negative(item);
+        if negative {
+            out.push('(');
+        }
+        write_value(out, item);
+        if negative {
+            out.push(')');
+        }
+    }
+    out.push_str(" ]");
+}
+
+/// Whether a list item must be parenthesized.
+///
+/// A leading minus would otherwise be parsed as a subtraction between list elements.
+fn is_negative(item: &Value) -> bool {
+    matches!(item, Value::Number(n) if format_number(n).starts_with('-'))
+}
+
+fn write_attrs(out: &mut String, attrs: &Map<String, Value>) {
+    out.push('{');
+    for key in sorted_keys(attrs) {
+        out.push(' ');
+        write_attr_name(out, key);
+        out.push_str(" = ");
+        write_value(out, &attrs[key]);
+        out.push(';');
+    }
+    out.push_str(" }");
+}
+
+/// Sorts explicitly so the output does not depend on how serde_json orders its maps.
+fn sorted_keys(attrs: &Map<String, Value>) -> Vec<&String> {
+    let mut keys: Vec<&String> = attrs.keys().collect();
+    keys.sort();
+    keys
+}
+
+fn write_attr_name(out: &mut String, name: &str) {
+    if is_identifier(name) {
+        out.push_str(name);
+    } else {
+        write_string(out, name);
+    }
+}
+
+/// Whether `name` can be written as a bare attribute name.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+    (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'))
+        && !KEYWORDS.contains(&name)
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn format_number(n: &Number) -> String {
+    if n.is_f64() {
+        // Nix float literals need a fractional part, e.g. `1.0` or `1.0e+20`.
+        let s = n.to_string();
+        if s.contains('.') {
+            s
+        } else if let Some(pos) = s.find(['e', 'E']) {
+            format!("{}.0{}", &s[..pos], &s[pos..])
+        } else {
+            s + ".0"
+        }
+    } else {
+        n.to_string()
+    }
+}
@@ -0,0 +1,657 @@
+use crate::options_schema::OptionsSchema;
+use crate::schema_types::Config;
+use crate::validation::{parse_config, ValidationError};
+use serde_json::{Map, Number, Value};
+use std::fmt;
+
+/// Operators that may follow a value, longest first; any of them means the expression has to
+/// be evaluated.
+const OPERATORS: &[&str] = &[
+    "//", "++", "==", "!=", "<=", ">=", "&&", "||", "->", "+", "-", "*", "/", "<", ">", "?",
+];
+
+/// How deeply attribute sets, lists and parentheses may be nested, as in `serde_json`, so that
+/// the recursive descent cannot overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A Nix expression that cannot be read as a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NixError {
+    /// 1-based line of the offending construct.
+    pub line: usize,
+    /// 1-based column of the offending construct, in characters.
+    pub column: usize,
+    pub message: String,
+    /// Whether the expression is valid Nix that only evaluation can turn into a value, rather
+    /// than a syntax error.
+    pub needs_evaluation: bool,
+}
+
+impl fmt::Display for NixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for NixError {}
+
+/// Reads a Nix expression made of attribute sets, lists, strings, numbers, booleans and `null`
+/// as JSON, the inverse of [`crate::nix_expr::to_nix`].
+///
+/// Hand-written expressions may also use comments, indented strings (`''...''`), dotted
+/// attribute paths such as `consensus.lighthouse.enable = true;` and parentheses. Anything
+/// that only evaluation can resolve, such as variables, function calls, `let`, `with`, `rec`,
+/// `inherit`, operators, paths or string interpolation, is rejected.
+///
+/// # Errors
+///
+/// Returns the first construct that cannot be read, with its position, including values nested
+/// more than 128 levels deep.
+pub fn from_nix(src: &str) -> Result<Value, NixError> {
+    let mut parser = Parser::new(src);
+    let value = parser.expr()?;
+    parser.end()?;
+    Ok(value)
+}
+
+/// Reads the `homestakeros` options of a host's `default.nix`, as written by
+/// [`crate::write_default_nix`] or edited by hand, and parses them like a submitted config.
+///
+/// The file must be a module, optionally a function of its arguments such as
+/// `{ pkgs, lib, config, ... }:`, whose attribute set is readable by [`from_nix`] as a whole.
+///
+/// # Errors
+///
+/// Returns the problems found: `invalid_nix` for a syntax error, `needs_evaluation` for a
+/// construct that only evaluation can resolve, `required` if the module does not set
+/// `homestakeros`, or the problems of the config itself.
+pub fn parse_default_nix(
+    src: &str,
+    schema: Option<&OptionsSchema>,
+) -> Result<Config, Vec<ValidationError>> {
+    let module = read_module(src).map_err(|e| {
+        let code = if e.needs_evaluation {
+            "needs_evaluation"
+        } else {
+            "invalid_nix"
+        };
+        vec![ValidationError::new("", code, &e.to_string())]
+    })?;
+    let Some(options) = module.get("homestakeros") else {
+        return Err(vec![ValidationError::new(
+            "",
+            "required",
+            "The module does not set 'homestakeros'",
+        )]);
+    };
+    parse_config(&options.to_string(), schema)
+}
+
+/// Reads the attribute set of a module, skipping the arguments it takes.
+fn read_module(src: &str) -> Result<Map<String, Value>, NixError> {
+    let mut parser = Parser::new(src);
+    parser.function_headers()?;
+    parser.skip_trivia()?;
+    let start = parser.pos;
+    let body = parser.expr()?;
+    parser.end()?;
+    match body {
+        Value::Object(attrs) => Ok(attrs),
+        _ => Err(parser.error_at(start, "The module must be an attribute set", false)),
+    }
+}
+
+/// A recursive descent parser over the source, which reports errors at byte offsets.
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Number of enclosing attribute sets, lists and parentheses, counting each component of
+    /// a dotted attribute path as an attribute set.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(src: &'a str) -> Self {
+        Parser {
+            src,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    /// Enters `levels` of nesting at `start`, failing beyond [`MAX_DEPTH`].
+    fn nest(&mut self, start: usize, levels: usize) -> Result<(), NixError> {
+        self.depth += levels;
+        if self.depth > MAX_DEPTH {
+            let message = format!("The expression is nested more than {MAX_DEPTH} levels deep");
+            return Err(self.error_at(start, &message, false));
+        }
+        Ok(())
+    }
+
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error_at(&self, pos: usize, message: &str, needs_evaluation: bool) -> NixError {
+        let before = &self.src[..pos];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        NixError {
+            line,
+            column: before[line_start..].chars().count() + 1,
+            message: message.to_string(),
+            needs_evaluation,
+        }
+    }
+
+    /// An error for the construct at `pos`, which only evaluation can resolve.
+    fn unsupported(&self, pos: usize, message: &str) -> NixError {
+        self.error_at(pos, message, true)
+    }
+
+    /// An error for whatever is at the current position instead of `expected`.
+    fn unexpected(&self, expected: &str) -> NixError {
+        let rest = self.rest();
+        if rest.starts_with('.') && !rest.starts_with("...") {
+            return self.unsupported(self.pos, "Selecting attributes needs evaluation");
+        }
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            return self.unsupported(self.pos, &format!("The operator '{op}' needs evaluation"));
+        }
+        let message = match self.peek() {
+            None => format!("Unexpected end of input, expected {expected}"),
+            Some(c) => format!("Unexpected '{c}', expected {expected}"),
+        };
+        self.error_at(self.pos, &message, false)
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), NixError> {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with(char::is_whitespace) {
+                self.bump();
+            } else if rest.starts_with('#') {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if rest.starts_with("/*") {
+                let Some(end) = rest.find("*/") else {
+                    return Err(self.error_at(self.pos, "Unterminated comment", false));
+                };
+                self.pos += end + 2;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn expect(&mut self, c: char, expected: &str) -> Result<(), NixError> {
+        self.skip_trivia()?;
+        if self.peek() == Some(c) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn end(&mut self) -> Result<(), NixError> {
+        self.skip_trivia()?;
+        if self.pos < self.src.len() {
+            return Err(self.unexpected("the end of the expression"));
+        }
+        Ok(())
+    }
+
+    /// Skips the function headers of a module, e.g. `{ pkgs, lib, config, ... }:` or `args:`,
+    /// as the options cannot depend on the arguments anyway.
+    fn function_headers(&mut self) -> Result<(), NixError> {
+        loop {
+            self.skip_trivia()?;
+            let start = self.pos;
+            if self.formals()? {
+                continue;
+            }
+            if self.peek().is_some_and(is_ident_start) {
+                self.ident();
+                self.skip_trivia()?;
+                match self.peek() {
+                    Some(':') => {
+                        self.bump();
+                        continue;
+                    }
+                    Some('@') => {
+                        self.bump();
+                        self.skip_trivia()?;
+                        if self.formals()? {
+                            continue;
+                        }
+                        return Err(self.unexpected("a set of function arguments"));
+                    }
+                    _ => {}
+                }
+            }
+            self.pos = start;
+            return Ok(());
+        }
+    }
+
+    /// Skips a set of function arguments, e.g. `{ pkgs, lib ? null, ... } @ args:`, if there is
+    /// one at the current position. Returns whether there was.
+    fn formals(&mut self) -> Result<bool, NixError> {
+        let start = self.pos;
+        if self.peek() != Some('{') {
+            return Ok(false);
+        }
+        self.bump();
+        loop {
+            self.skip_trivia()?;
+            if self.rest().starts_with("...") {
+                self.pos += 3;
+                self.skip_trivia()?;
+            } else if self.peek().is_some_and(is_ident_start) {
+                self.ident();
+                self.skip_trivia()?;
+                if self.peek() == Some('?') {
+                    self.bump();
+                    self.expr()?;
+                    self.skip_trivia()?;
+                }
+            }
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some('}') => {
+                    self.bump();
+                    break;
+                }
+                _ => {
+                    self.pos = start;
+                    return Ok(false);
+                }
+            }
+        }
+        self.skip_trivia()?;
+        if self.peek() == Some('@') {
+            self.bump();
+            self.skip_trivia()?;
+            if !self.peek().is_some_and(is_ident_start) {
+                return Err(self.unexpected("an argument name"));
+            }
+            self.ident();
+            self.skip_trivia()?;
+        }
+        if self.peek() == Some(':') {
+            self.bump();
+            Ok(true)
+        } else {
+            self.pos = start;
+            Ok(false)
+        }
+    }
+
+    /// A value, or a negated number.
+    fn expr(&mut self) -> Result<Value, NixError> {
+        self.skip_trivia()?;
+        if self.peek() != Some('-') {
+            return self.value();
+        }
+        let start = self.pos;
+        self.bump();
+        self.skip_trivia()?;
+        if self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.number(true)
+        } else {
+            Err(self.unsupported(start, "Negation needs evaluation"))
+        }
+    }
+
+    /// A value that can stand on its own in a list.
+    fn value(&mut self) -> Result<Value, NixError> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        let Some(c) = self.peek() else {
+            return Err(self.unexpected("a value"));
+        };
+        match c {
+            '{' | '[' | '(' => {
+                self.nest(start, 1)?;
+                let value = match c {
+                    '{' => self.attrs()?,
+                    '[' => self.list()?,
+                    _ => {
+                        self.bump();
+                        let value = self.expr()?;
+                        self.expect(')', "')'")?;
+                        value
+                    }
+                };
+                self.depth -= 1;
+                Ok(value)
+            }
+            '"' => self.string().map(Value::String),
+            '\'' if self.rest().starts_with("''") => self.indented_string().map(Value::String),
+            '0'..='9' => self.number(false),
+            '.' | '/' | '~' | '<' => Err(self.unsupported(start, "Paths need evaluation")),
+            c if is_ident_start(c) => {
+                let ident = self.ident();
+                match ident {
+                    "true" => return Ok(Value::Bool(true)),
+                    "false" => return Ok(Value::Bool(false)),
+                    "null" => return Ok(Value::Null),
+                    "rec" => {
+                        let message = "Recursive attribute sets need evaluation";
+                        return Err(self.unsupported(start, message));
+                    }
+                    "let" | "with" | "if" | "assert" => {
+                        let message = format!("'{ident}' expressions need evaluation");
+                        return Err(self.unsupported(start, &message));
+                    }
+                    _ => {}
+                }
+                self.skip_trivia()?;
+                let message = if matches!(self.peek(), Some(':' | '@')) {
+                    "Functions need evaluation".to_string()
+                } else {
+                    format!(
+                        "'{ident}' needs evaluation; only attribute sets, lists, strings, \
+                         numbers, booleans and null can be read"
+                    )
+                };
+                Err(self.unsupported(start, &message))
+            }
+            _ => Err(self.unexpected("a value")),
+        }
+    }
+
+    fn attrs(&mut self) -> Result<Value, NixError> {
+        self.bump();
+        let mut attrs = Map::new();
+        loop {
+            self.skip_trivia()?;
+            match self.peek() {
+                Some('}') => {
+                    self.bump();
+                    return Ok(Value::Object(attrs));
+                }
+                None => return Err(self.unexpected("'}'")),
+                _ => {}
+            }
+            let start = self.pos;
+            if self.peek().is_some_and(is_ident_start) && self.ident() == "inherit" {
+                return Err(self.unsupported(start, "'inherit' needs evaluation"));
+            }
+            self.pos = start;
+            let path = self.attr_path()?;
+            self.expect('=', "'='")?;
+            let levels = path.len() - 1;
+            self.nest(start, levels)?;
+            let value = self.expr()?;
+            self.depth -= levels;
+            self.expect(';', "';'")?;
+            if !insert(&mut attrs, &path, value) {
+                let message = format!(
+                    "The attribute '{}' is defined more than once",
+                    path.join(".")
+                );
+                return Err(self.error_at(start, &message, false));
+            }
+        }
+    }
+
+    fn attr_path(&mut self) -> Result<Vec<String>, NixError> {
+        let mut path = vec![self.attr_name()?];
+        loop {
+            self.skip_trivia()?;
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.bump();
+            path.push(self.attr_name()?);
+        }
+    }
+
+    fn attr_name(&mut self) -> Result<String, NixError> {
+        self.skip_trivia()?;
+        match self.peek() {
+            Some('"') => self.string(),
+            Some('$') if self.rest().starts_with("${") => {
+                Err(self.unsupported(self.pos, "Dynamic attribute names need evaluation"))
+            }
+            Some(c) if is_ident_start(c) => Ok(self.ident().to_string()),
+            _ => Err(self.unexpected("an attribute name")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Value, NixError> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(']') => {
+                    self.bump();
+                    return Ok(Value::Array(items));
+                }
+                None => return Err(self.unexpected("']'")),
+                Some('-') if !self.rest().starts_with("->") => {
+                    let message = "A negative number in a list must be parenthesized, e.g. '(-1)'";
+                    return Err(self.error_at(self.pos, message, false));
+                }
+                _ => items.push(self.value()?),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, NixError> {
+        let start = self.pos;
+        self.bump();
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.bump() else {
+                return Err(self.error_at(start, "Unterminated string", false));
+            };
+            match c {
+                '"' => return Ok(out),
+                '\\' => match self.bump() {
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some(c) => out.push(c),
+                    None => return Err(self.error_at(start, "Unterminated string", false)),
+                },
+                '$' if self.peek() == Some('$') => {
+                    self.bump();
+                    out.push_str("$$");
+                }
+                '$' if self.peek() == Some('{') => {
+                    let message = "String interpolation needs evaluation";
+                    return Err(self.unsupported(self.pos - 1, message));
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// An indented string, `''...''`, with its common indentation removed as Nix does.
+    fn indented_string(&mut self) -> Result<String, NixError> {
+        let start = self.pos;
+        self.pos += 2;
+        let content_start = self.pos;
+        let raw = loop {
+            let rest = self.rest();
+            if rest.starts_with("'''") || rest.starts_with("''$") {
+                self.pos += 3;
+            } else if rest.starts_with("''\\") {
+                self.pos += 3;
+                self.bump();
+            } else if rest.starts_with("''") {
+                let raw = &self.src[content_start..self.pos];
+                self.pos += 2;
+                break raw;
+            } else if rest.starts_with("$$") {
+                self.pos += 2;
+            } else if rest.starts_with("${") {
+                let message = "String interpolation needs evaluation";
+                return Err(self.unsupported(self.pos, message));
+            } else if self.bump().is_none() {
+                return Err(self.error_at(start, "Unterminated string", false));
+            }
+        };
+        Ok(unescape_indented(&strip_indentation(raw)))
+    }
+
+    fn number(&mut self, negative: bool) -> Result<Value, NixError> {
+        let start = self.pos;
+        let digits = |parser: &mut Self| {
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.bump();
+            }
+        };
+        digits(self);
+        let mut float = false;
+        let rest = self.rest();
+        if rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            float = true;
+            self.bump();
+            digits(self);
+        }
+        if self.peek().is_some_and(|c| c == 'e' || c == 'E') {
+            float = true;
+            self.bump();
+            if self.peek().is_some_and(|c| c == '+' || c == '-') {
+                self.bump();
+            }
+            digits(self);
+        }
+        let text = format!(
+            "{}{}",
+            if negative { "-" } else { "" },
+            &self.src[start..self.pos]
+        );
+        let number = if float {
+            text.parse::<f64>().ok().and_then(Number::from_f64)
+        } else {
+            text.parse::<i64>().ok().map(Number::from)
+        };
+        number
+            .map(Value::Number)
+            .ok_or_else(|| self.error_at(start, &format!("Invalid number '{text}'"), false))
+    }
+
+    fn ident(&mut self) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(is_ident_char) {
+            self.bump();
+        }
+        &self.src[start..self.pos]
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-')
+}
+
+/// Sets the attribute at `path`, merging attribute sets that are defined in several places as
+/// Nix does. Returns false if the attribute is already defined.
+fn insert(attrs: &mut Map<String, Value>, path: &[String], value: Value) -> bool {
+    let Some((name, rest)) = path.split_first() else {
+        return false;
+    };
+    if !rest.is_empty() {
+        let nested = attrs
+            .entry(name.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        return match nested {
+            Value::Object(nested) => insert(nested, rest, value),
+            _ => false,
+        };
+    }
+    match (attrs.get_mut(name), value) {
+        (None, value) => {
+            attrs.insert(name.clone(), value);
+            true
+        }
+        (Some(Value::Object(existing)), Value::Object(new)) => new
+            .into_iter()
+            .all(|(key, value)| insert(existing, &[key], value)),
+        _ => false,
+    }
+}
+
+/// Removes the indentation that all lines of an indented string share, ignoring lines of only
+/// spaces, and the first line if it is empty.
+fn strip_indentation(raw: &str) -> String {
+    let indentation = |line: &str| line.len() - line.trim_start_matches(' ').len();
+    let mut lines: Vec<&str> = raw.split('\n').collect();
+    let indent = lines
+        .iter()
+        .filter(|line| !line.trim_start_matches(' ').is_empty())
+        .map(|line| indentation(line))
+        .min()
+        .unwrap_or(0);
+    if lines.len() > 1 && lines[0].trim_start_matches(' ').is_empty() {
+        lines.remove(0);
+    }
+    let last = lines.len() - 1;
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == last && line.trim_start_matches(' ').is_empty() {
+                ""
+            } else {
+                &line[indentation(line).min(indent)..]
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+fn unescape_indented(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find("''") {
+        out.push_str(&rest[..i]);
+        let mut chars = rest[i + 2..].chars();
+        match chars.next() {
+            Some('\'') => out.push_str("''"),
+            Some('$') => out.push('$'),
+            Some('\\') => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some(c) => out.push(c),
+                None => {}
+            },
+            // The end of the string was found before, so any other `''` is kept as it is.
+            _ => {
+                out.push_str("''");
+                rest = &rest[i + 2..];
+                continue;
+            }
+        }
+        rest = chars.as_str();
+    }
+    out.push_str(rest);
+    out
+}
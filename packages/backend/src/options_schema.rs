@@ -0,0 +1,258 @@
+use crate::validation::{push_pointer, ValidationError};
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The type of a module option, read from the type names in the `.#schema` export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptionType {
+    Bool,
+    /// An integer, optionally with a lower bound such as for `ints.unsigned`.
+    Int {
+        min: Option<i64>,
+    },
+    Float,
+    Str {
+        non_empty: bool,
+    },
+    ListOf(Box<OptionType>),
+    AttrsOf(Box<OptionType>),
+    Submodule(BTreeMap<String, SchemaNode>),
+    /// A type whose values cannot be checked from its name, e.g. `enum` or `either`.
+    Any,
+}
+
+/// An entry of the schema: either an option or a set of nested entries.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaNode {
+    Option(OptionType),
+    Group(BTreeMap<String, SchemaNode>),
+}
+
+/// The options of the `homestakeros` NixOS module, as exported by `nix eval --json .#schema`.
+///
+/// Submitted configs are checked against it at runtime, so that options added to the module are
+/// accepted and type-checked without changes to [`crate::schema_types`]. The export unwraps
+/// `nullOr`, so `null` is accepted for every option and left for the module to reject.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionsSchema {
+    root: BTreeMap<String, SchemaNode>,
+}
+
+impl OptionsSchema {
+    /// Reads the schema from the JSON written by [`crate::update_schema`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or does not hold a schema.
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read schema from {path:?}"))?;
+        let value: Value = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse schema from {path:?}"))?;
+        Self::from_json(&value).with_context(|| format!("Invalid schema in {path:?}"))
+    }
+
+    /// Builds the schema from the `.#schema` JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON is not a set of options.
+    pub fn from_json(value: &Value) -> Result<Self> {
+        let Some(options) = value.as_object() else {
+            bail!("The schema must be an object of options");
+        };
+        Ok(OptionsSchema {
+            root: parse_group(options),
+        })
+    }
+
+    /// Checks a config against the schema, returning one problem per offending value.
+    ///
+    /// Unknown options are reported with the code `unknown_field`, values of the wrong type with
+    /// `invalid_type`, and values outside the range of their type with `invalid_value`. Missing
+    /// options are not reported, as the export does not tell which options have defaults.
+    #[must_use]
+    pub fn validate(&self, config: &Value) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        check_group(&self.root, config, "", "", &mut errors);
+        errors
+    }
+}
+
+fn parse_group(options: &Map<String, Value>) -> BTreeMap<String, SchemaNode> {
+    options
+        .iter()
+        .filter_map(|(name, entry)| Some((name.clone(), parse_node(entry.as_object()?))))
+        .collect()
+}
+
+fn parse_node(entry: &Map<String, Value>) -> SchemaNode {
+    // Options carry their type name as a string; an option named `type` inside a group is an
+    // object instead.
+    match entry.get("type").and_then(Value::as_str) {
+        Some(type_name) => {
+            let submodule = entry.get("options").and_then(Value::as_object);
+            SchemaNode::Option(parse_type(type_name, submodule))
+        }
+        None => SchemaNode::Group(parse_group(entry)),
+    }
+}
+
+/// Parses a type name such as `listOf (attrsOf (submodule))`. The submodule's options, if
+/// exported, belong to the innermost `submodule`.
+fn parse_type(name: &str, submodule: Option<&Map<String, Value>>) -> OptionType {
+    let name = name.trim();
+    let inner = |prefix: &str| {
+        name.strip_prefix(prefix)
+            .map(str::trim)
+            .and_then(|rest| rest.strip_prefix('('))
+            .and_then(|rest| rest.strip_suffix(')'))
+    };
+    if let Some(element) = inner("listOf") {
+        return OptionType::ListOf(Box::new(parse_type(element, submodule)));
+    }
+    if let Some(element) = inner("attrsOf") {
+        return OptionType::AttrsOf(Box::new(parse_type(element, submodule)));
+    }
+    match name {
+        "bool" => OptionType::Bool,
+        "int" | "intBetween" => OptionType::Int { min: None },
+        "unsignedInt" => OptionType::Int { min: Some(0) },
+        "positiveInt" => OptionType::Int { min: Some(1) },
+        "float" | "number" | "numberBetween" => OptionType::Float,
+        "str" | "path" | "separatedString" | "strMatching" | "passwdEntry" => {
+            OptionType::Str { non_empty: false }
+        }
+        "nonEmptyStr" => OptionType::Str { non_empty: true },
+        "attrs" => OptionType::AttrsOf(Box::new(OptionType::Any)),
+        "submodule" => match submodule {
+            Some(options) => OptionType::Submodule(parse_group(options)),
+            None => OptionType::Any,
+        },
+        // ints.u8 .. ints.u32 and ints.s8 .. ints.s32, including types.port.
+        _ if name.starts_with("unsignedInt") => OptionType::Int { min: Some(0) },
+        _ if name.starts_with("signedInt") => OptionType::Int { min: None },
+        _ => OptionType::Any,
+    }
+}
+
+fn check_group(
+    group: &BTreeMap<String, SchemaNode>,
+    value: &Value,
+    pointer: &str,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    let Some(object) = value.as_object() else {
+        if !value.is_null() {
+            errors.push(type_error(pointer, path, "an object", value));
+        }
+        return;
+    };
+    for (name, member) in object {
+        let pointer = push_pointer(pointer, name);
+        let path = join_path(path, name);
+        match group.get(name) {
+            Some(SchemaNode::Group(nested)) => check_group(nested, member, &pointer, &path, errors),
+            Some(SchemaNode::Option(option_type)) => {
+                check_value(option_type, member, &pointer, &path, errors);
+            }
+            None => errors.push(ValidationError::new(
+                &pointer,
+                "unknown_field",
+                &format!("The option '{path}' does not exist"),
+            )),
+        }
+    }
+}
+
+fn check_value(
+    option_type: &OptionType,
+    value: &Value,
+    pointer: &str,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    if value.is_null() {
+        return;
+    }
+    match option_type {
+        OptionType::Bool if !value.is_boolean() => {
+            errors.push(type_error(pointer, path, "a boolean", value));
+        }
+        OptionType::Int { .. } if !value.is_i64() && !value.is_u64() => {
+            errors.push(type_error(pointer, path, "an integer", value));
+        }
+        OptionType::Int { min: Some(min) } => {
+            if let Some(n) = value.as_i64().filter(|n| n < min) {
+                errors.push(ValidationError::new(
+                    pointer,
+                    "invalid_value",
+                    &format!("The option '{path}' must be at least {min}, but is {n}"),
+                ));
+            }
+        }
+        OptionType::Float if !value.is_number() => {
+            errors.push(type_error(pointer, path, "a number", value));
+        }
+        OptionType::Str { non_empty } => match value.as_str() {
+            None => errors.push(type_error(pointer, path, "a string", value)),
+            Some("") if *non_empty => errors.push(ValidationError::new(
+                pointer,
+                "invalid_value",
+                &format!("The option '{path}' must not be empty"),
+            )),
+            Some(_) => {}
+        },
+        OptionType::ListOf(element) => match value.as_array() {
+            None => errors.push(type_error(pointer, path, "a list", value)),
+            Some(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    let pointer = push_pointer(pointer, &i.to_string());
+                    let path = format!("{path}.{i}");
+                    check_value(element, item, &pointer, &path, errors);
+                }
+            }
+        },
+        OptionType::AttrsOf(element) => match value.as_object() {
+            None => errors.push(type_error(pointer, path, "an attribute set", value)),
+            Some(members) => {
+                for (name, member) in members {
+                    let pointer = push_pointer(pointer, name);
+                    let path = join_path(path, name);
+                    check_value(element, member, &pointer, &path, errors);
+                }
+            }
+        },
+        OptionType::Submodule(options) => check_group(options, value, pointer, path, errors),
+        _ => {}
+    }
+}
+
+fn type_error(pointer: &str, path: &str, expected: &str, value: &Value) -> ValidationError {
+    let found = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "an integer",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "an object",
+    };
+    ValidationError::new(
+        pointer,
+        "invalid_type",
+        &format!("The option '{path}' must be {expected}, but is {found}"),
+    )
+}
+
+fn join_path(path: &str, name: &str) -> String {
+    if path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{path}.{name}")
+    }
+}
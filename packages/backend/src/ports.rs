@@ -0,0 +1,681 @@
+use crate::validation::{push_pointer, ValidationError};
+use std::collections::HashMap;
+use std::fmt;
+use url::Url;
+
+/// A client endpoint such as `http://127.0.0.1:8551`, read the way the NixOS module's
+/// `parseEndpoint` reads it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub url: Url,
+    /// The host, lowercased, without brackets.
+    pub host: String,
+    pub port: u16,
+}
+
+impl Endpoint {
+    /// Parses an endpoint, which must be an `http` or `https` URL with a host and a port. As in
+    /// the module, the scheme may be left out, e.g. `localhost:8551`, and then reads as `http`.
+    ///
+    /// # Errors
+    ///
+    /// Returns why the endpoint cannot be used, phrased to follow the quoted value, e.g.
+    /// "must include a port".
+    pub fn parse(endpoint: &str) -> Result<Self, String> {
+        let with_scheme;
+        let endpoint = if endpoint.contains("://") {
+            endpoint
+        } else {
+            with_scheme = format!("http://{endpoint}");
+            &with_scheme
+        };
+        let url = Url::parse(endpoint).map_err(|e| format!("is not a valid URL: {e}"))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!(
+                "must use the http or https scheme, not '{}'",
+                url.scheme()
+            ));
+        }
+        let host = match url.host() {
+            Some(url::Host::Domain(domain)) => domain.to_string(),
+            Some(url::Host::Ipv4(address)) => address.to_string(),
+            Some(url::Host::Ipv6(_)) => {
+                return Err(
+                    "must not use an IPv6 address, which the NixOS module cannot read".to_string(),
+                )
+            }
+            None => return Err("must include a host".to_string()),
+        };
+        // The URL parser drops a port that equals the scheme's default, but the module needs
+        // one written out.
+        let port = url
+            .port()
+            .or_else(|| written_port(endpoint))
+            .ok_or_else(|| "must include a port, e.g. 'http://127.0.0.1:8551'".to_string())?;
+        Ok(Endpoint { url, host, port })
+    }
+
+    /// Whether both endpoints reach the same host and port; the scheme and path are ignored.
+    #[must_use]
+    pub fn same_host_and_port(&self, other: &Endpoint) -> bool {
+        self.port == other.port && normalize_address(&self.host) == normalize_address(&other.host)
+    }
+}
+
+/// The port written in the authority of a URL, e.g. `80` for `http://host:80/`.
+fn written_port(endpoint: &str) -> Option<u16> {
+    let (_, rest) = endpoint.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    authority.rsplit_once(':')?.1.parse().ok()
+}
+
+/// The transport protocol of a listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Tcp => write!(f, "TCP"),
+            Protocol::Udp => write!(f, "UDP"),
+        }
+    }
+}
+
+/// A socket that a service of the generated system binds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listener {
+    /// Dotted path of the service in the config, e.g. `execution.geth`.
+    pub service: String,
+    /// What the socket is for, e.g. `P2P` or `metrics`.
+    pub purpose: &'static str,
+    pub protocol: Protocol,
+    /// The address bound, e.g. `127.0.0.1` or `0.0.0.0` for all addresses.
+    pub address: String,
+    pub port: u16,
+    /// JSON pointer to the setting that chose the port: the endpoint, an item of
+    /// `extraOptions`, or `enable` if the client's default is used.
+    pub pointer: String,
+}
+
+/// Where the port of a listener comes from when no flag in `extraOptions` sets it.
+#[derive(Clone, Copy)]
+enum Port {
+    /// The port of the service's `endpoint`.
+    Endpoint,
+    /// The client's default, or the port that the module passes.
+    Fixed(u16),
+}
+
+/// Where the address of a listener comes from when no flag in `extraOptions` sets it.
+#[derive(Clone, Copy)]
+enum Address {
+    /// The host of the service's `endpoint`.
+    Endpoint,
+    Any,
+    Loopback,
+}
+
+/// A socket that a client opens when started by the NixOS module.
+struct Spec {
+    purpose: &'static str,
+    protocols: &'static [Protocol],
+    port: Port,
+    address: Address,
+    /// Flags that set the port, the one that takes precedence first.
+    port_flags: &'static [&'static str],
+    /// Flags that set the address.
+    address_flags: &'static [&'static str],
+    /// Flags that set both, as `host:port`.
+    listen_flags: &'static [&'static str],
+    /// Flags that open the socket, if the client does not open it by default.
+    enabled_by: &'static [&'static str],
+}
+
+impl Spec {
+    const fn new(
+        purpose: &'static str,
+        protocols: &'static [Protocol],
+        port: Port,
+        address: Address,
+        port_flags: &'static [&'static str],
+    ) -> Self {
+        Spec {
+            purpose,
+            protocols,
+            port,
+            address,
+            port_flags,
+            address_flags: &[],
+            listen_flags: &[],
+            enabled_by: &[],
+        }
+    }
+
+    const fn address_flags(mut self, flags: &'static [&'static str]) -> Self {
+        self.address_flags = flags;
+        self
+    }
+
+    const fn listen_flags(mut self, flags: &'static [&'static str]) -> Self {
+        self.listen_flags = flags;
+        self
+    }
+
+    const fn enabled_by(mut self, flags: &'static [&'static str]) -> Self {
+        self.enabled_by = flags;
+        self
+    }
+}
+
+const TCP: &[Protocol] = &[Protocol::Tcp];
+const UDP: &[Protocol] = &[Protocol::Udp];
+const TCP_UDP: &[Protocol] = &[Protocol::Tcp, Protocol::Udp];
+
+// The sockets below follow the flags in nixosModules/homestakeros/default.nix and the clients'
+// defaults for everything the module does not set.
+
+const ERIGON: &[Spec] = &[
+    Spec::new(
+        "engine API",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--authrpc.port"],
+    )
+    .address_flags(&["--authrpc.addr"]),
+    // WebSocket connections are served on the HTTP port.
+    Spec::new(
+        "JSON-RPC",
+        TCP,
+        Port::Fixed(8545),
+        Address::Endpoint,
+        &["--http.port"],
+    )
+    .address_flags(&["--http.addr"]),
+    Spec::new(
+        "private API",
+        TCP,
+        Port::Fixed(9090),
+        Address::Loopback,
+        &[],
+    )
+    .listen_flags(&["--private.api.addr"]),
+    Spec::new(
+        "P2P",
+        TCP_UDP,
+        Port::Fixed(30303),
+        Address::Any,
+        &["--port"],
+    ),
+    Spec::new("P2P", TCP_UDP, Port::Fixed(30304), Address::Any, &[]),
+    Spec::new(
+        "torrent",
+        TCP_UDP,
+        Port::Fixed(42069),
+        Address::Any,
+        &["--torrent.port"],
+    ),
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(6060),
+        Address::Loopback,
+        &["--metrics.port"],
+    )
+    .address_flags(&["--metrics.addr"]),
+];
+
+const GETH: &[Spec] = &[
+    Spec::new(
+        "engine API",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--authrpc.port"],
+    )
+    .address_flags(&["--authrpc.addr"]),
+    // The module puts WebSocket connections on the HTTP port.
+    Spec::new(
+        "JSON-RPC",
+        TCP,
+        Port::Fixed(8545),
+        Address::Endpoint,
+        &["--http.port"],
+    )
+    .address_flags(&["--http.addr"]),
+    Spec::new("P2P", TCP, Port::Fixed(30303), Address::Any, &["--port"]),
+    Spec::new(
+        "discovery",
+        UDP,
+        Port::Fixed(30303),
+        Address::Any,
+        &["--discovery.port", "--port"],
+    ),
+    // `--metrics` alone only collects metrics; the HTTP server needs an address.
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(6060),
+        Address::Loopback,
+        &["--metrics.port"],
+    )
+    .address_flags(&["--metrics.addr"])
+    .enabled_by(&["--metrics.addr"]),
+];
+
+const NETHERMIND: &[Spec] = &[
+    Spec::new(
+        "engine API",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--JsonRpc.EnginePort"],
+    )
+    .address_flags(&["--JsonRpc.EngineHost"]),
+    // The module puts WebSocket connections on the JSON-RPC port.
+    Spec::new(
+        "JSON-RPC",
+        TCP,
+        Port::Fixed(8545),
+        Address::Endpoint,
+        &["--JsonRpc.Port"],
+    )
+    .address_flags(&["--JsonRpc.Host"]),
+    Spec::new(
+        "P2P",
+        TCP,
+        Port::Fixed(30303),
+        Address::Any,
+        &["--Network.P2PPort"],
+    ),
+    Spec::new(
+        "discovery",
+        UDP,
+        Port::Fixed(30303),
+        Address::Any,
+        &["--Network.DiscoveryPort"],
+    ),
+    // Metrics are pushed unless a port to expose them on is given.
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(0),
+        Address::Any,
+        &["--Metrics.ExposePort"],
+    )
+    .enabled_by(&["--Metrics.ExposePort"]),
+];
+
+const BESU: &[Spec] = &[
+    Spec::new(
+        "engine API",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--engine-rpc-port"],
+    )
+    .address_flags(&["--rpc-http-host"]),
+    Spec::new(
+        "JSON-RPC",
+        TCP,
+        Port::Fixed(8545),
+        Address::Endpoint,
+        &["--rpc-http-port"],
+    )
+    .address_flags(&["--rpc-http-host"]),
+    Spec::new(
+        "WebSocket",
+        TCP,
+        Port::Fixed(8546),
+        Address::Endpoint,
+        &["--rpc-ws-port"],
+    )
+    .address_flags(&["--rpc-ws-host"]),
+    Spec::new(
+        "P2P",
+        TCP_UDP,
+        Port::Fixed(30303),
+        Address::Any,
+        &["--p2p-port"],
+    )
+    .address_flags(&["--p2p-interface"]),
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(9545),
+        Address::Loopback,
+        &["--metrics-port"],
+    )
+    .address_flags(&["--metrics-host"]),
+];
+
+const LIGHTHOUSE: &[Spec] = &[
+    Spec::new(
+        "HTTP API",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--http-port"],
+    )
+    .address_flags(&["--http-address"]),
+    Spec::new("P2P", TCP, Port::Fixed(9000), Address::Any, &["--port"])
+        .address_flags(&["--listen-address"]),
+    Spec::new(
+        "discovery",
+        UDP,
+        Port::Fixed(9000),
+        Address::Any,
+        &["--discovery-port", "--port"],
+    )
+    .address_flags(&["--listen-address"]),
+    Spec::new(
+        "QUIC",
+        UDP,
+        Port::Fixed(9001),
+        Address::Any,
+        &["--quic-port"],
+    )
+    .address_flags(&["--listen-address"]),
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(5054),
+        Address::Loopback,
+        &["--metrics-port"],
+    )
+    .address_flags(&["--metrics-address"]),
+];
+
+const PRYSM: &[Spec] = &[
+    Spec::new(
+        "gRPC gateway",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--grpc-gateway-port"],
+    )
+    .address_flags(&["--grpc-gateway-host"]),
+    Spec::new(
+        "gRPC",
+        TCP,
+        Port::Fixed(4000),
+        Address::Loopback,
+        &["--rpc-port"],
+    )
+    .address_flags(&["--rpc-host"]),
+    Spec::new(
+        "P2P",
+        TCP,
+        Port::Fixed(13000),
+        Address::Any,
+        &["--p2p-tcp-port"],
+    ),
+    Spec::new(
+        "discovery",
+        UDP,
+        Port::Fixed(12000),
+        Address::Any,
+        &["--p2p-udp-port"],
+    ),
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(8080),
+        Address::Loopback,
+        &["--monitoring-port"],
+    )
+    .address_flags(&["--monitoring-host"]),
+];
+
+const TEKU: &[Spec] = &[
+    Spec::new(
+        "REST API",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--rest-api-port"],
+    )
+    .address_flags(&["--rest-api-interface"]),
+    Spec::new(
+        "P2P",
+        TCP_UDP,
+        Port::Fixed(9000),
+        Address::Any,
+        &["--p2p-port"],
+    )
+    .address_flags(&["--p2p-interface"]),
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(8008),
+        Address::Loopback,
+        &["--metrics-port"],
+    )
+    .address_flags(&["--metrics-interface"]),
+];
+
+const NIMBUS: &[Spec] = &[
+    Spec::new(
+        "REST API",
+        TCP,
+        Port::Endpoint,
+        Address::Endpoint,
+        &["--rest-port"],
+    )
+    .address_flags(&["--rest-address"]),
+    Spec::new("P2P", TCP, Port::Fixed(9000), Address::Any, &["--tcp-port"])
+        .address_flags(&["--listen-address"]),
+    Spec::new(
+        "discovery",
+        UDP,
+        Port::Fixed(9000),
+        Address::Any,
+        &["--udp-port"],
+    )
+    .address_flags(&["--listen-address"]),
+    Spec::new(
+        "metrics",
+        TCP,
+        Port::Fixed(8008),
+        Address::Loopback,
+        &["--metrics-port"],
+    )
+    .address_flags(&["--metrics-address"]),
+];
+
+const MEV_BOOST: &[Spec] = &[
+    Spec::new("builder API", TCP, Port::Endpoint, Address::Endpoint, &[]).listen_flags(&["-addr"]),
+];
+
+const SSV_NODE: &[Spec] = &[
+    Spec::new("P2P", TCP, Port::Fixed(13001), Address::Any, &[]),
+    Spec::new("discovery", UDP, Port::Fixed(12001), Address::Any, &[]),
+];
+
+/// The sockets of a service, by the last segment of its path.
+fn specs(service: &str) -> &'static [Spec] {
+    match service.rsplit('.').next().unwrap_or_default() {
+        "erigon" => ERIGON,
+        "geth" => GETH,
+        "nethermind" => NETHERMIND,
+        "besu" => BESU,
+        "lighthouse" => LIGHTHOUSE,
+        "prysm" => PRYSM,
+        "teku" => TEKU,
+        "nimbus" => NIMBUS,
+        "mev-boost" => MEV_BOOST,
+        "ssv-node" => SSV_NODE,
+        _ => &[],
+    }
+}
+
+/// The sockets that a service binds when started by the NixOS module.
+///
+/// `service` is the dotted path of the service in the config, e.g. `consensus.teku`. Ports and
+/// addresses set by flags in `extraOptions` replace the defaults, whether given as
+/// `--flag=value`, as `--flag value` in one item, or as two items. Sockets whose port or
+/// address comes from the endpoint are left out if `endpoint` is `None`, such as when it is not
+/// a valid URL.
+#[must_use]
+pub fn listeners(
+    service: &str,
+    endpoint: Option<&Endpoint>,
+    extra_options: &[String],
+) -> Vec<Listener> {
+    let service_pointer = format!("/{}", service.replace('.', "/"));
+    let flags = parse_flags(extra_options);
+    let flag = |names: &[&str]| names.iter().find_map(|name| flags.get(*name));
+    let option_pointer = |index: usize| {
+        push_pointer(
+            &push_pointer(&service_pointer, "extraOptions"),
+            &index.to_string(),
+        )
+    };
+
+    let mut listeners = Vec::new();
+    for spec in specs(service) {
+        if !spec.enabled_by.is_empty() && flag(spec.enabled_by).is_none() {
+            continue;
+        }
+        let mut address = match (spec.address, endpoint) {
+            (Address::Endpoint, None) => continue,
+            (Address::Endpoint, Some(endpoint)) => endpoint.host.clone(),
+            (Address::Any, _) => "0.0.0.0".to_string(),
+            (Address::Loopback, _) => "127.0.0.1".to_string(),
+        };
+        let (mut port, mut pointer) = match (spec.port, endpoint) {
+            (Port::Endpoint, None) => continue,
+            (Port::Endpoint, Some(endpoint)) => {
+                (endpoint.port, push_pointer(&service_pointer, "endpoint"))
+            }
+            (Port::Fixed(port), _) => (port, push_pointer(&service_pointer, "enable")),
+        };
+        if let Some((value, index)) = flag(spec.listen_flags) {
+            if let Some((host, listen_port)) = value
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+            {
+                if !host.is_empty() {
+                    address = host.to_string();
+                }
+                port = listen_port;
+                pointer = option_pointer(*index);
+            }
+        }
+        if let Some((value, _)) = flag(spec.address_flags) {
+            address = value.clone();
+        }
+        if let Some((value, index)) = flag(spec.port_flags) {
+            if let Ok(flag_port) = value.parse() {
+                port = flag_port;
+                pointer = option_pointer(*index);
+            }
+        }
+        // Port 0 lets the system pick a free port.
+        if port == 0 {
+            continue;
+        }
+        for protocol in spec.protocols {
+            listeners.push(Listener {
+                service: service.to_string(),
+                purpose: spec.purpose,
+                protocol: *protocol,
+                address: address.clone(),
+                port,
+                pointer: pointer.clone(),
+            });
+        }
+    }
+    listeners
+}
+
+/// The flags in `extra_options` with their values and the index of the item that set them.
+/// A flag given more than once keeps its last value, as the clients do.
+fn parse_flags(extra_options: &[String]) -> HashMap<String, (String, usize)> {
+    let tokens: Vec<(usize, &str)> = extra_options
+        .iter()
+        .enumerate()
+        .flat_map(|(index, item)| item.split_whitespace().map(move |token| (index, token)))
+        .collect();
+    let mut flags = HashMap::new();
+    for (i, (index, token)) in tokens.iter().enumerate() {
+        if !token.starts_with('-') {
+            continue;
+        }
+        let (name, value) = match token.split_once('=') {
+            Some((name, value)) => (name, value.to_string()),
+            None => match tokens.get(i + 1) {
+                Some((_, next)) if !next.starts_with('-') => (*token, (*next).to_string()),
+                _ => (*token, String::new()),
+            },
+        };
+        let value = value.trim_matches(|c| c == '"' || c == '\'').to_string();
+        flags.insert(name.to_string(), (value, *index));
+    }
+    flags
+}
+
+/// Reports every listener that binds a port already bound by an earlier one.
+///
+/// Two listeners collide if they use the same protocol and port, and the same address or
+/// either binds all addresses. Each pair of services is reported once per port, at the setting
+/// that chose the later listener's port.
+#[must_use]
+pub fn port_conflicts(listeners: &[Listener]) -> Vec<ValidationError> {
+    let mut errors: Vec<ValidationError> = Vec::new();
+    let mut reported: Vec<(&str, &str, u16)> = Vec::new();
+    for (i, listener) in listeners.iter().enumerate() {
+        let Some(other) = listeners[..i].iter().find(|other| {
+            other.protocol == listener.protocol
+                && other.port == listener.port
+                && addresses_overlap(&other.address, &listener.address)
+        }) else {
+            continue;
+        };
+        let key = (
+            listener.pointer.as_str(),
+            other.service.as_str(),
+            listener.port,
+        );
+        if reported.contains(&key) {
+            continue;
+        }
+        reported.push(key);
+        let other_service = if other.service == listener.service {
+            "it".to_string()
+        } else {
+            format!("'{}'", other.service)
+        };
+        errors.push(ValidationError::new(
+            &listener.pointer,
+            "port_conflict",
+            &format!(
+                "'{}' would bind {} port {} for {}, which {other_service} already binds for {}",
+                listener.service, listener.protocol, listener.port, listener.purpose, other.purpose
+            ),
+        ));
+    }
+    errors
+}
+
+/// Maps the spellings of the loopback and wildcard addresses to one form each.
+fn normalize_address(address: &str) -> String {
+    let address = address
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    match address.as_str() {
+        "localhost" => "127.0.0.1".to_string(),
+        "" | "*" | "::" | "0.0.0.0" => "0.0.0.0".to_string(),
+        _ => address,
+    }
+}
+
+fn addresses_overlap(a: &str, b: &str) -> bool {
+    let (a, b) = (normalize_address(a), normalize_address(b));
+    a == b || a == "0.0.0.0" || b == "0.0.0.0"
+}
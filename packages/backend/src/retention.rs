@@ -0,0 +1,165 @@
+use crate::jobs::{BuildJob, JobStore};
+use crate::workspace::Workspace;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Limits on how many finished builds are kept around.
+///
+/// Builds that are still queued or running are never removed.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Remove builds that finished longer ago than this.
+    pub max_age: Option<Duration>,
+    /// Remove the oldest builds until the output directories take up at most this many bytes.
+    pub max_total_size: Option<u64>,
+    /// Keep only the newest builds of each hostname.
+    pub keep_per_host: Option<usize>,
+}
+
+impl RetentionPolicy {
+    /// Whether any limit is configured.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_total_size.is_some() || self.keep_per_host.is_some()
+    }
+}
+
+/// Selects the builds that fall outside the retention policy.
+///
+/// `sizes` maps build ids to the size of their output directories in bytes, and `now` is the
+/// current time in seconds since the Unix epoch.
+#[must_use]
+pub fn select_expired(
+    builds: &[BuildJob],
+    sizes: &HashMap<String, u64>,
+    policy: &RetentionPolicy,
+    now: u64,
+) -> Vec<String> {
+    // Newest first, so that the builds to keep come before the ones to drop.
+    let mut finished: Vec<&BuildJob> = builds.iter().filter(|b| b.status.is_finished()).collect();
+    finished.sort_by(|a, b| {
+        b.updated_at
+            .cmp(&a.updated_at)
+            .then_with(|| b.created_at.cmp(&a.created_at))
+    });
+
+    let mut expired = HashSet::new();
+    if let Some(max_age) = policy.max_age {
+        for build in &finished {
+            if now.saturating_sub(build.updated_at) > max_age.as_secs() {
+                expired.insert(build.build_id.clone());
+            }
+        }
+    }
+    if let Some(keep) = policy.keep_per_host {
+        let mut per_host: HashMap<&str, usize> = HashMap::new();
+        for build in &finished {
+            let count = per_host.entry(build.hostname.as_str()).or_default();
+            *count += 1;
+            if *count > keep {
+                expired.insert(build.build_id.clone());
+            }
+        }
+    }
+    if let Some(max_total_size) = policy.max_total_size {
+        let size_of = |build: &BuildJob| sizes.get(&build.build_id).copied().unwrap_or(0);
+        let mut total: u64 = builds
+            .iter()
+            .filter(|b| !expired.contains(&b.build_id))
+            .map(size_of)
+            .sum();
+        for build in finished.iter().rev() {
+            if total <= max_total_size {
+                break;
+            }
+            if expired.insert(build.build_id.clone()) {
+                total = total.saturating_sub(size_of(build));
+            }
+        }
+    }
+
+    // Report oldest first, in the order they would be removed.
+    finished
+        .iter()
+        .rev()
+        .filter(|b| expired.contains(&b.build_id))
+        .map(|b| b.build_id.clone())
+        .collect()
+}
+
+/// Removes the builds that fall outside the retention policy.
+///
+/// The index entry of each expired build is removed first, unless the build changed since it
+/// was selected, so that requests no longer reuse it; its output directory and nix GC root are
+/// removed afterwards. Returns the ids of the removed builds.
+///
+/// # Errors
+///
+/// Returns an error if the size of an output directory cannot be determined, or if the index
+/// holds a build id that is not a plain directory name.
+pub fn collect_garbage(
+    workspace: &Workspace,
+    jobs: &JobStore,
+    policy: &RetentionPolicy,
+    now: u64,
+) -> Result<Vec<String>> {
+    let builds = jobs.list();
+    let mut sizes = HashMap::new();
+    for build in &builds {
+        let output_dir = workspace.output_dir(&build.build_id)?;
+        if output_dir.exists() {
+            sizes.insert(build.build_id.clone(), dir_size(&output_dir)?);
+        }
+    }
+
+    let mut removed = Vec::new();
+    for build_id in select_expired(&builds, &sizes, policy, now) {
+        let output_dir = workspace.output_dir(&build_id)?;
+        let gc_root = workspace.gc_root(&build_id)?;
+        let Some(selected) = builds.iter().find(|b| b.build_id == build_id) else {
+            continue;
+        };
+        let unchanged =
+            |job: &BuildJob| job.status == selected.status && job.updated_at == selected.updated_at;
+        if jobs.remove_if(&build_id, unchanged).is_none() {
+            continue;
+        }
+        if output_dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&output_dir) {
+                eprintln!("Warning: Failed to remove {output_dir:?}: {e:?}");
+            }
+        }
+        // Dropping the out-link lets nix collect the build's store paths.
+        if gc_root.is_symlink() {
+            if let Err(e) = fs::remove_file(&gc_root) {
+                eprintln!("Warning: Failed to remove {gc_root:?}: {e:?}");
+            }
+        }
+        removed.push(build_id);
+    }
+    Ok(removed)
+}
+
+/// Computes the total size of the files below `path` in bytes, without following symlinks.
+///
+/// # Errors
+///
+/// Returns an error if a directory cannot be read.
+pub fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path).with_context(|| format!("Failed to read {path:?}"))? {
+        let entry = entry.with_context(|| "Failed to get directory entry")?;
+        let metadata = entry
+            .metadata()
+            .with_context(|| format!("Failed to read metadata of {:?}", entry.path()))?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
@@ -0,0 +1,222 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+
+/// A unit of work run by the scheduler.
+pub type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// Called with the build id of a task that panicked.
+type PanicHandler = Box<dyn Fn(&str) + Send + Sync + 'static>;
+
+/// Runs build tasks on a fixed number of worker threads.
+///
+/// Waiting tasks are queued per client, and the clients take turns: each pass hands out at most
+/// one task per client, so a client that submits many builds at once cannot starve the others.
+/// Tasks of a single client run in the order they were submitted.
+pub struct Scheduler {
+    state: Mutex<Queue>,
+    available: Condvar,
+    on_panic: PanicHandler,
+}
+
+#[derive(Default)]
+struct Queue {
+    tasks: HashMap<String, VecDeque<(String, Task)>>,
+    /// Clients with waiting tasks, in the order they get their next turn.
+    turns: VecDeque<String>,
+}
+
+impl Queue {
+    fn push(&mut self, client: &str, build_id: &str, task: Task) {
+        let tasks = self.tasks.entry(client.to_string()).or_default();
+        if tasks.is_empty() {
+            self.turns.push_back(client.to_string());
+        }
+        tasks.push_back((build_id.to_string(), task));
+    }
+
+    fn pop(&mut self) -> Option<(String, Task)> {
+        let client = self.turns.pop_front()?;
+        let tasks = self.tasks.get_mut(&client)?;
+        let next = tasks.pop_front()?;
+        if tasks.is_empty() {
+            self.tasks.remove(&client);
+        } else {
+            self.turns.push_back(client);
+        }
+        Some(next)
+    }
+
+    fn remove(&mut self, build_id: &str) -> Option<Task> {
+        let client = self
+            .tasks
+            .iter()
+            .find(|(_, tasks)| tasks.iter().any(|(id, _)| id == build_id))
+            .map(|(client, _)| client.clone())?;
+        let tasks = self.tasks.get_mut(&client)?;
+        let index = tasks.iter().position(|(id, _)| id == build_id)?;
+        let (_, task) = tasks.remove(index)?;
+        if tasks.is_empty() {
+            self.tasks.remove(&client);
+            self.turns.retain(|c| *c != client);
+        }
+        Some(task)
+    }
+
+    /// Build ids in the order they will be started.
+    fn order(&self) -> Vec<&str> {
+        let mut order = Vec::new();
+        let mut round = 0;
+        loop {
+            let before = order.len();
+            for client in &self.turns {
+                if let Some((id, _)) = self.tasks.get(client).and_then(|tasks| tasks.get(round)) {
+                    order.push(id.as_str());
+                }
+            }
+            if order.len() == before {
+                return order;
+            }
+            round += 1;
+        }
+    }
+}
+
+impl Scheduler {
+    /// Create a scheduler running at most `max_concurrent` tasks at a time.
+    ///
+    /// A task that panics does not take its worker down; `on_panic` is called with its build id
+    /// instead, so that the build can be marked as failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a worker thread cannot be spawned.
+    #[must_use]
+    pub fn start(
+        max_concurrent: usize,
+        on_panic: impl Fn(&str) + Send + Sync + 'static,
+    ) -> Arc<Self> {
+        let scheduler = Arc::new(Scheduler {
+            state: Mutex::new(Queue::default()),
+            available: Condvar::new(),
+            on_panic: Box::new(on_panic),
+        });
+        for i in 0..max_concurrent.max(1) {
+            let scheduler = Arc::clone(&scheduler);
+            thread::Builder::new()
+                .name(format!("build-worker-{i}"))
+                .spawn(move || scheduler.work())
+                .expect("Failed to spawn build worker");
+        }
+        scheduler
+    }
+
+    /// Queue a task for the given client.
+    pub fn submit(&self, client: &str, build_id: &str, task: Task) {
+        self.lock().push(client, build_id, task);
+        self.available.notify_one();
+    }
+
+    /// Position of a waiting build in the queue, starting from 1 for the next build to start.
+    #[must_use]
+    pub fn position(&self, build_id: &str) -> Option<usize> {
+        self.lock()
+            .order()
+            .iter()
+            .position(|id| *id == build_id)
+            .map(|index| index + 1)
+    }
+
+    /// Take a waiting task out of the queue.
+    #[must_use]
+    pub fn remove(&self, build_id: &str) -> Option<Task> {
+        self.lock().remove(build_id)
+    }
+
+    fn work(&self) {
+        loop {
+            let (build_id, task) = {
+                let mut queue = self.lock();
+                loop {
+                    if let Some(next) = queue.pop() {
+                        break next;
+                    }
+                    queue = self
+                        .available
+                        .wait(queue)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            };
+            // A panicking build must not take the worker down with it.
+            if panic::catch_unwind(AssertUnwindSafe(task)).is_err() {
+                eprintln!("Warning: The task of build {build_id} panicked");
+                (self.on_panic)(&build_id);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The client that a request counts against, given the address of its peer and the `Forwarded`
+/// and `X-Forwarded-For` headers it arrived with.
+///
+/// Proxies append to these headers, so only the entries on the right were written by a proxy;
+/// anything further left may have been sent by the client itself. The chain is therefore walked
+/// from the peer towards the left, and the first hop that is not a trusted proxy is the client.
+/// `Forwarded` is used when present, as it is the standard header.
+#[must_use]
+pub fn client_address(
+    peer: IpAddr,
+    forwarded: Option<&str>,
+    x_forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> String {
+    let hops: Vec<&str> = match (forwarded, x_forwarded_for) {
+        (Some(forwarded), _) => forwarded
+            .split(',')
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim()
+                        .eq_ignore_ascii_case("for")
+                        .then(|| value.trim().trim_matches('"'))
+                })
+            })
+            .collect(),
+        (None, Some(x_forwarded_for)) => x_forwarded_for.split(',').map(str::trim).collect(),
+        (None, None) => Vec::new(),
+    };
+
+    let mut client = peer.to_string();
+    let mut trusted = trusted_proxies.contains(&peer);
+    for hop in hops.iter().rev() {
+        if !trusted {
+            break;
+        }
+        match hop_address(hop) {
+            Some(addr) => {
+                client = addr.to_string();
+                trusted = trusted_proxies.contains(&addr);
+            }
+            // An obfuscated or unknown hop cannot be a trusted proxy; it names the client.
+            None => {
+                client = (*hop).to_string();
+                trusted = false;
+            }
+        }
+    }
+    client
+}
+
+/// The address of a forwarded hop, which may carry a port and brackets around an IPv6 address.
+fn hop_address(hop: &str) -> Option<IpAddr> {
+    hop.parse()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| hop.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
@@ -1,310 +1,548 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// The JSON Schema (draft 2020-12) of [`Config`], for validating configs outside the backend.
+///
+/// Descriptions are taken from the documentation of the fields. Unknown fields are rejected, as
+/// the backend does until it knows the module's options schema; from then on, options the types
+/// do not model are passed on to the module once the options schema vouches for them.
+#[must_use]
+pub fn config_json_schema() -> Value {
+    let mut schema = schemars::schema_for!(Config).to_value();
+    schema["title"] = Value::from("HomestakerOS config");
+    schema
+}
+
+/// A host configuration, as accepted by `POST /nixosConfig`.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Config {
+    /// Version of the config format; older documents are upgraded when they are loaded.
+    #[serde(default)]
+    pub version: u64,
+    /// Services that run alongside the clients.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub addons: Option<Addons>,
+    /// Consensus layer clients.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub consensus: Option<Consensus>,
+    /// Execution layer clients.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub execution: Option<Execution>,
+    /// Hostname and time zone.
     pub localization: Localization,
+    /// A set of systemd mount definitions.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mounts: Option<HashMap<String, Mount>>,
+    /// SSH access to the host.
     pub ssh: SSH,
+    /// VPN connections of the host.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub vpn: Option<Vpn>,
+    // Options of the module that are not modelled here; they are only accepted once checked
+    // against the module's options schema, and passed on to the Nix expression as they are.
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
+}
+
+impl Config {
+    /// The options to set on the homestakeros module, i.e. the config without its version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config cannot be serialized.
+    pub fn module_options(&self) -> serde_json::Result<Value> {
+        let mut options = serde_json::to_value(self)?;
+        if let Some(object) = options.as_object_mut() {
+            object.remove("version");
+        }
+        Ok(options)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Services that run alongside the clients.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Addons {
+    /// MEV-Boost relay client.
     #[serde(rename = "mev-boost", skip_serializing_if = "Option::is_none")]
     pub mev_boost: Option<MevBoost>,
+    /// SSV distributed validator node.
     #[serde(rename = "ssv-node", skip_serializing_if = "Option::is_none")]
     pub ssv_node: Option<SsvNode>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// MEV-Boost relay client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct MevBoost {
+    /// Whether to enable MEV-Boost.
     pub enable: bool,
+    /// Listening interface for the MEV-Boost server.
     pub endpoint: String,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// SSV distributed validator node.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct SsvNode {
+    /// Path to a persistent directory to store the node's database and keys.
+    /// Expected files: ssv_operator_key, ssv_operator_key.pub, and password.
+    /// Keys will be generated automatically if missing.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Path to the operator's private key.
     #[serde(rename = "privateKeyFile", skip_serializing_if = "Option::is_none")]
     pub private_key_file: Option<String>,
+    /// Path to the password of the operator's private key.
     #[serde(
         rename = "privateKeyPasswordFile",
         skip_serializing_if = "Option::is_none"
     )]
     pub private_key_password_file: Option<String>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Consensus layer clients.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Consensus {
+    /// Lighthouse consensus client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub lighthouse: Option<Lighthouse>,
+    /// Nimbus consensus client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nimbus: Option<Nimbus>,
+    /// Prysm consensus client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prysm: Option<Prysm>,
+    /// Teku consensus client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub teku: Option<Teku>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Lighthouse consensus client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Lighthouse {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Lighthouse.
     pub enable: bool,
+    /// HTTP server listening interface.
     pub endpoint: String,
+    /// Server endpoint for an execution layer JWT-authenticated HTTP JSON-RPC
+    /// connection.
     #[serde(rename = "execEndpoint")]
     pub exec_endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Slasher settings.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub slasher: Option<LighthouseSlasher>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Lighthouse slasher settings.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct LighthouseSlasher {
+    /// Whether to enable slasher.
     pub enable: bool,
+    /// Number of epochs to store.
     #[serde(rename = "historyLength")]
     pub history_length: i32,
+    /// Maximum size of the slasher database in gigabytes.
     #[serde(rename = "maxDatabaseSize")]
     pub max_database_size: i32,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Nimbus consensus client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Nimbus {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Nimbus.
     pub enable: bool,
+    /// JSON-HTTP server listening interface.
     pub endpoint: String,
+    /// Server endpoint for an execution layer JWT-authenticated HTTP JSON-RPC
+    /// connection.
     #[serde(rename = "execEndpoint")]
     pub exec_endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Prysm consensus client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Prysm {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Prysm.
     pub enable: bool,
+    /// JSON-HTTP server listening interface.
     pub endpoint: String,
+    /// Server endpoint for an execution layer JWT-authenticated HTTP JSON-RPC
+    /// connection.
     #[serde(rename = "execEndpoint")]
     pub exec_endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Slasher settings.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub slasher: Option<PrysmSlasher>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Prysm slasher settings.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct PrysmSlasher {
+    /// Whether to enable historical slasher.
     pub enable: bool,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Teku consensus client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Teku {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Teku.
     pub enable: bool,
+    /// JSON-HTTP server listening interface.
     pub endpoint: String,
+    /// Server endpoint for an execution layer JWT-authenticated HTTP JSON-RPC
+    /// connection.
     #[serde(rename = "execEndpoint")]
     pub exec_endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Execution layer clients.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Execution {
+    /// Besu execution client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub besu: Option<Besu>,
+    /// Erigon execution client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub erigon: Option<Erigon>,
+    /// Geth execution client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub geth: Option<Geth>,
+    /// Nethermind execution client.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nethermind: Option<Nethermind>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Besu execution client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Besu {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Besu.
     pub enable: bool,
+    /// HTTP-RPC server listening interface of engine API.
     pub endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Erigon execution client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Erigon {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Erigon.
     pub enable: bool,
+    /// HTTP-RPC server listening interface of engine API.
     pub endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Geth execution client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Geth {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Geth.
     pub enable: bool,
+    /// HTTP-RPC server listening interface of engine API.
     pub endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Nethermind execution client.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Nethermind {
+    /// Data directory for the blockchain.
     #[serde(rename = "dataDir")]
     pub data_dir: String,
+    /// Whether to enable Nethermind.
     pub enable: bool,
+    /// HTTP-RPC server listening interface of engine API.
     pub endpoint: String,
+    /// Path to the token that ensures safe connection between CL and EL.
     #[serde(rename = "jwtSecretFile", skip_serializing_if = "Option::is_none")]
     pub jwt_secret_file: Option<String>,
+    /// Additional command-line arguments.
     #[serde(rename = "extraOptions", skip_serializing_if = "Option::is_none")]
     pub extra_options: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// Hostname and time zone.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Localization {
+    /// The name of the machine.
     pub hostname: String,
+    /// The time zone used when displaying times and dates.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timezone: Option<String>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// A systemd mount unit.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Mount {
+    /// Description of this unit used in systemd messages and progress indicators.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Whether to enable this mount.
     pub enable: bool,
+    /// Options used to mount the file system; strings concatenated with ",".
     #[serde(skip_serializing_if = "Option::is_none")]
     pub options: Option<String>,
+    /// File system type.
     #[serde(rename = "type")]
     pub mount_type: String,
+    /// Units that want (i.e. depend on) this unit.
     #[serde(rename = "wantedBy", skip_serializing_if = "Option::is_none")]
     pub wanted_by: Option<Vec<String>>,
+    /// Absolute path of device node, file or other resource.
     pub what: String,
+    /// Absolute path of a directory of the mount point. Will be created if it doesn't
+    /// exist.
     #[serde(rename = "where")]
     pub mount_point: String,
-    // Additional keys:
+    /// Units that this unit wants, started along with it.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub wants: Option<Vec<String>>,
+    /// Units that are kept running while this unit is active.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upholds: Option<Vec<String>>,
+    /// Units that keep this unit running while they are active.
     #[serde(rename = "upheldBy", skip_serializing_if = "Option::is_none")]
     pub upheld_by: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub unit_config: Option<String>,
+    /// Extra entries of the [Unit] section, e.g. `{"DefaultDependencies": "no"}`.
+    #[serde(rename = "unitConfig", skip_serializing_if = "Option::is_none")]
+    pub unit_config: Option<HashMap<String, String>>,
+    /// Interval in seconds for rate-limiting starts of this unit.
     #[serde(
         rename = "startLimitIntervalSec",
         skip_serializing_if = "Option::is_none"
     )]
     pub start_limit_interval_sec: Option<u64>,
+    /// Number of starts allowed within the start limit interval.
     #[serde(rename = "startLimitBurst", skip_serializing_if = "Option::is_none")]
     pub start_limit_burst: Option<u64>,
+    /// Values whose change restarts this unit.
     #[serde(rename = "restartTriggers", skip_serializing_if = "Option::is_none")]
     pub restart_triggers: Option<Vec<String>>,
+    /// Units that must already be active when this unit starts.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub requisite: Option<Vec<String>>,
+    /// Units that this unit requires.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub requires: Option<Vec<String>>,
+    /// Units that require this unit.
     #[serde(rename = "requiredBy", skip_serializing_if = "Option::is_none")]
     pub required_by: Option<Vec<String>>,
+    /// Values whose change reloads this unit.
     #[serde(rename = "reloadTriggers", skip_serializing_if = "Option::is_none")]
     pub reload_triggers: Option<Vec<String>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Units whose stop or restart also stops or restarts this unit.
+    #[serde(rename = "partOf", skip_serializing_if = "Option::is_none")]
     pub part_of: Option<Vec<String>>,
+    /// How the unit definition is combined with an existing one.
     #[serde(rename = "overrideStrategy", skip_serializing_if = "Option::is_none")]
+    #[schemars(extend("enum" = ["asDropinIfExists", "asDropin", null]))]
     pub override_strategy: Option<String>,
+    /// Units activated when this unit succeeds.
     #[serde(rename = "onSuccess", skip_serializing_if = "Option::is_none")]
     pub on_success: Option<Vec<String>>,
+    /// Units activated when this unit fails.
     #[serde(rename = "onFailure", skip_serializing_if = "Option::is_none")]
     pub on_failure: Option<Vec<String>>,
+    /// Name of the unit file.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
+    /// Extra entries of the [Mount] section.
     #[serde(rename = "mountConfig", skip_serializing_if = "Option::is_none")]
     pub mount_config: Option<String>,
+    /// Documentation URLs of this unit.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub documentation: Option<String>,
+    /// Units that are stopped when this unit starts.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub conflicts: Option<Vec<String>>,
+    /// Units whose stop also stops this unit.
     #[serde(rename = "bindsTo", skip_serializing_if = "Option::is_none")]
     pub binds_to: Option<Vec<String>>,
+    /// If the specified units are started at the same time as this unit, delay them until this
+    /// unit has started.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub before: Option<Vec<String>>,
+    /// Other names of this unit.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub aliases: Option<Vec<String>>,
+    /// Units that must be started before this unit.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub after: Option<Vec<String>>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// SSH access to the host.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct SSH {
+    /// A list of public SSH keys to be added to the user's authorized keys.
     #[serde(rename = "authorizedKeys")]
     pub authorized_keys: Vec<String>,
+    /// Path to the Ed25519 SSH host key. If absent, the key will be generated
+    /// automatically.
     #[serde(rename = "privateKeyFile", skip_serializing_if = "Option::is_none")]
     pub private_key_file: Option<String>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// VPN connections of the host.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Vpn {
+    /// WireGuard connection.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub wireguard: Option<Wireguard>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(deny_unknown_fields)]
+/// WireGuard connection.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[schemars(deny_unknown_fields)]
 pub struct Wireguard {
+    /// A file path for the wg-quick configuration.
     #[serde(rename = "configFile")]
     pub config_file: String,
+    /// Whether to enable WireGuard.
     pub enable: bool,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: Map<String, Value>,
 }
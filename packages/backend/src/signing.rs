@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::Signer;
+use serde_json::json;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use crate::compute_sha256;
+
+/// File name of the build manifest inside the output directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// File name of the manifest signature inside the output directory.
+pub const SIGNATURE_FILE: &str = "manifest.json.minisig";
+
+/// An ed25519 key that signs build manifests in the minisign format.
+///
+/// Signatures use minisign's pre-hashed mode, so they can be checked with
+/// `minisign -Vm manifest.json -p homestakeros.pub`.
+pub struct SigningKey {
+    key: ed25519_dalek::SigningKey,
+    key_id: [u8; 8],
+}
+
+impl SigningKey {
+    /// Create a key from a 32-byte ed25519 seed.
+    ///
+    /// The minisign key id is derived from the public key, so it is stable for a given seed.
+    #[must_use]
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let key = ed25519_dalek::SigningKey::from_bytes(seed);
+        let digest = Blake2b512::digest(key.verifying_key().as_bytes());
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&digest[..8]);
+        SigningKey { key, key_id }
+    }
+
+    /// Load the key stored at `path`, generating and storing a new one if the file does not exist.
+    ///
+    /// The file holds the base64-encoded seed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or written, or does not hold a valid seed.
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read signing key {path:?}"))?;
+            let seed: [u8; 32] = BASE64
+                .decode(content.trim())
+                .with_context(|| format!("Failed to decode signing key {path:?}"))?
+                .try_into()
+                .map_err(|_| anyhow!("Signing key {path:?} must hold a 32-byte seed"))?;
+            return Ok(Self::from_seed(&seed));
+        }
+
+        let mut seed = [0u8; 32];
+        getrandom::fill(&mut seed).map_err(|e| anyhow!("Failed to generate signing key: {e}"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create parent directories for {path:?}"))?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to create signing key {path:?}"))?;
+        writeln!(file, "{}", BASE64.encode(seed))
+            .with_context(|| format!("Failed to write signing key {path:?}"))?;
+        Ok(Self::from_seed(&seed))
+    }
+
+    /// The minisign key id, as shown in minisign's comments.
+    #[must_use]
+    pub fn key_id(&self) -> String {
+        self.key_id
+            .iter()
+            .rev()
+            .map(|b| format!("{b:02X}"))
+            .collect()
+    }
+
+    /// The public key in the minisign public key file format.
+    #[must_use]
+    pub fn public_key(&self) -> String {
+        let mut blob = Vec::with_capacity(42);
+        blob.extend_from_slice(b"Ed");
+        blob.extend_from_slice(&self.key_id);
+        blob.extend_from_slice(self.key.verifying_key().as_bytes());
+        format!(
+            "untrusted comment: minisign public key {}\n{}\n",
+            self.key_id(),
+            BASE64.encode(blob)
+        )
+    }
+
+    /// Signs `data` and returns the signature in the minisign signature file format.
+    ///
+    /// `trusted_comment` is covered by the signature; line breaks in it are replaced by spaces.
+    #[must_use]
+    pub fn sign(&self, data: &[u8], trusted_comment: &str) -> String {
+        let trusted_comment = trusted_comment.replace(['\r', '\n'], " ");
+        let signature = self.key.sign(&Blake2b512::digest(data));
+
+        let mut blob = Vec::with_capacity(74);
+        blob.extend_from_slice(b"ED");
+        blob.extend_from_slice(&self.key_id);
+        blob.extend_from_slice(&signature.to_bytes());
+
+        let mut global = signature.to_bytes().to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global_signature = self.key.sign(&global);
+
+        format!(
+            "untrusted comment: signature from homestakeros secret key\n{}\ntrusted comment: {trusted_comment}\n{}\n",
+            BASE64.encode(blob),
+            BASE64.encode(global_signature.to_bytes())
+        )
+    }
+}
+
+/// Writes a manifest of the files in the output directory and, given a key, its signature.
+///
+/// The manifest lists the name, size and SHA-256 hash of every file, so that an operator who has
+/// verified the manifest can check the artifacts before booting them.
+///
+/// # Errors
+///
+/// Returns an error if the output directory cannot be read or the manifest cannot be written.
+pub fn write_manifest(
+    output_dir: &Path,
+    build_id: &str,
+    hostname: &str,
+    key: Option<&SigningKey>,
+) -> Result<()> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(output_dir)
+        .with_context(|| format!("Failed to read output_dir: {output_dir:?}"))?
+    {
+        let entry = entry.with_context(|| "Failed to get directory entry")?;
+        let path = entry.path();
+        let filename = entry.file_name().to_string_lossy().to_string();
+        if !path.is_file() || filename == MANIFEST_FILE || filename == SIGNATURE_FILE {
+            continue;
+        }
+        let size = entry
+            .metadata()
+            .with_context(|| format!("Failed to read metadata of {path:?}"))?
+            .len();
+        let sha = compute_sha256(&path)
+            .with_context(|| format!("Failed to compute SHA256 for {path:?}"))?;
+        files.push(json!({ "file": filename, "size": size, "sha256": sha }));
+    }
+    files.sort_by(|a, b| a["file"].as_str().cmp(&b["file"].as_str()));
+
+    let manifest = serde_json::to_string_pretty(&json!({
+        "build_id": build_id,
+        "hostname": hostname,
+        "files": files
+    }))
+    .context("Failed to serialize manifest")?;
+    let manifest_path = output_dir.join(MANIFEST_FILE);
+    fs::write(&manifest_path, &manifest)
+        .with_context(|| format!("Failed to write {manifest_path:?}"))?;
+
+    if let Some(key) = key {
+        let trusted_comment =
+            format!("build_id:{build_id}\thostname:{hostname}\tfile:{MANIFEST_FILE}");
+        let signature_path = output_dir.join(SIGNATURE_FILE);
+        fs::write(
+            &signature_path,
+            key.sign(manifest.as_bytes(), &trusted_comment),
+        )
+        .with_context(|| format!("Failed to write {signature_path:?}"))?;
+    }
+    Ok(())
+}
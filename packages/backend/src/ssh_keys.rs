@@ -0,0 +1,187 @@
+use anyhow::{anyhow, bail, Result};
+use base64::engine::general_purpose::{STANDARD as BASE64, STANDARD_NO_PAD as BASE64_NO_PAD};
+use base64::Engine;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Smallest RSA modulus that OpenSSH accepts, in bits.
+const MIN_RSA_BITS: usize = 1024;
+
+/// A public key from an `authorized_keys` line that OpenSSH will accept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuthorizedKey {
+    /// The key type, e.g. `ssh-ed25519`.
+    pub algorithm: String,
+    /// The fingerprint as printed by `ssh-keygen -l`, e.g. `SHA256:1NC4kk...`.
+    pub fingerprint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Parses a line in the `authorized_keys` format: optional options, the key type, the base64
+/// key blob and an optional comment.
+///
+/// The blob is decoded and its structure is checked against the key type, so that truncated or
+/// mangled pastes are caught before they end up in an image nobody can log into.
+///
+/// # Errors
+///
+/// Returns an error describing why the key cannot be used.
+pub fn parse_authorized_key(line: &str) -> Result<AuthorizedKey> {
+    let tokens = split_tokens(line.trim())?;
+    // Options such as `no-pty` or `command="..."` may precede the key type.
+    let start = match tokens.first() {
+        Some(first) if is_key_type(first) => 0,
+        Some(_) if tokens.get(1).is_some_and(|t| is_key_type(t)) => 1,
+        Some(first) if first.starts_with("ssh-dss") => {
+            bail!("DSA keys are no longer accepted by OpenSSH")
+        }
+        Some(first) => bail!("Unsupported key type '{first}'"),
+        None => bail!("The key is empty"),
+    };
+    let algorithm = tokens[start];
+    let encoded = tokens
+        .get(start + 1)
+        .ok_or_else(|| anyhow!("The key data is missing"))?;
+    let blob = BASE64
+        .decode(encoded)
+        .map_err(|e| anyhow!("The key data is not valid base64: {e}"))?;
+    check_blob(algorithm, &blob)?;
+
+    let comment = tokens[start + 2..].join(" ");
+    Ok(AuthorizedKey {
+        algorithm: algorithm.to_string(),
+        fingerprint: format!("SHA256:{}", BASE64_NO_PAD.encode(Sha256::digest(&blob))),
+        comment: (!comment.is_empty()).then_some(comment),
+    })
+}
+
+fn is_key_type(token: &str) -> bool {
+    matches!(
+        token,
+        "ssh-ed25519"
+            | "ssh-rsa"
+            | "ecdsa-sha2-nistp256"
+            | "ecdsa-sha2-nistp384"
+            | "ecdsa-sha2-nistp521"
+            | "sk-ssh-ed25519@openssh.com"
+            | "sk-ecdsa-sha2-nistp256@openssh.com"
+    )
+}
+
+/// Splits a line on whitespace, keeping double-quoted option values together.
+fn split_tokens(line: &str) -> Result<Vec<&str>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if let Some(s) = start.take() {
+                    tokens.push(&line[s..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if quoted {
+        bail!("The key options have an unterminated quote");
+    }
+    if let Some(s) = start {
+        tokens.push(&line[s..]);
+    }
+    Ok(tokens)
+}
+
+/// Checks that a decoded key blob holds a well-formed key of the given type.
+fn check_blob(algorithm: &str, blob: &[u8]) -> Result<()> {
+    let mut reader = Reader(blob);
+    let blob_type = reader.string()?;
+    if blob_type != algorithm.as_bytes() {
+        bail!(
+            "The key data is of type '{}', but the line says '{algorithm}'",
+            String::from_utf8_lossy(blob_type)
+        );
+    }
+
+    match algorithm {
+        "ssh-ed25519" | "sk-ssh-ed25519@openssh.com" => {
+            if reader.string()?.len() != 32 {
+                bail!("The ed25519 public key must be 32 bytes long");
+            }
+        }
+        "ssh-rsa" => {
+            let exponent = reader.string()?;
+            let modulus = reader.string()?;
+            if exponent.iter().all(|b| *b == 0) {
+                bail!("The RSA public exponent is zero");
+            }
+            let bits = mpint_bits(modulus);
+            if bits < MIN_RSA_BITS {
+                bail!("The RSA key has {bits} bits, but at least {MIN_RSA_BITS} are required");
+            }
+        }
+        _ => {
+            // ecdsa-sha2-<curve> and sk-ecdsa-sha2-<curve>@openssh.com
+            let curve = algorithm
+                .trim_start_matches("sk-")
+                .trim_start_matches("ecdsa-sha2-")
+                .trim_end_matches("@openssh.com");
+            if reader.string()? != curve.as_bytes() {
+                bail!("The key data is not for the curve '{curve}'");
+            }
+            let point = reader.string()?;
+            let coordinate_len = match curve {
+                "nistp256" => 32,
+                "nistp384" => 48,
+                _ => 66,
+            };
+            if point.len() != 1 + 2 * coordinate_len || point[0] != 4 {
+                bail!("The ECDSA public key is not an uncompressed point on '{curve}'");
+            }
+        }
+    }
+    if algorithm.starts_with("sk-") {
+        // The application string, usually `ssh:`.
+        reader.string()?;
+    }
+    if !reader.0.is_empty() {
+        bail!("The key data has trailing bytes");
+    }
+    Ok(())
+}
+
+/// Number of significant bits of an SSH mpint.
+fn mpint_bits(mpint: &[u8]) -> usize {
+    let digits: Vec<&u8> = mpint.iter().skip_while(|b| **b == 0).collect();
+    match digits.first() {
+        Some(first) => (digits.len() - 1) * 8 + (8 - first.leading_zeros() as usize),
+        None => 0,
+    }
+}
+
+/// Reads length-prefixed strings from an SSH wire-format blob.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let truncated = || anyhow!("The key data is truncated");
+        let (len, rest) = self.0.split_first_chunk::<4>().ok_or_else(truncated)?;
+        let len = u32::from_be_bytes(*len) as usize;
+        if rest.len() < len {
+            return Err(truncated());
+        }
+        let (value, rest) = rest.split_at(len);
+        self.0 = rest;
+        Ok(value)
+    }
+}
@@ -7,41 +7,129 @@
   };
 
   outputs = inputs:
+    let
+      inherit (inputs.nixpkgs) lib;
+
+      ls =
+        if builtins.pathExists ./nixosConfigurations
+        then builtins.readDir ./nixosConfigurations
+        else { };
+      hostnames =
+        builtins.filter
+          (name: ls.${name} == "directory")
+          (builtins.attrNames ls);
+
+      # The system of a host, with the modules of the image format it is built as.
+      mkSystem = hostname: formatModules: lib.nixosSystem {
+        system = "x86_64-linux";
+        specialArgs = { inherit inputs; };
+        modules = [
+          inputs.ponkila.nixosModules.base
+          inputs.homestakeros.nixosModules.homestakeros
+          ./nixosConfigurations/${hostname}
+          {
+            system.stateVersion = "24.11";
+          }
+        ] ++ formatModules;
+      };
+
+      # A raw EFI disk image, derived from the raw-efi format of nixos-generators.
+      rawImage = { config, lib, pkgs, modulesPath, ... }: {
+        fileSystems."/" = {
+          device = "/dev/disk/by-label/nixos";
+          autoResize = true;
+          fsType = "ext4";
+        };
+        fileSystems."/boot" = {
+          device = "/dev/disk/by-label/ESP";
+          fsType = "vfat";
+        };
+        boot.growPartition = true;
+        boot.loader.grub = {
+          device = "nodev";
+          efiSupport = true;
+          efiInstallAsRemovable = true;
+        };
+        boot.loader.timeout = lib.mkDefault 1;
+        system.build.raw = import "${toString modulesPath}/../lib/make-disk-image.nix" {
+          inherit lib config pkgs;
+          diskSize = "auto";
+          format = "raw";
+          partitionTableType = "efi";
+        };
+      };
+
+      # A qcow2 disk image that QEMU boots with its default BIOS firmware, for testing.
+      vmImage = { config, lib, pkgs, modulesPath, ... }: {
+        imports = [ "${toString modulesPath}/profiles/qemu-guest.nix" ];
+        fileSystems."/" = {
+          device = "/dev/disk/by-label/nixos";
+          autoResize = true;
+          fsType = "ext4";
+        };
+        boot.growPartition = true;
+        boot.kernelParams = [ "console=ttyS0" ];
+        boot.loader.grub.device = lib.mkDefault "/dev/vda";
+        boot.loader.timeout = lib.mkDefault 1;
+        system.build.qcow = import "${toString modulesPath}/../lib/make-disk-image.nix" {
+          inherit lib config pkgs;
+          diskSize = "auto";
+          format = "qcow2";
+          partitionTableType = "legacy";
+        };
+      };
+
+      # Boots the image next to it with the QEMU of the machine it is downloaded to, so it
+      # must not refer to the store of the build machine.
+      runVm = pkgs: pkgs.writeText "run-vm.sh" ''
+        #!/bin/sh
+        exec qemu-system-${pkgs.stdenv.hostPlatform.qemuArch} -machine accel=kvm:tcg -m 4096 -smp 2 \
+          -nographic -nic user,model=virtio-net-pci \
+          -drive "file=$(dirname "$0")/nixos.qcow2,if=virtio,format=qcow2" "$@"
+      '';
+
+      # The outputs of each build target, with the artifacts at the top level.
+      buildTargets = hostname:
+        let
+          kexec = mkSystem hostname [ inputs.ponkila.nixosModules.kexecTree ];
+          iso = mkSystem hostname [ inputs.ponkila.nixosModules.isoImage ];
+          raw = mkSystem hostname [ rawImage ];
+          vm = mkSystem hostname [ vmImage ];
+          inherit (kexec) pkgs;
+        in
+        {
+          kexec = kexec.config.system.build.kexecTree;
+          netboot = kexec.config.system.build.kexecTree;
+          iso = pkgs.linkFarm "iso" [
+            {
+              name = "nixos.iso";
+              path = "${iso.config.system.build.isoImage}/iso/nixos.iso";
+            }
+          ];
+          raw-image = pkgs.linkFarm "raw-image" [
+            {
+              name = "nixos.img";
+              path = "${raw.config.system.build.raw}/nixos.img";
+            }
+          ];
+          vm = pkgs.linkFarm "vm" [
+            {
+              name = "nixos.qcow2";
+              path = "${vm.config.system.build.qcow}/nixos.qcow2";
+            }
+            {
+              name = "run-vm.sh";
+              path = runVm pkgs;
+            }
+          ];
+        };
+    in
     inputs.flake-parts.lib.mkFlake { inherit inputs; } {
       systems = inputs.nixpkgs.lib.systems.flakeExposed;
       flake = {
-        nixosConfigurations =
-          let
-            ls = builtins.readDir ./nixosConfigurations;
-            hostnames =
-              builtins.filter
-                (name: builtins.hasAttr name ls && (ls.${name} == "directory"))
-                (builtins.attrNames ls);
-          in
-          inputs.nixpkgs.lib.mkIf
-            (
-              builtins.pathExists ./nixosConfigurations
-            )
-            (
-              builtins.listToAttrs (map
-                (hostname: {
-                  name = hostname;
-                  value = inputs.nixpkgs.lib.nixosSystem {
-                    system = "x86_64-linux";
-                    specialArgs = { inherit inputs; };
-                    modules = [
-                      inputs.ponkila.nixosModules.base
-                      inputs.ponkila.nixosModules.kexecTree
-                      inputs.homestakeros.nixosModules.homestakeros
-                      ./nixosConfigurations/${hostname}
-                      {
-                        system.stateVersion = "24.11";
-                      }
-                    ];
-                  };
-                })
-                hostnames)
-            );
+        nixosConfigurations = lib.genAttrs hostnames
+          (hostname: mkSystem hostname [ inputs.ponkila.nixosModules.kexecTree ]);
+        buildTargets = lib.genAttrs hostnames buildTargets;
         inherit (inputs.homestakeros) schema;
       };
     };
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Error};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// What a build produces for a host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildTarget {
+    /// A kernel, initrd and script to kexec into from a running Linux system.
+    #[default]
+    Kexec,
+    /// A kernel, initrd and iPXE scripts to boot over the network.
+    Netboot,
+    /// A bootable ISO image.
+    Iso,
+    /// A raw EFI disk image to write onto a disk.
+    RawImage,
+    /// A qcow2 disk image and a script that boots it in QEMU, for testing.
+    Vm,
+}
+
+impl BuildTarget {
+    /// All targets, in the order they are listed to users.
+    pub const ALL: [BuildTarget; 5] = [
+        BuildTarget::Kexec,
+        BuildTarget::Netboot,
+        BuildTarget::Iso,
+        BuildTarget::RawImage,
+        BuildTarget::Vm,
+    ];
+
+    /// The name of the target as used in requests and in the flake.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildTarget::Kexec => "kexec",
+            BuildTarget::Netboot => "netboot",
+            BuildTarget::Iso => "iso",
+            BuildTarget::RawImage => "raw-image",
+            BuildTarget::Vm => "vm",
+        }
+    }
+
+    /// The flake output that builds this target for `hostname`.
+    #[must_use]
+    pub fn flake_attribute(self, hostname: &str) -> String {
+        format!("buildTargets.{hostname}.{}", self.name())
+    }
+
+    /// The files of the build output that are served as artifacts.
+    #[must_use]
+    pub fn whitelist(self) -> &'static [&'static str] {
+        match self {
+            BuildTarget::Kexec => &["bzImage", "initrd.zst", "kexec-boot"],
+            BuildTarget::Netboot => &["bzImage", "initrd.zst", "netboot.ipxe", "variables.ipxe"],
+            BuildTarget::Iso => &["nixos.iso"],
+            BuildTarget::RawImage => &["nixos.img"],
+            BuildTarget::Vm => &["nixos.qcow2", "run-vm.sh"],
+        }
+    }
+}
+
+impl fmt::Display for BuildTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BuildTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BuildTarget::ALL
+            .into_iter()
+            .find(|target| target.name() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = BuildTarget::ALL.iter().map(|t| t.name()).collect();
+                anyhow!(
+                    "Unknown build target '{s}', expected one of: {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
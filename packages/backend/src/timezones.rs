@@ -0,0 +1,87 @@
+use chrono_tz::{Tz, TZ_VARIANTS};
+use std::str::FromStr;
+
+pub use chrono_tz::IANA_TZDB_VERSION;
+
+/// How similar a name must be to a time zone to be suggested, as the share of characters that
+/// need no edit.
+const MIN_SIMILARITY: f64 = 0.75;
+
+/// Upper bound on the suggestions for one misspelled name.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The names of all time zones in the bundled IANA tz database, sorted, including the links
+/// kept for backward compatibility such as `US/Eastern`.
+#[must_use]
+pub fn timezone_names() -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = TZ_VARIANTS.iter().map(|tz| tz.name()).collect();
+    names.sort_unstable();
+    names
+}
+
+/// Checks that `timezone` names a zone of the bundled IANA tz database, as NixOS requires of
+/// `time.timeZone`.
+///
+/// # Errors
+///
+/// Returns a message describing the problem, with the closest known names if there are any.
+pub fn validate_timezone(timezone: &str) -> Result<(), String> {
+    if Tz::from_str(timezone).is_ok() {
+        return Ok(());
+    }
+    let mut message =
+        format!("The timezone '{timezone}' is not in the tz database {IANA_TZDB_VERSION}");
+    let suggestions: Vec<String> = suggest_timezones(timezone)
+        .iter()
+        .map(|name| format!("'{name}'"))
+        .collect();
+    if let Some((last, rest)) = suggestions.split_last() {
+        message.push_str("; did you mean ");
+        if !rest.is_empty() {
+            message.push_str(&rest.join(", "));
+            message.push_str(" or ");
+        }
+        message.push_str(last);
+        message.push('?');
+    }
+    Err(message)
+}
+
+/// The known time zones closest to `timezone`, most similar first.
+///
+/// Names are compared without regard to case and with spaces read as underscores, and a name
+/// without a region, such as `helsinki`, is also compared to the last part of each zone. A name
+/// that only differs in case yields just the zone it spells.
+#[must_use]
+pub fn suggest_timezones(timezone: &str) -> Vec<&'static str> {
+    let wanted = timezone.trim().to_lowercase().replace(' ', "_");
+    if wanted.is_empty() {
+        return Vec::new();
+    }
+    if let Some(tz) = TZ_VARIANTS
+        .iter()
+        .find(|tz| tz.name().eq_ignore_ascii_case(&wanted))
+    {
+        return vec![tz.name()];
+    }
+    let mut scored: Vec<(f64, &'static str)> = TZ_VARIANTS
+        .iter()
+        .map(|tz| tz.name())
+        .filter_map(|name| {
+            let candidate = name.to_lowercase();
+            let mut score = strsim::normalized_damerau_levenshtein(&wanted, &candidate);
+            if !wanted.contains('/') {
+                if let Some((_, city)) = candidate.rsplit_once('/') {
+                    score = score.max(strsim::normalized_damerau_levenshtein(&wanted, city));
+                }
+            }
+            (score >= MIN_SIMILARITY).then_some((score, name))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(b.1)));
+    scored
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name)
+        .collect()
+}
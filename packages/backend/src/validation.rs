@@ -0,0 +1,615 @@
+use crate::migrations::migrate;
+use crate::mounts::mount_errors;
+use crate::options_schema::OptionsSchema;
+use crate::ports::{listeners, port_conflicts, Endpoint};
+use crate::schema_types::{config_json_schema, Config, Mount};
+use serde::Serialize;
+use serde_json::Value;
+use serde_path_to_error::Segment;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// The `jwtSecretFile` the NixOS module uses when a client does not set one.
+pub const DEFAULT_JWT_SECRET_FILE: &str = "/mnt/secrets/jwt.hex";
+
+/// Mount types that do not keep data across reboots.
+const VOLATILE_MOUNT_TYPES: &[&str] = &["tmpfs", "overlay", "squashfs"];
+
+/// The JSON Schema of the modelled options, which rejects unknown options until the module's
+/// options schema is known.
+static CONFIG_SCHEMA: LazyLock<Value> = LazyLock::new(config_json_schema);
+
+/// Upper bound on the problems reported for one payload.
+const MAX_ERRORS: usize = 100;
+
+/// A problem with a submitted config.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationError {
+    /// JSON pointer to the offending value, e.g. `/consensus/lighthouse/execEndpoint`.
+    pub pointer: String,
+    /// Machine-readable kind of the problem, e.g. `jwt_secret_mismatch`.
+    pub code: &'static str,
+    /// Human-readable description of the problem.
+    pub message: String,
+}
+
+impl ValidationError {
+    #[must_use]
+    pub fn new(pointer: &str, code: &'static str, message: &str) -> Self {
+        ValidationError {
+            pointer: pointer.to_string(),
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Appends a reference token to a JSON pointer, escaping `~` and `/`.
+#[must_use]
+pub fn push_pointer(pointer: &str, token: &str) -> String {
+    format!("{pointer}/{}", token.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Moves problems found in a config to where the config sits in a larger document, such as
+/// `/hosts/1` for a host of a cluster.
+#[must_use]
+pub fn prefix_pointers(prefix: &str, errors: Vec<ValidationError>) -> Vec<ValidationError> {
+    errors
+        .into_iter()
+        .map(|e| ValidationError {
+            pointer: format!("{prefix}{}", e.pointer),
+            ..e
+        })
+        .collect()
+}
+
+/// Parses a config from JSON, reporting every problem with its location.
+///
+/// Documents of older versions of the format are upgraded first.
+///
+/// The config types only model the options the backend reasons about and keep any others as
+/// they are, so unknown options are rejected before deserializing: by the module's options
+/// schema if it is known, and otherwise by [`config_json_schema`], which knows no options
+/// beyond the modelled ones.
+///
+/// Deserialization stops at the first problem, so the offending value is replaced by a
+/// placeholder of the expected type, or dropped, and the payload is parsed again until no new
+/// problem turns up. A missing field, or a problem that does not go away this way, ends the
+/// search.
+///
+/// # Errors
+///
+/// Returns the problems found if the payload is not valid JSON or does not match the config.
+pub fn parse_config(
+    body: &str,
+    schema: Option<&OptionsSchema>,
+) -> Result<Config, Vec<ValidationError>> {
+    let mut value: Value = serde_json::from_str(body).map_err(|e| {
+        vec![ValidationError::new(
+            "",
+            "invalid_json",
+            &format!("Invalid JSON: {e}"),
+        )]
+    })?;
+
+    migrate(&mut value).map_err(|e| vec![e])?;
+
+    let mut errors = match schema {
+        Some(schema) => {
+            // The version belongs to the document, not to the module.
+            let mut options = value.clone();
+            if let Some(object) = options.as_object_mut() {
+                object.remove("version");
+            }
+            schema.validate(&options)
+        }
+        None => {
+            let mut errors = Vec::new();
+            unknown_fields(&CONFIG_SCHEMA, &CONFIG_SCHEMA, &value, "", "", &mut errors);
+            errors
+        }
+    };
+    errors.truncate(MAX_ERRORS);
+    let mut skipped: Vec<String> = Vec::new();
+    loop {
+        let error = match serde_path_to_error::deserialize::<_, Config>(&value) {
+            Ok(config) if errors.is_empty() => return Ok(config),
+            Ok(_) => return Err(errors),
+            Err(e) => deserialize_error(&e),
+        };
+        if skipped.contains(&error.pointer) {
+            return Err(errors);
+        }
+        skipped.push(error.pointer.clone());
+        let retry = error.code != "missing_field" && skip_value(&mut value, &error);
+        // The schema may already have reported the same value.
+        if !errors.iter().any(|e| e.pointer == error.pointer) {
+            errors.push(error);
+        }
+        if !retry || errors.len() >= MAX_ERRORS {
+            return Err(errors);
+        }
+    }
+}
+
+/// Reports the members of `value` that the JSON Schema `node` does not allow, following the
+/// references, optional values, maps and lists the generated [`config_json_schema`] uses.
+fn unknown_fields(
+    root: &Value,
+    node: &Value,
+    value: &Value,
+    pointer: &str,
+    path: &str,
+    errors: &mut Vec<ValidationError>,
+) {
+    if let Some(name) = node["$ref"]
+        .as_str()
+        .and_then(|r| r.strip_prefix("#/$defs/"))
+    {
+        return unknown_fields(root, &root["$defs"][name], value, pointer, path, errors);
+    }
+    if let Some(variants) = node["anyOf"].as_array() {
+        for variant in variants {
+            unknown_fields(root, variant, value, pointer, path, errors);
+        }
+        return;
+    }
+    match value {
+        Value::Object(members) => {
+            for (name, member) in members {
+                let pointer = push_pointer(pointer, name);
+                let path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{path}.{name}")
+                };
+                let member_node = match &node["properties"][name] {
+                    Value::Null => &node["additionalProperties"],
+                    property => property,
+                };
+                match member_node {
+                    Value::Bool(false) => errors.push(ValidationError::new(
+                        &pointer,
+                        "unknown_field",
+                        &format!("The option '{path}' does not exist"),
+                    )),
+                    Value::Object(_) => {
+                        unknown_fields(root, member_node, member, &pointer, &path, errors);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Value::Array(items) if node["items"].is_object() => {
+            for (i, item) in items.iter().enumerate() {
+                let pointer = push_pointer(pointer, &i.to_string());
+                let path = format!("{path}.{i}");
+                unknown_fields(root, &node["items"], item, &pointer, &path, errors);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Converts a deserialization error into a problem pointing at the offending field.
+fn deserialize_error(error: &serde_path_to_error::Error<serde_json::Error>) -> ValidationError {
+    let mut pointer = String::new();
+    for segment in error.path().iter() {
+        match segment {
+            Segment::Seq { index } => pointer = push_pointer(&pointer, &index.to_string()),
+            Segment::Map { key } => pointer = push_pointer(&pointer, key),
+            Segment::Enum { variant } => pointer = push_pointer(&pointer, variant),
+            Segment::Unknown => {}
+        }
+    }
+
+    let message = error.inner().to_string();
+    let field_in = |prefix: &str| {
+        message
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.split('`').next())
+            .map(str::to_string)
+    };
+    let code = if message.starts_with("unknown field") {
+        "unknown_field"
+    } else if let Some(field) = field_in("missing field `") {
+        // Raised by the enclosing object, so the path stops one level short.
+        pointer = push_pointer(&pointer, &field);
+        "missing_field"
+    } else if message.starts_with("invalid type") {
+        "invalid_type"
+    } else {
+        "invalid_value"
+    };
+    ValidationError::new(&pointer, code, &message)
+}
+
+/// Gets the offending value out of the way. Returns whether the payload was changed.
+fn skip_value(value: &mut Value, error: &ValidationError) -> bool {
+    if let Some(placeholder) = placeholder(&error.message).filter(|_| error.code == "invalid_type")
+    {
+        if let Some(slot) = value.pointer_mut(&error.pointer) {
+            *slot = placeholder;
+            return true;
+        }
+    }
+    remove_pointer(value, &error.pointer)
+}
+
+/// A value of the type expected by a serde "invalid type" message, for scalars and lists.
+///
+/// Objects are not filled in, as an empty object would only trade the problem for missing fields.
+fn placeholder(message: &str) -> Option<Value> {
+    let (_, expected) = message.rsplit_once(", expected ")?;
+    if expected.starts_with("a string") {
+        Some(Value::from(""))
+    } else if expected.starts_with("a boolean") {
+        Some(Value::from(false))
+    } else if expected.starts_with("a sequence") {
+        Some(Value::Array(Vec::new()))
+    } else if expected.starts_with('i') || expected.starts_with('u') {
+        Some(Value::from(0))
+    } else {
+        None
+    }
+}
+
+/// Removes the member of an object at `pointer`. Returns whether anything was removed.
+///
+/// Array items are left in place, as removing one would shift the pointers of its siblings.
+fn remove_pointer(value: &mut Value, pointer: &str) -> bool {
+    let Some((parent, token)) = pointer.rsplit_once('/') else {
+        return false;
+    };
+    let key = token.replace("~1", "/").replace("~0", "~");
+    value
+        .pointer_mut(parent)
+        .and_then(Value::as_object_mut)
+        .and_then(|object| object.remove(&key))
+        .is_some()
+}
+
+/// The settings of an enabled client that the cross-field rules look at.
+struct Client<'a> {
+    /// Dotted path of the client in the config, e.g. `consensus.lighthouse`.
+    path: String,
+    /// JSON pointer to the client, e.g. `/consensus/lighthouse`.
+    pointer: String,
+    data_dir: &'a str,
+    endpoint: &'a str,
+    jwt_secret_file: &'a str,
+    /// Only set for consensus clients.
+    exec_endpoint: Option<&'a str>,
+    extra_options: &'a [String],
+}
+
+impl<'a> Client<'a> {
+    fn new(
+        path: &str,
+        data_dir: &'a str,
+        endpoint: &'a str,
+        jwt_secret_file: Option<&'a String>,
+        exec_endpoint: Option<&'a str>,
+        extra_options: Option<&'a Vec<String>>,
+    ) -> Self {
+        Client {
+            path: path.to_string(),
+            pointer: format!("/{}", path.replace('.', "/")),
+            data_dir,
+            endpoint,
+            jwt_secret_file: jwt_secret_file.map_or(DEFAULT_JWT_SECRET_FILE, String::as_str),
+            exec_endpoint,
+            extra_options: extra_options.map_or(&[], Vec::as_slice),
+        }
+    }
+}
+
+/// Checks the rules that span several fields of the config.
+///
+/// These mistakes are accepted by the NixOS module, but leave the booted node without a working
+/// client: a consensus client that cannot reach or authenticate with its execution client, or a
+/// client whose data ends up in memory and is lost on reboot, or a mount that systemd cannot set
+/// up, or services that would bind the same port. Endpoints must be http or https URLs with a
+/// port. Returns one problem per violation.
+#[must_use]
+pub fn semantic_errors(config: &Config) -> Vec<ValidationError> {
+    let execution = execution_clients(config);
+    let consensus = consensus_clients(config);
+    let mut errors = Vec::new();
+
+    if !consensus.is_empty() && execution.is_empty() {
+        errors.push(ValidationError::new(
+            &push_pointer(&consensus[0].pointer, "enable"),
+            "missing_execution_client",
+            &format!(
+                "The '{}' client needs an enabled execution client",
+                consensus[0].path
+            ),
+        ));
+    }
+
+    let mut endpoints: Vec<(&str, &str, &str, &[String])> = execution
+        .iter()
+        .chain(&consensus)
+        .map(|c| {
+            (
+                c.path.as_str(),
+                c.pointer.as_str(),
+                c.endpoint,
+                c.extra_options,
+            )
+        })
+        .collect();
+    let mev_boost = config
+        .addons
+        .as_ref()
+        .and_then(|a| a.mev_boost.as_ref())
+        .filter(|m| m.enable);
+    if let Some(mev_boost) = mev_boost {
+        endpoints.push((
+            "addons.mev-boost",
+            "/addons/mev-boost",
+            &mev_boost.endpoint,
+            mev_boost.extra_options.as_deref().unwrap_or_default(),
+        ));
+    }
+    let mut sockets = Vec::new();
+    let mut parsed: HashMap<&str, Endpoint> = HashMap::new();
+    for (path, pointer, endpoint, extra_options) in endpoints {
+        let endpoint = match Endpoint::parse(endpoint) {
+            Ok(endpoint) => Some(endpoint),
+            Err(problem) => {
+                errors.push(ValidationError::new(
+                    &push_pointer(pointer, "endpoint"),
+                    "invalid_url",
+                    &format!("The '{path}.endpoint' '{endpoint}' {problem}"),
+                ));
+                None
+            }
+        };
+        sockets.extend(listeners(path, endpoint.as_ref(), extra_options));
+        if let Some(endpoint) = endpoint {
+            parsed.insert(path, endpoint);
+        }
+    }
+
+    for cl in &consensus {
+        let exec_endpoint = cl.exec_endpoint.unwrap_or_default();
+        let target = match Endpoint::parse(exec_endpoint) {
+            Ok(target) => target,
+            Err(problem) => {
+                errors.push(ValidationError::new(
+                    &push_pointer(&cl.pointer, "execEndpoint"),
+                    "invalid_url",
+                    &format!("The '{}.execEndpoint' '{exec_endpoint}' {problem}", cl.path),
+                ));
+                continue;
+            }
+        };
+        let Some(el) = execution.iter().find(|el| {
+            parsed
+                .get(el.path.as_str())
+                .is_some_and(|endpoint| endpoint.same_host_and_port(&target))
+        }) else {
+            if !execution.is_empty() {
+                let endpoints: Vec<String> = execution
+                    .iter()
+                    .map(|el| format!("'{}' ({})", el.endpoint, el.path))
+                    .collect();
+                errors.push(ValidationError::new(
+                    &push_pointer(&cl.pointer, "execEndpoint"),
+                    "exec_endpoint_mismatch",
+                    &format!(
+                        "The '{}.execEndpoint' '{exec_endpoint}' does not match the endpoint of an enabled execution client: {}",
+                        cl.path,
+                        endpoints.join(", ")
+                    ),
+                ));
+            }
+            continue;
+        };
+        if cl.jwt_secret_file != el.jwt_secret_file {
+            errors.push(ValidationError::new(
+                &push_pointer(&cl.pointer, "jwtSecretFile"),
+                "jwt_secret_mismatch",
+                &format!(
+                    "The '{}.jwtSecretFile' '{}' differs from '{}.jwtSecretFile' '{}'",
+                    cl.path, cl.jwt_secret_file, el.path, el.jwt_secret_file
+                ),
+            ));
+        }
+    }
+
+    let mut data_dirs: Vec<(&str, &str)> = execution
+        .iter()
+        .chain(&consensus)
+        .map(|client| (client.path.as_str(), client.data_dir))
+        .collect();
+    // The module only starts the SSV node next to a CL and an EL, and silently skips it otherwise.
+    let ssv_node = config.addons.as_ref().and_then(|a| a.ssv_node.as_ref());
+    if let Some(ssv_node) = ssv_node.filter(|_| !consensus.is_empty() && !execution.is_empty()) {
+        data_dirs.push(("addons.ssv-node", &ssv_node.data_dir));
+    }
+    for (path, data_dir) in data_dirs {
+        if !is_persistent_path(config.mounts.as_ref(), data_dir) {
+            errors.push(ValidationError::new(
+                &format!("/{}/dataDir", path.replace('.', "/")),
+                "not_persistent",
+                &format!("The '{path}.dataDir' '{data_dir}' is not on an enabled persistent mount"),
+            ));
+        }
+    }
+
+    let mut services: Vec<String> = execution
+        .iter()
+        .chain(&consensus)
+        .filter_map(|client| client.path.rsplit('.').next())
+        .map(|name| format!("{name}.service"))
+        .collect();
+    if ssv_node.is_some() && !consensus.is_empty() && !execution.is_empty() {
+        services.push("ssv-node.service".to_string());
+        sockets.extend(listeners("addons.ssv-node", None, &[]));
+    }
+    if mev_boost.is_some() {
+        services.push("mev-boost.service".to_string());
+    }
+    let wireguard = config.vpn.as_ref().and_then(|v| v.wireguard.as_ref());
+    if let Some(wireguard) = wireguard.filter(|w| w.enable) {
+        // The module names the interface after the config file, up to its first dot.
+        let file_name = Path::new(&wireguard.config_file)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let interface = file_name.split('.').next().unwrap_or_default();
+        services.push(format!("wg-quick-{interface}.service"));
+    }
+    errors.extend(mount_errors(config.mounts.as_ref(), &services));
+    errors.extend(port_conflicts(&sockets));
+
+    errors
+}
+
+/// Longest hostname label allowed by RFC 1123.
+const MAX_HOSTNAME_LEN: usize = 63;
+
+/// Checks that `hostname` is a single RFC 1123 label: 1 to 63 ASCII letters, digits and
+/// hyphens, neither starting nor ending with a hyphen.
+///
+/// Dots are rejected as well, since NixOS does not allow them in `networking.hostName` and the
+/// hostname is used as a directory name and as a flake attribute.
+///
+/// # Errors
+///
+/// Returns a message describing why the hostname cannot be used.
+pub fn validate_hostname(hostname: &str) -> Result<(), String> {
+    if hostname.is_empty() || hostname.len() > MAX_HOSTNAME_LEN {
+        return Err(format!(
+            "The hostname must be 1 to {MAX_HOSTNAME_LEN} characters long, but '{hostname}' has {}",
+            hostname.len()
+        ));
+    }
+    if let Some(c) = hostname
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-')
+    {
+        return Err(format!(
+            "The hostname '{hostname}' contains {c:?}, but only ASCII letters, digits and '-' are allowed"
+        ));
+    }
+    if hostname.starts_with('-') || hostname.ends_with('-') {
+        return Err(format!(
+            "The hostname '{hostname}' must not start or end with '-'"
+        ));
+    }
+    Ok(())
+}
+
+/// Whether `path` lies on an enabled mount that keeps its data across reboots.
+///
+/// Mirrors `isPersistentPath` of the NixOS module, but compares whole path components so that
+/// a mount at `/mnt/e` does not cover `/mnt/erigon`.
+#[must_use]
+pub fn is_persistent_path(mounts: Option<&HashMap<String, Mount>>, path: &str) -> bool {
+    mounts.into_iter().flat_map(HashMap::values).any(|mount| {
+        mount.enable
+            && !VOLATILE_MOUNT_TYPES.contains(&mount.mount_type.as_str())
+            && Path::new(path).starts_with(&mount.mount_point)
+    })
+}
+
+fn execution_clients(config: &Config) -> Vec<Client<'_>> {
+    let mut clients = Vec::new();
+    let Some(execution) = &config.execution else {
+        return clients;
+    };
+    if let Some(c) = execution.besu.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "execution.besu",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            None,
+            c.extra_options.as_ref(),
+        ));
+    }
+    if let Some(c) = execution.erigon.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "execution.erigon",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            None,
+            c.extra_options.as_ref(),
+        ));
+    }
+    if let Some(c) = execution.geth.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "execution.geth",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            None,
+            c.extra_options.as_ref(),
+        ));
+    }
+    if let Some(c) = execution.nethermind.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "execution.nethermind",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            None,
+            c.extra_options.as_ref(),
+        ));
+    }
+    clients
+}
+
+fn consensus_clients(config: &Config) -> Vec<Client<'_>> {
+    let mut clients = Vec::new();
+    let Some(consensus) = &config.consensus else {
+        return clients;
+    };
+    if let Some(c) = consensus.lighthouse.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "consensus.lighthouse",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            Some(&c.exec_endpoint),
+            c.extra_options.as_ref(),
+        ));
+    }
+    if let Some(c) = consensus.nimbus.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "consensus.nimbus",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            Some(&c.exec_endpoint),
+            c.extra_options.as_ref(),
+        ));
+    }
+    if let Some(c) = consensus.prysm.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "consensus.prysm",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            Some(&c.exec_endpoint),
+            c.extra_options.as_ref(),
+        ));
+    }
+    if let Some(c) = consensus.teku.as_ref().filter(|c| c.enable) {
+        clients.push(Client::new(
+            "consensus.teku",
+            &c.data_dir,
+            &c.endpoint,
+            c.jwt_secret_file.as_ref(),
+            Some(&c.exec_endpoint),
+            c.extra_options.as_ref(),
+        ));
+    }
+    clients
+}
@@ -1,12 +1,14 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use tempfile::TempDir;
 use uuid::Uuid;
 
 /// Top-level workspace.
 pub struct Workspace {
-    pub base_dir: TempDir,
+    pub base_dir: PathBuf,
+    // Keeps a temporary base directory alive for the lifetime of the workspace.
+    _temp_dir: Option<TempDir>,
 }
 
 /// A build-specific workspace.
@@ -16,30 +18,85 @@ pub struct Build {
     pub nix_config_dir: PathBuf,
     pub hostname_dir: PathBuf,
     pub output_dir: PathBuf,
+    /// Out-link of the nix build, keeping its store paths alive while the build is retained.
+    pub gc_root: PathBuf,
 }
 
 impl Workspace {
-    /// Create a new top-level workspace.
+    /// Create a new top-level workspace in a temporary directory that is removed on drop.
     ///
     /// # Errors
     ///
     /// Returns an error if a temporary directory cannot be created or if the builds directory cannot be created.
     pub fn new() -> Result<Self> {
-        let base_dir = TempDir::new().context("Failed to create temporary directory")?;
-        fs::create_dir_all(base_dir.path().join("builds"))
-            .with_context(|| format!("Failed to create builds directory at {base_dir:?}/builds"))?;
-        Ok(Workspace { base_dir })
+        let temp_dir = TempDir::new().context("Failed to create temporary directory")?;
+        let mut workspace = Self::with_state_dir(temp_dir.path())?;
+        workspace._temp_dir = Some(temp_dir);
+        Ok(workspace)
+    }
+
+    /// Create a top-level workspace in a persistent state directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state directory or the builds directory cannot be created.
+    pub fn with_state_dir(state_dir: &Path) -> Result<Self> {
+        let builds_dir = state_dir.join("builds");
+        fs::create_dir_all(&builds_dir)
+            .with_context(|| format!("Failed to create builds directory at {builds_dir:?}"))?;
+        let gcroots_dir = state_dir.join("gcroots");
+        fs::create_dir_all(&gcroots_dir)
+            .with_context(|| format!("Failed to create gcroots directory at {gcroots_dir:?}"))?;
+        Ok(Workspace {
+            base_dir: state_dir.to_path_buf(),
+            _temp_dir: None,
+        })
+    }
+
+    /// Output directory of the build with the given id.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the build id would lead outside the builds directory.
+    pub fn output_dir(&self, build_id: &str) -> Result<PathBuf> {
+        join_inside(&self.base_dir, &["builds", build_id])
+    }
+
+    /// Nix GC root of the build with the given id.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the build id would lead outside the gcroots directory.
+    pub fn gc_root(&self, build_id: &str) -> Result<PathBuf> {
+        join_inside(&self.base_dir, &["gcroots", build_id])
+    }
+
+    /// Path of the build index inside the workspace.
+    #[must_use]
+    pub fn index_path(&self) -> PathBuf {
+        self.base_dir.join("builds.json")
+    }
+
+    /// Directory of the git repositories that keep the history of the builds' flakes.
+    #[must_use]
+    pub fn repos_dir(&self) -> PathBuf {
+        self.base_dir.join("repos")
     }
 
     /// Create a new build-specific workspace.
     ///
     /// # Errors
     ///
-    /// Returns an error if any of the required directories cannot be created.
+    /// Returns an error if the hostname would lead outside the workspace, or if any of the
+    /// required directories cannot be created.
     pub fn new_build_workspace(&self, hostname: &str) -> Result<Build> {
         let build_uuid = Uuid::new_v4().to_string();
         let dir_name = "build_work_".to_string() + &build_uuid;
-        let working_dir = self.base_dir.path().join(dir_name);
+        let working_dir = join_inside(&self.base_dir, &[&dir_name])?;
+        let hostname_dir = join_inside(
+            &working_dir,
+            &["nixConfig", "nixosConfigurations", hostname],
+        )?;
 
         // Create the directories.
         fs::create_dir_all(&working_dir)
@@ -48,25 +105,60 @@ impl Workspace {
         fs::create_dir_all(&nix_config_dir).with_context(|| {
             format!("Failed to create nix config directory at {nix_config_dir:?}")
         })?;
-        let hostname_dir = nix_config_dir.join("nixosConfigurations").join(hostname);
         fs::create_dir_all(&hostname_dir)
             .with_context(|| format!("Failed to create hostname directory at {hostname_dir:?}"))?;
 
         // Create the final build directory.
-        let output_dir = self.base_dir.path().join("builds").join(&build_uuid);
+        let output_dir = self.output_dir(&build_uuid)?;
         fs::create_dir_all(&output_dir)
             .with_context(|| format!("Failed to create output directory at {output_dir:?}"))?;
 
+        let gc_root = self.gc_root(&build_uuid)?;
+
         Ok(Build {
             uuid: build_uuid,
             working_dir,
             nix_config_dir,
             hostname_dir,
             output_dir,
+            gc_root,
         })
     }
 }
 
+impl Build {
+    /// The directory of a host under `nixosConfigurations`, created if needed.
+    ///
+    /// Builds of a cluster render every host of the cluster into the same flake.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the hostname would lead outside the flake, or if the directory cannot
+    /// be created.
+    pub fn host_dir(&self, hostname: &str) -> Result<PathBuf> {
+        let host_dir = join_inside(&self.nix_config_dir, &["nixosConfigurations", hostname])?;
+        fs::create_dir_all(&host_dir)
+            .with_context(|| format!("Failed to create hostname directory at {host_dir:?}"))?;
+        Ok(host_dir)
+    }
+
+    /// Remove the build's output directory and GC root along with its working directory.
+    ///
+    /// Used for builds that will never produce artifacts.
+    pub fn discard(self) {
+        if self.output_dir.exists() {
+            if let Err(e) = fs::remove_dir_all(&self.output_dir) {
+                eprintln!("Warning: Failed to remove output_dir: {e:?}");
+            }
+        }
+        if self.gc_root.is_symlink() {
+            if let Err(e) = fs::remove_file(&self.gc_root) {
+                eprintln!("Warning: Failed to remove gc_root: {e:?}");
+            }
+        }
+    }
+}
+
 /// Automatic partial cleanup.
 impl Drop for Build {
     fn drop(&mut self) {
@@ -77,3 +169,19 @@ impl Drop for Build {
         }
     }
 }
+
+/// Joins `parts` onto `base`, making sure that the result stays inside `base`.
+///
+/// Each part must be a single plain path component, so that user-supplied names such as
+/// hostnames cannot contain separators, `..` or an absolute path.
+pub(crate) fn join_inside(base: &Path, parts: &[&str]) -> Result<PathBuf> {
+    let mut path = base.to_path_buf();
+    for part in parts {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) if name == *part => path.push(name),
+            _ => bail!("Refusing to use {part:?} as a path component inside {base:?}"),
+        }
+    }
+    Ok(path)
+}
@@ -0,0 +1,95 @@
+use backend::cluster::{parse_cluster, validate_cluster};
+use serde_json::{json, Value};
+
+fn host(hostname: &str) -> Value {
+    json!({
+        "localization": { "hostname": hostname },
+        "ssh": {
+            "authorizedKeys": [
+                "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKhrzD3JMfXTSO1vK0qFvEwtfCeVH+i6Upq8VksFXY++ user@host"
+            ]
+        }
+    })
+}
+
+#[test]
+fn test_parse_list_of_configs() {
+    let body = json!([host("a"), host("b")]).to_string();
+    let cluster = parse_cluster(&body, None).unwrap();
+    assert_eq!(cluster.name, None);
+    assert_eq!(cluster.hosts.len(), 2);
+    assert_eq!(cluster.host_pointer(1), "/1");
+    let others = cluster.others(0);
+    assert_eq!(others.len(), 1);
+    assert_eq!(others[0].localization.hostname, "b");
+    assert!(validate_cluster(&cluster).is_ok());
+}
+
+#[test]
+fn test_parse_named_cluster() {
+    let body = json!({ "name": "mainnet", "hosts": [host("a"), host("b")] }).to_string();
+    let cluster = parse_cluster(&body, None).unwrap();
+    assert_eq!(cluster.name.as_deref(), Some("mainnet"));
+    assert_eq!(cluster.host_pointer(0), "/hosts/0");
+}
+
+#[test]
+fn test_parse_errors_point_into_the_cluster() {
+    let mut broken = host("b");
+    broken["localization"]["hostname"] = json!(42);
+    let body = json!({ "hosts": [host("a"), broken] }).to_string();
+    let errors = parse_cluster(&body, None).unwrap_err();
+    assert!(!errors.is_empty());
+    assert!(errors
+        .iter()
+        .all(|e| e.pointer.starts_with("/hosts/1/localization/hostname")));
+}
+
+#[test]
+fn test_parse_invalid_clusters() {
+    let code = |body: Value| parse_cluster(&body.to_string(), None).unwrap_err()[0].code;
+    assert_eq!(code(json!([])), "required");
+    assert_eq!(code(json!({ "hosts": [] })), "required");
+    assert_eq!(code(json!({ "name": "mainnet" })), "required");
+    assert_eq!(
+        code(json!({ "name": 1, "hosts": [host("a")] })),
+        "invalid_value"
+    );
+    assert_eq!(
+        code(json!({ "name": "a/b", "hosts": [host("a")] })),
+        "invalid_value"
+    );
+    assert_eq!(
+        code(json!({ "name": "..", "hosts": [host("a")] })),
+        "invalid_value"
+    );
+    assert_eq!(
+        code(json!({ "hosts": [host("a")], "extra": 1 })),
+        "unknown_field"
+    );
+    assert_eq!(code(json!("a")), "invalid_type");
+    assert_eq!(
+        parse_cluster("not json", None).unwrap_err()[0].code,
+        "invalid_json"
+    );
+}
+
+#[test]
+fn test_validate_duplicate_hostnames() {
+    let body = json!({ "hosts": [host("a"), host("b"), host("a")] }).to_string();
+    let cluster = parse_cluster(&body, None).unwrap();
+    let errors = validate_cluster(&cluster).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/hosts/2/localization/hostname");
+    assert_eq!(errors[0].code, "duplicate_hostname");
+    assert!(errors[0].message.contains("'/hosts/0'"));
+}
+
+#[test]
+fn test_validate_hosts() {
+    let body = json!([host("a"), host("not a hostname")]).to_string();
+    let cluster = parse_cluster(&body, None).unwrap();
+    let errors = validate_cluster(&cluster).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/1/localization/hostname");
+}
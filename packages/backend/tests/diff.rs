@@ -0,0 +1,146 @@
+use backend::diff::{diff_configs, parse_diff_request, Change, ChangeKind};
+use backend::schema_types::Config;
+use serde_json::{json, Value};
+
+fn base() -> Value {
+    json!({
+        "localization": { "hostname": "node", "timezone": "UTC" },
+        "ssh": { "authorizedKeys": ["ssh-ed25519 AAAA a", "ssh-ed25519 BBBB b"] },
+        "execution": {
+            "geth": {
+                "enable": true,
+                "dataDir": "/var/lib/geth",
+                "endpoint": "http://127.0.0.1:8551"
+            }
+        },
+        "consensus": {
+            "lighthouse": {
+                "enable": true,
+                "dataDir": "/var/lib/lighthouse",
+                "endpoint": "http://127.0.0.1:5052",
+                "execEndpoint": "http://127.0.0.1:8551",
+                "extraOptions": ["--a", "--b"]
+            }
+        },
+        "mounts": {
+            "data": { "enable": true, "what": "/dev/sda1", "where": "/mnt/data", "type": "ext4" }
+        }
+    })
+}
+
+fn config(value: &Value) -> Config {
+    serde_json::from_value(value.clone()).unwrap()
+}
+
+fn kinds(changes: &[Change]) -> Vec<(ChangeKind, &str)> {
+    changes
+        .iter()
+        .map(|c| (c.kind, c.pointer.as_str()))
+        .collect()
+}
+
+#[test]
+fn test_no_changes() {
+    let diff = diff_configs(&config(&base()), &config(&base())).unwrap();
+    assert!(diff.is_empty());
+    assert_eq!(diff.default_nix, "");
+    assert_eq!(diff.to_string(), "No changes\n");
+}
+
+#[test]
+fn test_service_switches() {
+    let mut after = base();
+    after["execution"]["geth"]["enable"] = json!(false);
+    after["execution"]["erigon"] = json!({
+        "enable": true,
+        "dataDir": "/var/lib/erigon",
+        "endpoint": "http://127.0.0.1:8552"
+    });
+    let diff = diff_configs(&config(&base()), &config(&after)).unwrap();
+    let changes = kinds(&diff.changes);
+    assert_eq!(changes[0], (ChangeKind::Enabled, "/execution/erigon"));
+    assert_eq!(changes[1], (ChangeKind::Disabled, "/execution/geth"));
+    assert!(changes.contains(&(ChangeKind::Added, "/execution/erigon/endpoint")));
+}
+
+#[test]
+fn test_endpoints_mounts_and_options() {
+    let mut after = base();
+    after["consensus"]["lighthouse"]["execEndpoint"] = json!("http://192.168.1.2:8551");
+    after["consensus"]["lighthouse"]["extraOptions"] = json!(["--b", "--c"]);
+    after["mounts"] = json!({
+        "backup": { "enable": true, "what": "/dev/sdb1", "where": "/mnt/backup", "type": "ext4" }
+    });
+    after["localization"]["timezone"] = json!("Europe/Helsinki");
+    after["ssh"]["authorizedKeys"] = json!(["ssh-ed25519 BBBB b", "ssh-ed25519 AAAA a"]);
+
+    let diff = diff_configs(&config(&base()), &config(&after)).unwrap();
+    assert_eq!(
+        kinds(&diff.changes),
+        vec![
+            (
+                ChangeKind::EndpointChanged,
+                "/consensus/lighthouse/execEndpoint"
+            ),
+            (ChangeKind::MountAdded, "/mounts/backup"),
+            (ChangeKind::MountRemoved, "/mounts/data"),
+            (
+                ChangeKind::OptionAdded,
+                "/consensus/lighthouse/extraOptions"
+            ),
+            (
+                ChangeKind::OptionRemoved,
+                "/consensus/lighthouse/extraOptions"
+            ),
+            (ChangeKind::Changed, "/localization/timezone"),
+        ]
+    );
+    assert_eq!(diff.changes[3].after, Some(json!("--c")));
+    assert_eq!(diff.changes[4].before, Some(json!("--a")));
+
+    let text = diff.to_string();
+    assert!(text.contains(
+        "~ consensus.lighthouse.execEndpoint: \"http://127.0.0.1:8551\" -> \"http://192.168.1.2:8551\"\n"
+    ));
+    assert!(text.contains("+ mounts.backup added: \"/dev/sdb1\" on \"/mnt/backup\"\n"));
+    assert!(text.contains("- consensus.lighthouse.extraOptions: \"--a\"\n"));
+    assert!(text.contains("--- a/default.nix\n+++ b/default.nix\n"));
+}
+
+#[test]
+fn test_default_nix_line_diff() {
+    let mut after = base();
+    after["localization"]["timezone"] = json!("Europe/Helsinki");
+    let diff = diff_configs(&config(&base()), &config(&after)).unwrap();
+    let removed: Vec<&str> = diff
+        .default_nix
+        .lines()
+        .filter(|line| line.starts_with('-') && !line.starts_with("---"))
+        .collect();
+    let added: Vec<&str> = diff
+        .default_nix
+        .lines()
+        .filter(|line| line.starts_with('+') && !line.starts_with("+++"))
+        .collect();
+    assert_eq!(removed, vec!["-      timezone = \"UTC\";"]);
+    assert_eq!(added, vec!["+      timezone = \"Europe/Helsinki\";"]);
+}
+
+#[test]
+fn test_parse_diff_request() {
+    let body = json!({ "before": base(), "after": base() }).to_string();
+    assert!(parse_diff_request(&body, None).is_ok());
+
+    let mut broken = base();
+    broken["localization"]["hostname"] = json!(1);
+    let body = json!({ "before": base(), "after": broken }).to_string();
+    let errors = parse_diff_request(&body, None).unwrap_err();
+    assert!(errors
+        .iter()
+        .all(|e| e.pointer.starts_with("/after/localization/hostname")));
+
+    let errors = parse_diff_request(&json!({ "after": base() }).to_string(), None).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/before");
+    assert_eq!(errors[0].code, "required");
+}
@@ -1,3 +1,6 @@
+use backend::schema_types::Config;
+use backend::targets::BuildTarget;
+use backend::validation::ValidationError;
 use serde_json::Value;
 use std::fs;
 use std::io::Write;
@@ -5,8 +8,9 @@ use tempfile::{tempdir, NamedTempFile};
 
 // Import the helper functions from our library.
 use backend::{
-    compute_sha256, create_tarball, run_json2nix, run_nix_build, update_hostnames, update_schema,
-    write_default_nix, write_json_to_file,
+    cache_key, cluster_cache_key, compute_sha256, config_hash, create_tarball, default_nix,
+    run_nix_build, terminate_process_group, update_hostnames, update_schema, write_default_nix,
+    write_json_to_file,
 };
 
 #[test]
@@ -22,6 +26,62 @@ fn test_compute_sha256() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_cache_key() -> Result<(), Box<dyn std::error::Error>> {
+    let a: Config = serde_json::from_str(
+        r#"{"localization": {"hostname": "testi"}, "ssh": {"authorizedKeys": ["ssh-ed25519 AAAA"]}}"#,
+    )?;
+    let b: Config = serde_json::from_str(
+        r#"{"ssh": {"authorizedKeys": ["ssh-ed25519 AAAA"]}, "localization": {"hostname": "testi"}}"#,
+    )?;
+    let c: Config = serde_json::from_str(
+        r#"{"localization": {"hostname": "other"}, "ssh": {"authorizedKeys": ["ssh-ed25519 AAAA"]}}"#,
+    )?;
+
+    // Key order does not matter, but the config, the target, the flake and the lock file do.
+    assert_eq!(config_hash(&a)?, config_hash(&b)?);
+    assert_eq!(
+        cache_key(&a, BuildTarget::Kexec, "flake", None)?,
+        cache_key(&b, BuildTarget::Kexec, "flake", None)?
+    );
+    assert_ne!(
+        cache_key(&a, BuildTarget::Kexec, "flake", None)?,
+        cache_key(&c, BuildTarget::Kexec, "flake", None)?
+    );
+    assert_ne!(
+        cache_key(&a, BuildTarget::Kexec, "flake", None)?,
+        cache_key(&a, BuildTarget::Kexec, "other", None)?
+    );
+    assert_ne!(
+        cache_key(&a, BuildTarget::Kexec, "flake", None)?,
+        cache_key(&a, BuildTarget::Kexec, "flake", Some("lock"))?
+    );
+    assert_ne!(
+        cache_key(&a, BuildTarget::Kexec, "flake", None)?,
+        cache_key(&a, BuildTarget::Iso, "flake", None)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cluster_cache_key() -> Result<(), Box<dyn std::error::Error>> {
+    let host = |hostname: &str| -> Result<Config, serde_json::Error> {
+        serde_json::from_str(&format!(
+            r#"{{"localization": {{"hostname": "{hostname}"}}, "ssh": {{"authorizedKeys": []}}}}"#
+        ))
+    };
+    let (a, b, c) = (host("a")?, host("b")?, host("c")?);
+    let key = |others: &[&Config]| cluster_cache_key(&a, others, BuildTarget::Kexec, "flake", None);
+
+    // Alone, a host shares its builds with single-host requests.
+    assert_eq!(key(&[])?, cache_key(&a, BuildTarget::Kexec, "flake", None)?);
+    // The other hosts count, but not their order.
+    assert_eq!(key(&[&b, &c])?, key(&[&c, &b])?);
+    assert_ne!(key(&[&b, &c])?, key(&[&b])?);
+    assert_ne!(key(&[&b])?, key(&[])?);
+    Ok(())
+}
+
 #[test]
 fn test_write_default_nix() -> Result<(), Box<dyn std::error::Error>> {
     // Create a temporary directory and a subdirectory.
@@ -29,21 +89,28 @@ fn test_write_default_nix() -> Result<(), Box<dyn std::error::Error>> {
     let hostname_dir = dir.path().join("hostname");
     fs::create_dir_all(&hostname_dir)?;
 
-    // Write default.nix with dummy json2nix output.
-    let json2nix_output = "dummy_output";
-    write_default_nix(&hostname_dir, json2nix_output)?;
+    // Write default.nix with a dummy Nix expression.
+    let nix_expr = "dummy_output";
+    write_default_nix(&hostname_dir, nix_expr)?;
 
     // Read the generated default.nix content.
     let default_nix_path = hostname_dir.join("default.nix");
     let content = fs::read_to_string(default_nix_path)?;
 
     // Build expected content and compare.
-    let expected =
-        "{ pkgs, lib, config, ... }: { homestakeros = ".to_string() + json2nix_output + "; }";
+    let expected = "{ pkgs, lib, config, ... }:\n{\n  homestakeros = dummy_output;\n}\n";
     assert_eq!(content, expected);
     Ok(())
 }
 
+#[test]
+fn test_default_nix_indents_expression() {
+    assert_eq!(
+        default_nix("{\n  a = 1;\n}"),
+        "{ pkgs, lib, config, ... }:\n{\n  homestakeros = {\n    a = 1;\n  };\n}\n"
+    );
+}
+
 #[test]
 fn test_process_artifacts() -> Result<(), Box<dyn std::error::Error>> {
     let build_id = "test_build";
@@ -55,8 +122,9 @@ fn test_process_artifacts() -> Result<(), Box<dyn std::error::Error>> {
     fs::write(&file_path, "artifact content")?;
 
     // Call process_artifacts function.
-    let artifacts_info = backend::process_artifacts(output_dir.path(), build_id)
-        .map_err(|e| format!("process_artifacts failed: {}", e))?;
+    let artifacts_info =
+        backend::process_artifacts(output_dir.path(), build_id, BuildTarget::Netboot)
+            .map_err(|e| format!("process_artifacts failed: {}", e))?;
 
     // Check that the file was processed.
     let artifact = &artifacts_info[0];
@@ -71,6 +139,9 @@ fn test_process_artifacts() -> Result<(), Box<dyn std::error::Error>> {
     let expected_url = "/builds/".to_string() + build_id + "/" + "bzImage";
     assert_eq!(artifact["download_url"], expected_url);
 
+    // Verify the target tag.
+    assert_eq!(artifact["target"], "netboot");
+
     Ok(())
 }
 
@@ -101,19 +172,28 @@ async fn test_handle_error() {
     assert_eq!(json_val["error"], dummy_error);
 }
 
-#[test]
-fn test_run_json2nix() {
-    let input = r#"{"networking": { "hostName": "nixos", "firewall": {"enable":true, "allowedTCPPorts": [ 80, 443 ]} }}"#;
-    let result = run_json2nix(input);
-    match result {
-        Ok(output) => {
-            let expected = r#"{ networking = { firewall = { allowedTCPPorts = [ 80 443 ]; enable = true; }; hostName = "nixos"; }; }"#;
-            assert_eq!(output.trim(), expected.trim());
-        }
-        Err(err) => {
-            panic!("json2nix failed with error: {}", err);
-        }
-    }
+#[actix_web::test]
+async fn test_handle_validation_errors() {
+    let errors = vec![
+        ValidationError::new("/localization/hostname", "required", "Hostname is empty"),
+        ValidationError::new("/ssh/authorizedKeys", "required", "No keys"),
+    ];
+    let response = backend::handle_validation_errors("Failed to validate JSON", &errors);
+    assert_eq!(
+        response.status(),
+        actix_web::http::StatusCode::UNPROCESSABLE_ENTITY
+    );
+
+    let body = actix_web::body::to_bytes(response.into_body())
+        .await
+        .unwrap();
+    let json_val: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json_val["status"], "error");
+    assert_eq!(json_val["message"], "Failed to validate JSON");
+    assert_eq!(json_val["error"], "Hostname is empty; No keys");
+    assert_eq!(json_val["errors"][0]["pointer"], "/localization/hostname");
+    assert_eq!(json_val["errors"][0]["code"], "required");
+    assert_eq!(json_val["errors"][1]["message"], "No keys");
 }
 
 #[test]
@@ -145,26 +225,38 @@ fn test_run_nix_build() -> Result<(), Box<dyn std::error::Error>> {
 "#;
     fs::write(hostname_dir.join("default.nix"), default_nix_content)?;
 
-    // Create an output directory and define whitelist.
+    // Create an output directory.
     let output_dir = tmp_dir.path().join("output");
     fs::create_dir_all(&output_dir)?;
-    let whitelist = &["bzImage", "initrd.zst", "kexec-boot"];
 
     // Now call run_nix_build.
-    run_nix_build(&nix_config_dir, hostname, &output_dir, whitelist)
-        .map_err(|e| format!("run_nix_build failed: {}", e))?;
+    let mut log_lines = 0;
+    let out_link = nix_config_dir.join("result");
+    run_nix_build(
+        &nix_config_dir,
+        hostname,
+        BuildTarget::Kexec,
+        &out_link,
+        &output_dir,
+        |_| {},
+        |_| {
+            log_lines += 1;
+        },
+    )
+    .map_err(|e| format!("run_nix_build failed: {}", e))?;
+
+    // Nix should have reported its progress through the log callback.
+    assert!(log_lines > 0, "run_nix_build should forward the nix log");
 
     // Check if files were properly created in the output directory.
     let mut _found_files = false; // Prefixed with underscore to silence the unused variable warning
     if output_dir.exists() {
-        for entry in fs::read_dir(&output_dir)? {
-            if let Ok(entry) = entry {
-                if let Some(filename) = entry.file_name().to_str() {
-                    if whitelist.contains(&filename) {
-                        _found_files = true;
-                        println!("Found expected file: {}", filename);
-                        break;
-                    }
+        for entry in fs::read_dir(&output_dir)?.flatten() {
+            if let Some(filename) = entry.file_name().to_str() {
+                if BuildTarget::Kexec.whitelist().contains(&filename) {
+                    _found_files = true;
+                    println!("Found expected file: {}", filename);
+                    break;
                 }
             }
         }
@@ -173,6 +265,26 @@ fn test_run_nix_build() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_terminate_process_group() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::process::CommandExt;
+
+    // A shell in its own process group, with a child of its own.
+    let mut child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg("sleep 60 & wait")
+        .process_group(0)
+        .spawn()?;
+
+    terminate_process_group(child.id())?;
+    let status = child.wait()?;
+    assert!(
+        !status.success(),
+        "The process group should have been terminated"
+    );
+    Ok(())
+}
+
 #[test]
 fn test_create_tarball() -> Result<(), Box<dyn std::error::Error>> {
     // Create a temporary directory with a test file
@@ -316,7 +428,6 @@ fn test_update_schema() -> Result<(), Box<dyn std::error::Error>> {
     fs::write(nix_config_dir.join("flake.nix"), flake_contents)?;
 
     // Define the output path for options.json.
-    let modules_dir = nix_config_dir.join("nixosModules").join("homestakeros");
     let options_file = nix_config_dir.join("options.json");
 
     // Call update_schema.
@@ -0,0 +1,110 @@
+use backend::git_export::{ExportedBuild, GitExport, RepoOwner, BRANCH, BUNDLE_FILE};
+use backend::targets::BuildTarget;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::tempdir;
+
+fn build(build_id: &str) -> ExportedBuild<'_> {
+    ExportedBuild {
+        build_id,
+        hostname: "node",
+        config_hash: "abc123",
+        target: BuildTarget::Kexec,
+    }
+}
+
+/// Runs git on a repository and returns its output.
+fn git(repo: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn test_export_keeps_history() -> Result<(), Box<dyn std::error::Error>> {
+    let state = tempdir()?;
+    let export = GitExport::new(&state.path().join("repos"))?;
+    let flake = tempdir()?;
+    let output = tempdir()?;
+    let bundle = output.path().join(BUNDLE_FILE);
+
+    fs::create_dir_all(flake.path().join("nixosConfigurations/node"))?;
+    fs::write(flake.path().join("flake.nix"), "{ }")?;
+    fs::write(
+        flake.path().join("nixosConfigurations/node/default.nix"),
+        "a",
+    )?;
+    fs::write(flake.path().join("stale"), "b")?;
+    let owner = RepoOwner::Host("node");
+    let first = export.export(owner, flake.path(), &build("build-1"), &bundle)?;
+
+    fs::remove_file(flake.path().join("stale"))?;
+    fs::write(
+        flake.path().join("nixosConfigurations/node/default.nix"),
+        "c",
+    )?;
+    let second = export.export(owner, flake.path(), &build("build-2"), &bundle)?;
+
+    let repo = export.repo_path(owner)?;
+    assert_eq!(repo, state.path().join("repos/hosts/node.git"));
+    assert_eq!(git(&repo, &["rev-parse", BRANCH]).trim(), second);
+    assert_eq!(
+        git(&repo, &["rev-parse", &format!("{second}^")]).trim(),
+        first
+    );
+
+    let message = git(&repo, &["log", "-1", "--format=%B", &second]);
+    assert!(message.starts_with("Build build-2 of node\n"));
+    assert!(message.contains("Build-Id: build-2\nConfig-Hash: abc123\nTarget: kexec\n"));
+
+    let files = git(&repo, &["ls-tree", "-r", "--name-only", &second]);
+    assert_eq!(
+        files.lines().collect::<Vec<_>>(),
+        vec!["flake.nix", "nixosConfigurations/node/default.nix"]
+    );
+
+    // The bundle holds the whole history.
+    let clone = tempdir()?;
+    let status = Command::new("git")
+        .args(["clone", "--quiet"])
+        .arg(&bundle)
+        .arg(clone.path().join("node"))
+        .status()?;
+    assert!(status.success());
+    let cloned = clone.path().join("node");
+    assert_eq!(
+        fs::read_to_string(cloned.join("nixosConfigurations/node/default.nix"))?,
+        "c"
+    );
+    assert_eq!(
+        git(&cloned.join(".git"), &["rev-list", "--count", "HEAD"]).trim(),
+        "2"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_repositories_per_host_and_cluster() -> Result<(), Box<dyn std::error::Error>> {
+    let state = tempdir()?;
+    let export = GitExport::new(state.path())?;
+    assert_eq!(
+        export.repo_path(RepoOwner::Host("node"))?,
+        state.path().join("hosts/node.git")
+    );
+    assert_eq!(
+        export.repo_path(RepoOwner::Cluster("node"))?,
+        state.path().join("clusters/node.git")
+    );
+    assert!(export.repo_path(RepoOwner::Cluster("../escape")).is_err());
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use backend::import::{allowed_flake_dir, unpack_flake, MAX_TARBALL_ENTRIES, MAX_UNPACKED_SIZE};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use tar::{Builder, Header};
+use tempfile::tempdir;
+
+/// A tar archive of the given files.
+fn tarball(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut builder = Builder::new(Vec::new());
+    for (path, contents) in files {
+        let mut header = Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, path, contents.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+#[test]
+fn test_unpack_flake_at_top_level() {
+    let dir = tempdir().unwrap();
+    let archive = tarball(&[("flake.nix", "{ }"), ("hosts/a/default.nix", "{ }")]);
+    let flake_dir = unpack_flake(&archive, dir.path()).unwrap();
+    assert_eq!(flake_dir, dir.path());
+    assert!(flake_dir.join("hosts/a/default.nix").is_file());
+}
+
+#[test]
+fn test_unpack_gzipped_flake_in_directory() {
+    let dir = tempdir().unwrap();
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&tarball(&[("repo-main/flake.nix", "{ }")]))
+        .unwrap();
+    let archive = encoder.finish().unwrap();
+    let flake_dir = unpack_flake(&archive, dir.path()).unwrap();
+    assert_eq!(flake_dir, dir.path().join("repo-main"));
+}
+
+#[test]
+fn test_unpack_without_flake() {
+    let dir = tempdir().unwrap();
+    let archive = tarball(&[("a/flake.nix", "{ }"), ("b/flake.nix", "{ }")]);
+    let error = unpack_flake(&archive, dir.path()).unwrap_err();
+    assert!(error.to_string().contains("does not contain a flake.nix"));
+}
+
+#[test]
+fn test_unpack_invalid_archive() {
+    let dir = tempdir().unwrap();
+    assert!(unpack_flake(b"not a tarball", dir.path()).is_err());
+}
+
+#[test]
+fn test_unpack_too_many_entries() {
+    let dir = tempdir().unwrap();
+    let names: Vec<String> = (0..=MAX_TARBALL_ENTRIES).map(|i| format!("f{i}")).collect();
+    let files: Vec<(&str, &str)> = names.iter().map(|name| (name.as_str(), "")).collect();
+    let error = unpack_flake(&tarball(&files), dir.path()).unwrap_err();
+    assert!(error.to_string().contains("entries"));
+}
+
+#[test]
+fn test_unpack_too_large() {
+    // Only the header claims the size; it is rejected before any data is read.
+    let mut header = Header::new_gnu();
+    header.set_path("flake.nix").unwrap();
+    header.set_size(MAX_UNPACKED_SIZE + 1);
+    header.set_mode(0o644);
+    header.set_cksum();
+    let mut archive = header.as_bytes().to_vec();
+    archive.extend_from_slice(&[0; 1024]);
+
+    let dir = tempdir().unwrap();
+    let error = unpack_flake(&archive, dir.path()).unwrap_err();
+    assert!(error.to_string().contains("unpacks to more than"));
+    assert!(!dir.path().join("flake.nix").exists());
+}
+
+#[test]
+fn test_allowed_flake_dir() {
+    let root = tempdir().unwrap();
+    let other = tempdir().unwrap();
+    let flake = root.path().join("flake");
+    std::fs::create_dir(&flake).unwrap();
+    let roots = vec![root.path().canonicalize().unwrap()];
+
+    assert_eq!(
+        allowed_flake_dir(&flake, &roots).unwrap(),
+        flake.canonicalize().unwrap()
+    );
+    assert!(allowed_flake_dir(&flake.join(".."), &roots).is_ok());
+    assert!(allowed_flake_dir(&flake.join("../.."), &roots).is_err());
+    assert!(allowed_flake_dir(other.path(), &roots).is_err());
+    assert!(allowed_flake_dir(other.path(), &[]).is_err());
+    assert!(allowed_flake_dir(&root.path().join("missing"), &roots).is_err());
+}
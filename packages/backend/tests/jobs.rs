@@ -0,0 +1,395 @@
+use backend::jobs::{BuildJob, BuildStatus, CancelRequest, JobStore, MAX_LOG_BYTES, MAX_LOG_LINES};
+use backend::targets::BuildTarget;
+use serde_json::json;
+
+#[test]
+fn test_new_job_is_queued() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+
+    let job = jobs.get("test_build").expect("Job should be registered");
+    assert_eq!(job.status, BuildStatus::Queued);
+    assert_eq!(job.hostname, "testhost");
+    assert!(job.artifacts.is_empty());
+    assert!(job.error.is_none());
+}
+
+#[test]
+fn test_job_lifecycle() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+
+    jobs.set_status("test_build", BuildStatus::Evaluating);
+    assert_eq!(
+        jobs.get("test_build").unwrap().status,
+        BuildStatus::Evaluating
+    );
+
+    jobs.set_status("test_build", BuildStatus::Building);
+    assert!(!jobs.get("test_build").unwrap().status.is_finished());
+
+    let artifacts = vec![json!({ "file": "bzImage" })];
+    jobs.succeed("test_build", artifacts.clone());
+    let job = jobs.get("test_build").unwrap();
+    assert_eq!(job.status, BuildStatus::Succeeded);
+    assert!(job.status.is_finished());
+    assert_eq!(job.artifacts, artifacts);
+}
+
+#[test]
+fn test_failed_job_reports_error() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    jobs.fail("test_build", "nix build failed");
+
+    // The status is serialized in lowercase for the status API.
+    let job = serde_json::to_value(jobs.get("test_build").unwrap()).unwrap();
+    assert_eq!(job["status"], "failed");
+    assert_eq!(job["error"], "nix build failed");
+}
+
+#[test]
+fn test_unknown_job() {
+    let jobs = JobStore::new();
+    assert!(jobs.get("missing").is_none());
+}
+
+#[test]
+fn test_log_replay_and_live_lines() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    jobs.append_log("test_build", "first");
+
+    // Late subscribers get the log so far and then the new lines.
+    let (replay, mut rx) = jobs.subscribe("test_build").expect("Job should exist");
+    assert_eq!(replay, vec!["first".to_string()]);
+
+    jobs.append_log("test_build", "second");
+    assert_eq!(rx.try_recv().unwrap(), "second");
+
+    // Finishing the job closes the stream.
+    jobs.succeed("test_build", Vec::new());
+    assert!(rx.try_recv().is_err());
+    assert!(rx.is_closed());
+}
+
+#[test]
+fn test_subscribe_to_finished_job() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    jobs.append_log("test_build", "error: build failed");
+    jobs.fail("test_build", "nix build failed");
+
+    let (replay, rx) = jobs.subscribe("test_build").expect("Job should exist");
+    assert_eq!(replay, vec!["error: build failed".to_string()]);
+    assert!(rx.is_closed());
+    assert!(jobs.subscribe("missing").is_none());
+}
+
+#[test]
+fn test_log_keeps_latest_lines() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    for i in 0..MAX_LOG_LINES + 5 {
+        jobs.append_log("test_build", &format!("line {i}"));
+    }
+    let (replay, _rx) = jobs.subscribe("test_build").expect("Job should exist");
+    assert_eq!(replay.len(), MAX_LOG_LINES + 1);
+    assert_eq!(replay[0], "[5 earlier log lines were dropped]");
+    assert_eq!(replay[1], "line 5");
+    assert_eq!(replay[MAX_LOG_LINES], format!("line {}", MAX_LOG_LINES + 4));
+
+    // Long lines are limited by their size as well.
+    let long = "x".repeat(MAX_LOG_BYTES / 2);
+    for _ in 0..3 {
+        jobs.append_log("test_build", &long);
+    }
+    let (replay, _rx) = jobs.subscribe("test_build").expect("Job should exist");
+    assert_eq!(replay.len(), 3);
+    assert_eq!(
+        replay[0],
+        format!("[{} earlier log lines were dropped]", MAX_LOG_LINES + 6)
+    );
+}
+
+#[test]
+fn test_index_survives_restart() -> Result<(), Box<dyn std::error::Error>> {
+    let state_dir = tempfile::tempdir()?;
+    let index_path = state_dir.path().join("builds.json");
+
+    {
+        let jobs = JobStore::open(&index_path)?;
+        jobs.insert(BuildJob::new(
+            "done",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec,
+        ));
+        jobs.succeed("done", vec![json!({ "file": "bzImage" })]);
+        jobs.insert(BuildJob::new(
+            "running",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec,
+        ));
+        jobs.set_status("running", BuildStatus::Building);
+    }
+    assert!(index_path.exists());
+
+    // Reopening the index restores finished builds as they were.
+    let jobs = JobStore::open(&index_path)?;
+    let done = jobs.get("done").expect("Finished build should be restored");
+    assert_eq!(done.status, BuildStatus::Succeeded);
+    assert_eq!(done.config_hash, "hash");
+    assert_eq!(done.artifacts, vec![json!({ "file": "bzImage" })]);
+
+    // Builds that were still running cannot resume, so they are marked as failed.
+    let running = jobs
+        .get("running")
+        .expect("Running build should be restored");
+    assert_eq!(running.status, BuildStatus::Failed);
+    assert!(running.error.is_some());
+
+    assert_eq!(jobs.list().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_insert_unless_cached() {
+    let jobs = JobStore::new();
+    assert!(jobs
+        .insert_unless_cached(BuildJob::new(
+            "first",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec
+        ))
+        .is_none());
+
+    // An identical request while the first build is running shares it.
+    let cached = jobs
+        .insert_unless_cached(BuildJob::new(
+            "second",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec,
+        ))
+        .expect("In-flight build should be reused");
+    assert_eq!(cached.build_id, "first");
+    assert!(jobs.get("second").is_none());
+
+    // A finished build is reused along with its artifacts.
+    jobs.succeed("first", vec![json!({ "file": "bzImage" })]);
+    let cached = jobs
+        .insert_unless_cached(BuildJob::new(
+            "third",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec,
+        ))
+        .expect("Finished build should be reused");
+    assert_eq!(cached.artifacts, vec![json!({ "file": "bzImage" })]);
+
+    // A different key starts a new build.
+    assert!(jobs
+        .insert_unless_cached(BuildJob::new(
+            "other",
+            "testhost",
+            "hash",
+            "other",
+            BuildTarget::Kexec
+        ))
+        .is_none());
+}
+
+#[test]
+fn test_failed_build_is_not_cached() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "first",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    jobs.fail("first", "nix build failed");
+
+    assert!(jobs
+        .insert_unless_cached(BuildJob::new(
+            "retry",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec
+        ))
+        .is_none());
+    assert!(jobs.get("retry").is_some());
+}
+
+#[test]
+fn test_remove_if_unchanged() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    let selected = jobs.get("test_build").unwrap();
+
+    // A job that changed since it was looked at is kept.
+    jobs.set_status("test_build", BuildStatus::Building);
+    assert!(jobs
+        .remove_if("test_build", |job| job.status == selected.status)
+        .is_none());
+    assert!(jobs.get("test_build").is_some());
+
+    assert!(jobs
+        .remove_if("test_build", |job| job.status == BuildStatus::Building)
+        .is_some());
+    assert!(jobs.get("test_build").is_none());
+    assert!(jobs.remove_if("test_build", |_| true).is_none());
+}
+
+#[test]
+fn test_cancel_running_job() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    jobs.set_status("test_build", BuildStatus::Building);
+    assert!(!jobs.set_pid("test_build", Some(1234)));
+
+    // The running process group is handed back so that it can be terminated.
+    assert_eq!(
+        jobs.request_cancel("test_build"),
+        CancelRequest::Requested(Some(1234))
+    );
+    assert!(jobs.is_cancel_requested("test_build"));
+
+    jobs.cancel("test_build");
+    let job = jobs.get("test_build").unwrap();
+    assert_eq!(job.status, BuildStatus::Cancelled);
+    assert!(job.status.is_finished());
+
+    // Cancelled builds are never reused.
+    assert!(jobs
+        .insert_unless_cached(BuildJob::new(
+            "retry",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec
+        ))
+        .is_none());
+}
+
+#[test]
+fn test_cancelling_build_is_not_cached() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "first",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    jobs.set_status("first", BuildStatus::Building);
+    assert_eq!(jobs.request_cancel("first"), CancelRequest::Requested(None));
+
+    // The build is still running, but a resubmission starts a new one.
+    assert!(jobs
+        .insert_unless_cached(BuildJob::new(
+            "retry",
+            "testhost",
+            "hash",
+            "key",
+            BuildTarget::Kexec
+        ))
+        .is_none());
+    assert_eq!(jobs.get("retry").unwrap().status, BuildStatus::Queued);
+}
+
+#[test]
+fn test_cancel_before_spawn() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    assert_eq!(
+        jobs.request_cancel("test_build"),
+        CancelRequest::Requested(None)
+    );
+
+    // A process started after the request must be terminated by its starter.
+    assert!(jobs.set_pid("test_build", Some(1234)));
+}
+
+#[test]
+fn test_cancel_finished_or_unknown_job() {
+    let jobs = JobStore::new();
+    jobs.insert(BuildJob::new(
+        "test_build",
+        "testhost",
+        "hash",
+        "key",
+        BuildTarget::Kexec,
+    ));
+    jobs.succeed("test_build", Vec::new());
+
+    assert_eq!(
+        jobs.request_cancel("test_build"),
+        CancelRequest::Finished(BuildStatus::Succeeded)
+    );
+    assert_eq!(jobs.request_cancel("missing"), CancelRequest::NotFound);
+}
@@ -1,5 +1,6 @@
 use backend::schema_types::Config;
 use backend::validate_config;
+use backend::validation::parse_config;
 use serde_json::Error;
 
 #[test]
@@ -45,7 +46,7 @@ fn test_invalid_types() {
 #[test]
 fn test_unknown_fields() {
     // This JSON contains an extra field "extra" in configuration root,
-    // which should be rejected due to #[serde(deny_unknown_fields)].
+    // which should be rejected as no options schema vouches for it.
     let json_str = r#"
     {
         "localization": {
@@ -59,17 +60,17 @@ fn test_unknown_fields() {
         }
     }
     "#;
-    let config: Result<Config, Error> = serde_json::from_str(json_str);
+    let config = parse_config(json_str, None);
     assert!(
         config.is_err(),
-        "Deserialization should fail due to unknown block 'extra'"
+        "Parsing should fail due to unknown block 'extra'"
     );
 }
 
 #[test]
 fn test_unknown_nested_fields() {
     // This JSON contains an extra field "extra" in the localization block,
-    // which should be rejected due to #[serde(deny_unknown_fields)].
+    // which should be rejected as no options schema vouches for it.
     let json_str = r#"
     {
         "localization": {
@@ -81,10 +82,10 @@ fn test_unknown_nested_fields() {
         }
     }
     "#;
-    let config: Result<Config, Error> = serde_json::from_str(json_str);
+    let config = parse_config(json_str, None);
     assert!(
         config.is_err(),
-        "Deserialization should fail due to unknown field 'extra'"
+        "Parsing should fail due to unknown field 'extra'"
     );
 }
 
@@ -166,7 +167,7 @@ fn test_empty_hostname() {
         "Configuration with empty hostname should fail validation"
     );
     assert_eq!(
-        result.unwrap_err(),
+        result.unwrap_err()[0].message,
         "The 'localization.hostname' must not be empty"
     );
 }
@@ -192,7 +193,7 @@ fn test_empty_authorized_keys() {
         "Configuration with empty authorizedKeys should fail validation"
     );
     assert_eq!(
-        result.unwrap_err(),
+        result.unwrap_err()[0].message,
         "The 'ssh.authorizedKeys' must contain at least one key"
     );
 }
@@ -218,7 +219,7 @@ fn test_authorized_keys_contains_empty_string() {
         "Configuration with an empty string in authorizedKeys should fail validation"
     );
     assert_eq!(
-        result.unwrap_err(),
+        result.unwrap_err()[0].message,
         "The 'ssh.authorizedKeys' must not contain an empty key"
     );
 }
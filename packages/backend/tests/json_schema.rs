@@ -0,0 +1,96 @@
+use backend::schema_types::config_json_schema;
+use serde_json::{json, Value};
+
+fn config() -> Value {
+    json!({
+        "localization": { "hostname": "example", "timezone": "Europe/Helsinki" },
+        "ssh": { "authorizedKeys": ["ssh-ed25519 AAAA user@host"] },
+        "execution": {
+            "geth": {
+                "enable": true,
+                "endpoint": "http://127.0.0.1:8551",
+                "dataDir": "/mnt/eth/geth"
+            }
+        },
+        "mounts": {
+            "eth": {
+                "enable": true,
+                "type": "ext4",
+                "what": "/dev/sda1",
+                "where": "/mnt/eth",
+                "overrideStrategy": "asDropin"
+            }
+        }
+    })
+}
+
+/// The problems the JSON Schema finds in `instance`, as instance paths.
+fn problems(instance: &Value) -> Vec<String> {
+    let validator = jsonschema::draft202012::new(&config_json_schema())
+        .expect("The JSON Schema should be valid");
+    validator
+        .iter_errors(instance)
+        .map(|e| e.instance_path.to_string())
+        .collect()
+}
+
+#[test]
+fn test_schema_header() {
+    let schema = config_json_schema();
+    assert_eq!(
+        schema["$schema"],
+        "https://json-schema.org/draft/2020-12/schema"
+    );
+    assert_eq!(schema["required"], json!(["localization", "ssh"]));
+    assert_eq!(schema["additionalProperties"], json!(false));
+    assert_eq!(
+        schema["$defs"]["Localization"]["properties"]["hostname"]["description"],
+        "The name of the machine."
+    );
+}
+
+#[test]
+fn test_valid_config() {
+    assert!(problems(&config()).is_empty());
+}
+
+#[test]
+fn test_required_fields() {
+    let mut config = config();
+    config["localization"]
+        .as_object_mut()
+        .unwrap()
+        .remove("hostname");
+    config.as_object_mut().unwrap().remove("ssh");
+    let mut problems = problems(&config);
+    problems.sort();
+    assert_eq!(problems, vec!["", "/localization"]);
+}
+
+#[test]
+fn test_unknown_fields() {
+    let mut config = config();
+    config["ssh"]["extra"] = json!(true);
+    config["mounts"]["eth"]["extra"] = json!(true);
+    let mut problems = problems(&config);
+    problems.sort();
+    assert_eq!(problems, vec!["/mounts/eth", "/ssh"]);
+}
+
+#[test]
+fn test_types_and_enums() {
+    let mut config = config();
+    config["mounts"]["eth"]["enable"] = json!("yes");
+    config["mounts"]["eth"]["overrideStrategy"] = json!("replace");
+    config["mounts"]["eth"]["startLimitBurst"] = json!(-1);
+    let mut problems = problems(&config);
+    problems.sort();
+    assert_eq!(
+        problems,
+        vec![
+            "/mounts/eth/enable",
+            "/mounts/eth/overrideStrategy",
+            "/mounts/eth/startLimitBurst",
+        ]
+    );
+}
@@ -0,0 +1,135 @@
+use backend::migrations::{migrate, migrate_file, CURRENT_VERSION};
+use backend::validation::parse_config;
+use serde_json::{json, Value};
+use std::fs;
+
+/// A config from before the format was versioned.
+fn unversioned() -> Value {
+    json!({
+        "localization": { "hostname": "example" },
+        "ssh": { "authorizedKeys": ["ssh-ed25519 AAAA user@host"] },
+        "mounts": {
+            "eth": {
+                "enable": true,
+                "type": "ext4",
+                "what": "/dev/sda1",
+                "where": "/mnt/eth",
+                "unit_config": "DefaultDependencies=no\nRequiresMountsFor = /mnt",
+                "part_of": ["local-fs.target"]
+            }
+        }
+    })
+}
+
+/// The unversioned config with the given `unit_config`.
+fn unversioned_with_unit_config(unit_config: &str) -> String {
+    let mut document = unversioned();
+    document["mounts"]["eth"]["unit_config"] = json!(unit_config);
+    document.to_string()
+}
+
+#[test]
+fn test_migrate_unversioned() {
+    let mut document = unversioned();
+    let migrated = migrate(&mut document).unwrap();
+    assert_eq!(migrated.from_version, 0);
+    assert_eq!(migrated.to_version, CURRENT_VERSION);
+    assert_eq!(document["version"], json!(CURRENT_VERSION));
+    assert_eq!(
+        document["mounts"]["eth"]["unitConfig"],
+        json!({ "DefaultDependencies": "no", "RequiresMountsFor": "/mnt" })
+    );
+    assert_eq!(
+        document["mounts"]["eth"]["partOf"],
+        json!(["local-fs.target"])
+    );
+    assert!(document["mounts"]["eth"].get("unit_config").is_none());
+
+    let pointers: Vec<&str> = migrated
+        .changes
+        .iter()
+        .map(|c| c.pointer.as_str())
+        .collect();
+    assert_eq!(
+        pointers,
+        vec!["/mounts/eth/unitConfig", "/mounts/eth/partOf", "/version"]
+    );
+    assert_eq!(
+        migrated.changes[0].message,
+        "Renamed 'mounts.eth.unit_config' to 'mounts.eth.unitConfig', one entry per line"
+    );
+    assert_eq!(
+        migrated.changes[1].message,
+        "Renamed 'mounts.eth.part_of' to 'mounts.eth.partOf'"
+    );
+
+    // A value that is not made of entries is only renamed, and left for validation to reject.
+    let mut document = unversioned();
+    document["mounts"]["eth"]["unit_config"] = json!("x");
+    migrate(&mut document).unwrap();
+    assert_eq!(document["mounts"]["eth"]["unitConfig"], "x");
+    let errors = parse_config(&unversioned_with_unit_config("x"), None).unwrap_err();
+    assert_eq!(errors[0].pointer, "/mounts/eth/unitConfig");
+    assert_eq!(errors[0].code, "invalid_type");
+}
+
+#[test]
+fn test_migrate_current_is_unchanged() {
+    let mut document = unversioned();
+    migrate(&mut document).unwrap();
+    let before = document.clone();
+    let migrated = migrate(&mut document).unwrap();
+    assert!(migrated.is_unchanged());
+    assert_eq!(document, before);
+}
+
+#[test]
+fn test_migrate_rejects_bad_versions() {
+    let mut document = unversioned();
+    document["version"] = json!(CURRENT_VERSION + 1);
+    let error = migrate(&mut document).unwrap_err();
+    assert_eq!(error.pointer, "/version");
+    assert_eq!(error.code, "unsupported_version");
+
+    document["version"] = json!("1");
+    assert_eq!(migrate(&mut document).unwrap_err().code, "invalid_version");
+    assert_eq!(migrate(&mut json!([])).unwrap_err().code, "invalid_type");
+}
+
+#[test]
+fn test_parse_config_upgrades_old_documents() {
+    let config = parse_config(&unversioned().to_string(), None).unwrap();
+    assert_eq!(config.version, CURRENT_VERSION);
+    let mount = &config.mounts.as_ref().unwrap()["eth"];
+    let unit_config = mount.unit_config.as_ref().unwrap();
+    assert_eq!(unit_config["DefaultDependencies"], "no");
+
+    // The version is part of the document, but not an option of the module.
+    let options = config.module_options().unwrap();
+    assert!(options.get("version").is_none());
+    assert_eq!(
+        serde_json::to_value(&config).unwrap()["version"],
+        json!(CURRENT_VERSION)
+    );
+}
+
+#[test]
+fn test_migrate_file() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("default.json");
+    fs::write(&path, unversioned().to_string())?;
+
+    // A check leaves the file alone.
+    let migrated = migrate_file(&path, false)?;
+    assert!(!migrated.is_unchanged());
+    assert_eq!(fs::read_to_string(&path)?, unversioned().to_string());
+
+    let migrated = migrate_file(&path, true)?;
+    assert_eq!(migrated.changes.len(), 3);
+    let rewritten: Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    assert_eq!(rewritten["version"], json!(CURRENT_VERSION));
+    assert!(!dir.path().join("default.json.tmp").exists());
+
+    assert!(migrate_file(&path, true)?.is_unchanged());
+    Ok(())
+}
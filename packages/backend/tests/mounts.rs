@@ -0,0 +1,161 @@
+use backend::mounts::{escape_path, mount_errors, mount_unit_name};
+use backend::schema_types::{Config, Mount};
+use backend::validation::semantic_errors;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+fn mounts(value: Value) -> HashMap<String, Mount> {
+    serde_json::from_value(value).expect("Mounts should deserialize")
+}
+
+fn eth() -> Value {
+    json!({
+        "enable": true,
+        "type": "ext4",
+        "what": "/dev/disk/by-label/eth",
+        "where": "/mnt/eth",
+        "wantedBy": ["multi-user.target"],
+        "before": ["geth.service"]
+    })
+}
+
+/// The pointers and codes of the problems with `value`, with `geth.service` generated.
+fn problems(value: Value) -> Vec<(String, &'static str)> {
+    let mounts = mounts(value);
+    let mut problems: Vec<(String, &'static str)> =
+        mount_errors(Some(&mounts), &["geth.service".to_string()])
+            .into_iter()
+            .map(|e| (e.pointer, e.code))
+            .collect();
+    problems.sort();
+    problems
+}
+
+#[test]
+fn test_escape_path() {
+    assert_eq!(escape_path("/mnt/eth"), "mnt-eth");
+    assert_eq!(escape_path("/mnt//eth/"), "mnt-eth");
+    assert_eq!(escape_path("/"), "-");
+    assert_eq!(escape_path("/mnt/eth-data"), "mnt-eth\\x2ddata");
+    assert_eq!(escape_path("/.hidden/a b"), "\\x2ehidden-a\\x20b");
+    assert_eq!(mount_unit_name("/var/lib/geth"), "var-lib-geth.mount");
+}
+
+#[test]
+fn test_valid_mounts() {
+    let mut ssd = eth();
+    ssd["what"] = json!("UUID=8f3a2c1e-7d4b-4f1a-9c2e-5b6d7e8f9a0b");
+    ssd["where"] = json!("/mnt/ssd");
+    ssd["name"] = json!("mnt-ssd.mount");
+    ssd["after"] = json!(["mnt-eth.mount", "dev-sda1.device"]);
+    let tmp = json!({ "enable": true, "type": "tmpfs", "what": "tmpfs", "where": "/tmp/x" });
+    let nfs = json!({ "enable": true, "type": "nfs4", "what": "nas:/export", "where": "/mnt/nas" });
+    assert!(problems(json!({ "eth": eth(), "ssd": ssd, "tmp": tmp, "nfs": nfs })).is_empty());
+}
+
+#[test]
+fn test_relative_mount_point() {
+    let mut mount = eth();
+    mount["where"] = json!("mnt/eth");
+    assert_eq!(
+        problems(json!({ "eth": mount })),
+        vec![("/mounts/eth/where".to_string(), "relative_mount_point")]
+    );
+}
+
+#[test]
+fn test_invalid_source() {
+    for what in ["sda1", "UUID=", ""] {
+        let mut mount = eth();
+        mount["what"] = json!(what);
+        assert_eq!(
+            problems(json!({ "eth": mount })),
+            vec![("/mounts/eth/what".to_string(), "invalid_mount_source")],
+            "{what:?}"
+        );
+    }
+}
+
+#[test]
+fn test_unknown_fs_type() {
+    let mut mount = eth();
+    mount["type"] = json!("ext5");
+    assert_eq!(
+        problems(json!({ "eth": mount })),
+        vec![("/mounts/eth/type".to_string(), "unknown_fs_type")]
+    );
+}
+
+#[test]
+fn test_duplicate_mount_point() {
+    let mut other = eth();
+    other["where"] = json!("/mnt/eth/");
+    let mut disabled = eth();
+    disabled["enable"] = json!(false);
+    let errors = mount_errors(
+        Some(&mounts(json!({ "a": eth(), "b": other, "c": disabled }))),
+        &["geth.service".to_string()],
+    );
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/mounts/b/where");
+    assert_eq!(errors[0].code, "duplicate_mount_point");
+    assert!(errors[0].message.ends_with("'mounts.a'"));
+}
+
+#[test]
+fn test_unknown_units() {
+    let mut mount = eth();
+    mount["wantedBy"] = json!(["multi-user.target", "multiuser.target"]);
+    mount["before"] = json!(["lighthouse.service"]);
+    mount["requires"] = json!(["mnt-other.mount"]);
+    assert_eq!(
+        problems(json!({ "eth": mount })),
+        vec![
+            ("/mounts/eth/before/0".to_string(), "unknown_unit"),
+            ("/mounts/eth/requires/0".to_string(), "unknown_unit"),
+            ("/mounts/eth/wantedBy/1".to_string(), "unknown_unit"),
+        ]
+    );
+}
+
+#[test]
+fn test_unit_name_mismatch() {
+    let mut mount = eth();
+    mount["name"] = json!("eth.mount");
+    let errors = mount_errors(Some(&mounts(json!({ "eth": mount }))), &[]);
+    let mismatch = errors
+        .iter()
+        .find(|e| e.code == "mount_unit_name_mismatch")
+        .expect("The name should be rejected");
+    assert_eq!(mismatch.pointer, "/mounts/eth/name");
+    assert!(mismatch.message.contains("'mnt-eth.mount'"));
+}
+
+#[test]
+fn test_generated_services() {
+    // Units of enabled clients and addons are part of the generated system.
+    let config: Config = serde_json::from_value(json!({
+        "localization": { "hostname": "example" },
+        "ssh": { "authorizedKeys": [] },
+        "execution": {
+            "geth": { "enable": true, "endpoint": "http://127.0.0.1:8551", "dataDir": "/mnt/eth/geth" }
+        },
+        "vpn": { "wireguard": { "enable": true, "configFile": "/mnt/secrets/wg0.conf" } },
+        "mounts": {
+            "eth": {
+                "enable": true,
+                "type": "ext4",
+                "what": "/dev/sda1",
+                "where": "/mnt/eth",
+                "before": ["geth.service", "wg-quick-wg0.service", "sshd.service"],
+                "after": ["lighthouse.service"]
+            }
+        }
+    }))
+    .unwrap();
+    let pointers: Vec<String> = semantic_errors(&config)
+        .into_iter()
+        .map(|e| e.pointer)
+        .collect();
+    assert_eq!(pointers, vec!["/mounts/eth/after/0"]);
+}
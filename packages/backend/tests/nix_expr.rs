@@ -0,0 +1,122 @@
+use backend::nix_expr::{to_nix, to_nix_pretty};
+use serde_json::{json, Value};
+use std::process::Command;
+
+#[test]
+fn test_attrs_and_lists() {
+    let input = json!({"networking": { "hostName": "nixos", "firewall": {"enable":true, "allowedTCPPorts": [ 80, 443 ]} }});
+    let expected = r#"{ networking = { firewall = { allowedTCPPorts = [ 80 443 ]; enable = true; }; hostName = "nixos"; }; }"#;
+    assert_eq!(to_nix(&input), expected);
+}
+
+#[test]
+fn test_deterministic_key_order() {
+    let a: Value = serde_json::from_str(r#"{"b": 1, "a": 2, "c": {"z": 1, "y": 2}}"#).unwrap();
+    let b: Value = serde_json::from_str(r#"{"c": {"y": 2, "z": 1}, "a": 2, "b": 1}"#).unwrap();
+    assert_eq!(to_nix(&a), to_nix(&b));
+    assert_eq!(to_nix(&a), "{ a = 2; b = 1; c = { y = 2; z = 1; }; }");
+}
+
+#[test]
+fn test_string_escaping() {
+    // Values that broke the json2nix shell-out: Nix indented-string terminators and interpolation.
+    let input = json!({
+        "interpolation": "${pkgs.hello}",
+        "indented": "it''s",
+        "quotes": "say \"hi\"",
+        "backslash": "C:\\path",
+        "whitespace": "a\nb\tc\r",
+        "dollar": "$HOME and $ {x}"
+    });
+    let expected = concat!(
+        r#"{ backslash = "C:\\path"; dollar = "$HOME and $ {x}"; indented = "it''s"; "#,
+        r#"interpolation = "\${pkgs.hello}"; quotes = "say \"hi\""; whitespace = "a\nb\tc\r"; }"#
+    );
+    assert_eq!(to_nix(&input), expected);
+}
+
+#[test]
+fn test_attribute_name_quoting() {
+    let input = json!({
+        "mev-boost": {},
+        "with": true,
+        "var-lib-ethereum.mount": {},
+        "1st": 1,
+        "": null
+    });
+    let expected = r#"{ "" = null; "1st" = 1; mev-boost = { }; "var-lib-ethereum.mount" = { }; "with" = true; }"#;
+    assert_eq!(to_nix(&input), expected);
+}
+
+#[test]
+fn test_numbers() {
+    let input = json!({ "int": -3, "float": 1.5, "list": [-1, 2, -0.5], "big": 1e20 });
+    let expected = "{ big = 1.0e20; float = 1.5; int = -3; list = [ (-1) 2 (-0.5) ]; }";
+    assert_eq!(to_nix(&input), expected);
+}
+
+#[test]
+fn test_empty_containers() {
+    assert_eq!(to_nix(&json!({})), "{ }");
+    assert_eq!(to_nix(&json!([])), "[ ]");
+    assert_eq!(to_nix(&json!({ "a": [] })), "{ a = [ ]; }");
+}
+
+#[test]
+fn test_pretty_layout() {
+    let input = json!({
+        "b": { "enable": true, "extraOptions": ["--a", "--b"] },
+        "a": [-1, 2],
+        "empty": { "attrs": {}, "list": [] }
+    });
+    let expected = r#"{
+  a = [
+    (-1)
+    2
+  ];
+  b = {
+    enable = true;
+    extraOptions = [
+      "--a"
+      "--b"
+    ];
+  };
+  empty = {
+    attrs = { };
+    list = [ ];
+  };
+}"#;
+    assert_eq!(to_nix_pretty(&input), expected);
+    assert_eq!(to_nix_pretty(&json!("a\nb")), to_nix(&json!("a\nb")));
+}
+
+#[test]
+fn test_round_trip_through_nix() {
+    // Evaluate the rendered expression with nix and compare it to the original JSON.
+    let input = json!({
+        "localization": { "hostname": "testi", "timezone": "Europe/Helsinki" },
+        "ssh": { "authorizedKeys": ["ssh-ed25519 AAAAC3Nza... user@host"] },
+        "addons": { "mev-boost": { "enable": true, "endpoint": "http://192.168.100.10:18550",
+                    "extraOptions": ["--relay ${RELAY}", "it''s", "\"quoted\" \\ back"] } },
+        "mounts": { "var-lib-ethereum": { "enable": true, "what": "/dev/disk/by-label/ethereum",
+                    "where": "/var/lib/ethereum", "type": "btrfs", "description": "line1\nline2" } },
+        "numbers": [-1, 0, 1.25]
+    });
+    for expr in [to_nix(&input), to_nix_pretty(&input)] {
+        let output = Command::new("nix-instantiate")
+            .arg("--eval")
+            .arg("--strict")
+            .arg("--json")
+            .arg("--expr")
+            .arg(expr)
+            .output()
+            .expect("Failed to run nix-instantiate");
+        assert!(
+            output.status.success(),
+            "nix-instantiate failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let round_trip: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(round_trip, input);
+    }
+}
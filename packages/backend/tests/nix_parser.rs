@@ -0,0 +1,260 @@
+use backend::default_nix;
+use backend::nix_expr::{to_nix, to_nix_pretty};
+use backend::nix_parser::{from_nix, parse_default_nix};
+use backend::schema_types::Config;
+use serde_json::json;
+
+#[test]
+fn test_round_trip_through_renderers() {
+    let input = json!({
+        "localization": { "hostname": "testi", "timezone": "Europe/Helsinki" },
+        "addons": { "mev-boost": { "enable": true, "endpoint": "http://192.168.100.10:18550",
+                    "extraOptions": ["--relay ${RELAY}", "it''s", "\"quoted\" \\ back", "$${a}"] } },
+        "mounts": { "var-lib-ethereum": { "enable": false, "description": "line1\nline2\ttab" } },
+        "numbers": [-1, 0, 1.25, -2.5e-3],
+        "let": null,
+        "empty": { "attrs": {}, "list": [] }
+    });
+    assert_eq!(from_nix(&to_nix(&input)).unwrap(), input);
+    assert_eq!(from_nix(&to_nix_pretty(&input)).unwrap(), input);
+}
+
+#[test]
+fn test_hand_written_expression() {
+    let src = r#"
+        # The consensus client.
+        {
+          consensus.lighthouse = {
+            enable = true; /* switched on
+                              last week */
+            extraOptions = [ "--a" ("--b") ];
+          };
+          consensus.lighthouse.dataDir = "/var/lib/lighthouse";
+          mounts."var-lib" = {
+            description = ''
+              Data of the clients,
+                indented
+            '';
+            options = ''a ''${b} '''c''' ''\t'';
+          };
+        }
+    "#;
+    assert_eq!(
+        from_nix(src).unwrap(),
+        json!({
+            "consensus": { "lighthouse": {
+                "enable": true,
+                "extraOptions": ["--a", "--b"],
+                "dataDir": "/var/lib/lighthouse"
+            } },
+            "mounts": { "var-lib": {
+                "description": "Data of the clients,\n  indented\n",
+                "options": "a ${b} ''c'' \t"
+            } }
+        })
+    );
+}
+
+#[test]
+fn test_constructs_that_need_evaluation() {
+    let cases = [
+        ("{ a = lib.mkForce true; }", 1, 7, "'lib' needs evaluation"),
+        (
+            "{\n  a = let b = 1; in b;\n}",
+            2,
+            7,
+            "'let' expressions need evaluation",
+        ),
+        (
+            "{ a = \"${b}\"; }",
+            1,
+            8,
+            "String interpolation needs evaluation",
+        ),
+        (
+            "{ a = ''x ${b}''; }",
+            1,
+            11,
+            "String interpolation needs evaluation",
+        ),
+        (
+            "rec { a = 1; }",
+            1,
+            1,
+            "Recursive attribute sets need evaluation",
+        ),
+        ("{ inherit a; }", 1, 3, "'inherit' needs evaluation"),
+        (
+            "{ a = { } // { }; }",
+            1,
+            11,
+            "The operator '//' needs evaluation",
+        ),
+        (
+            "[ \"a\" ] ++ [ ]",
+            1,
+            9,
+            "The operator '++' needs evaluation",
+        ),
+        ("{ a = ./secret; }", 1, 7, "Paths need evaluation"),
+        ("{ a = x: x; }", 1, 7, "Functions need evaluation"),
+        (
+            "{ ${a} = 1; }",
+            1,
+            3,
+            "Dynamic attribute names need evaluation",
+        ),
+        (
+            "{ a = { b = 1; }.b; }",
+            1,
+            17,
+            "Selecting attributes needs evaluation",
+        ),
+    ];
+    for (src, line, column, message) in cases {
+        let error = from_nix(src).unwrap_err();
+        assert!(error.needs_evaluation, "{src}: {error}");
+        assert_eq!((error.line, error.column), (line, column), "{src}: {error}");
+        assert!(error.message.starts_with(message), "{src}: {error}");
+    }
+}
+
+#[test]
+fn test_syntax_errors() {
+    let cases = [
+        ("{ a = 1 }", "Unexpected '}', expected ';'"),
+        ("{ a = 1;", "Unexpected end of input, expected '}'"),
+        ("{ a = \"b; }", "Unterminated string"),
+        (
+            "[ 1 -1 ]",
+            "A negative number in a list must be parenthesized",
+        ),
+        (
+            "{ a = 1; a = 2; }",
+            "The attribute 'a' is defined more than once",
+        ),
+        (
+            "{ a.b = 1; a = { b = 2; }; }",
+            "The attribute 'a' is defined more than once",
+        ),
+        (
+            "{ a = 1; } }",
+            "Unexpected '}', expected the end of the expression",
+        ),
+        ("/* open", "Unterminated comment"),
+    ];
+    for (src, message) in cases {
+        let error = from_nix(src).unwrap_err();
+        assert!(!error.needs_evaluation, "{src}: {error}");
+        assert!(error.message.starts_with(message), "{src}: {error}");
+    }
+    assert_eq!(
+        from_nix("{\n  a = 1\n}").unwrap_err().to_string(),
+        "Unexpected '}', expected ';' at line 3, column 1"
+    );
+}
+
+#[test]
+fn test_parse_generated_default_nix() {
+    let config: Config = serde_json::from_value(json!({
+        "version": 1,
+        "localization": { "hostname": "node", "timezone": "UTC" },
+        "ssh": { "authorizedKeys": ["ssh-ed25519 AAAA user@host"] },
+        "consensus": { "lighthouse": {
+            "enable": true,
+            "dataDir": "/var/lib/lighthouse",
+            "endpoint": "http://127.0.0.1:5052",
+            "execEndpoint": "http://127.0.0.1:8551",
+            "extraOptions": ["--a"]
+        } }
+    }))
+    .unwrap();
+    let options = config.module_options().unwrap();
+    for src in [
+        default_nix(&to_nix_pretty(&options)),
+        default_nix(&to_nix(&options)),
+        format!(
+            "{{ pkgs, lib, config, ... }}: {{ homestakeros = {}; }}",
+            to_nix(&options)
+        ),
+    ] {
+        let parsed = parse_default_nix(&src, None).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+    }
+}
+
+#[test]
+fn test_parse_hand_written_default_nix() {
+    let src = r#"
+        args @ { lib ? null, ... }:
+        {
+          homestakeros.localization.hostname = "node";
+          homestakeros.ssh.authorizedKeys = [ ];
+        }
+    "#;
+    let config = parse_default_nix(src, None).unwrap();
+    assert_eq!(config.localization.hostname, "node");
+}
+
+#[test]
+fn test_parse_default_nix_errors() {
+    let errors = parse_default_nix("{ ... }: { networking.hostName = \"a\"; }", None).unwrap_err();
+    assert_eq!(errors[0].code, "required");
+
+    let errors =
+        parse_default_nix("{ ... }: { homestakeros = lib.mkForce { }; }", None).unwrap_err();
+    assert_eq!(errors[0].code, "needs_evaluation");
+    assert_eq!(
+        errors[0].message,
+        "'lib' needs evaluation; only attribute sets, lists, strings, numbers, booleans and null \
+         can be read at line 1, column 27"
+    );
+
+    let errors = parse_default_nix("{ ... }: { homestakeros = { }", None).unwrap_err();
+    assert_eq!(errors[0].code, "invalid_nix");
+
+    let errors = parse_default_nix("{ ... }: [ ]", None).unwrap_err();
+    assert_eq!(
+        errors[0].message,
+        "The module must be an attribute set at line 1, column 10"
+    );
+
+    let src = "{ homestakeros = { localization.hostname = 1; ssh.authorizedKeys = [ ]; }; }";
+    let errors = parse_default_nix(src, None).unwrap_err();
+    assert!(errors[0].pointer.starts_with("/localization/hostname"));
+}
+
+#[test]
+fn test_nesting_limit() {
+    let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+    assert!(from_nix(&nested(128)).is_ok());
+    let error = from_nix(&nested(129)).unwrap_err();
+    assert!(!error.needs_evaluation);
+    assert!(
+        error.message.contains("nested more than 128 levels"),
+        "{error}"
+    );
+    assert_eq!(error.column, 129);
+
+    // A deeply nested request fails cleanly instead of overflowing the stack.
+    let src = format!("{{ homestakeros = {}; }}", nested(120_000));
+    let errors = parse_default_nix(&src, None).unwrap_err();
+    assert_eq!(errors[0].code, "invalid_nix");
+
+    // Dotted attribute paths nest attribute sets as well.
+    let path = vec!["a"; 128].join(".");
+    assert!(from_nix(&format!("{{ {path} = 1; }}")).is_ok());
+    let error = from_nix(&format!("{{ {path}.a = 1; }}")).unwrap_err();
+    assert!(
+        error.message.contains("nested more than 128 levels"),
+        "{error}"
+    );
+    let error = from_nix(&format!("{{ {path} = {{ }}; }}")).unwrap_err();
+    assert!(
+        error.message.contains("nested more than 128 levels"),
+        "{error}"
+    );
+}
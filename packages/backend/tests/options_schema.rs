@@ -0,0 +1,152 @@
+use backend::options_schema::OptionsSchema;
+use serde_json::{json, Value};
+
+/// An option as exported by `introspect.nix`.
+fn option(type_name: &str) -> Value {
+    json!({ "type": type_name, "default": null, "description": null, "example": null })
+}
+
+/// A cut-down `.#schema` export with the shapes the homestakeros module uses.
+fn schema() -> OptionsSchema {
+    let mut mounts = option("attrsOf (submodule)");
+    mounts["options"] = json!({
+        "enable": option("bool"),
+        "type": option("str"),
+        "options": option("str"),
+        "wantedBy": option("listOf (str)"),
+    });
+    OptionsSchema::from_json(&json!({
+        "localization": { "hostname": option("str"), "timezone": option("str") },
+        "mounts": mounts,
+        "consensus": {
+            "lighthouse": {
+                "enable": option("bool"),
+                "slasher": {
+                    "historyLength": option("int"),
+                    "maxDatabaseSize": option("int"),
+                },
+                "extraOptions": option("listOf (str)"),
+            }
+        },
+        "metrics": { "port": option("unsignedInt16"),
+                     "retention": option("positiveInt") },
+        "addons": { "newAddon": { "mode": option("enum") } },
+    }))
+    .unwrap()
+}
+
+fn problems(config: &Value) -> Vec<(String, &'static str)> {
+    schema()
+        .validate(config)
+        .into_iter()
+        .map(|e| (e.pointer, e.code))
+        .collect()
+}
+
+#[test]
+fn test_nested_submodule_types() {
+    // 'listOf (attrsOf (submodule))' keeps the submodule options for the innermost type.
+    let mut peers = option("listOf (attrsOf (submodule))");
+    peers["options"] = json!({ "port": option("unsignedInt16") });
+    let schema = OptionsSchema::from_json(&json!({ "peers": peers })).unwrap();
+    assert!(schema
+        .validate(&json!({ "peers": [{ "a": { "port": 30303 } }] }))
+        .is_empty());
+    let errors = schema.validate(&json!({ "peers": [{ "a": { "port": -1, "host": "x" } }] }));
+    let problems: Vec<(&str, &str)> = errors
+        .iter()
+        .map(|e| (e.pointer.as_str(), e.code))
+        .collect();
+    assert_eq!(
+        problems,
+        vec![
+            ("/peers/0/a/host", "unknown_field"),
+            ("/peers/0/a/port", "invalid_value"),
+        ]
+    );
+}
+
+#[test]
+fn test_valid_config() {
+    let config = json!({
+        "localization": { "hostname": "example", "timezone": null },
+        "mounts": { "eth": { "enable": true, "type": "ext4", "wantedBy": ["multi-user.target"] } },
+        "consensus": { "lighthouse": { "enable": true, "slasher": { "historyLength": 4096 } } },
+        "metrics": { "port": 9100, "retention": 7 },
+        "addons": { "newAddon": { "mode": "fast" } },
+    });
+    assert!(problems(&config).is_empty());
+}
+
+#[test]
+fn test_unknown_options() {
+    let config = json!({
+        "localization": { "hostname": "example", "hostName": "typo" },
+        "mounts": { "eth": { "enable": true, "wants": ["x.target"] } },
+        "nonsense": {},
+    });
+    assert_eq!(
+        problems(&config),
+        vec![
+            ("/localization/hostName".to_string(), "unknown_field"),
+            ("/mounts/eth/wants".to_string(), "unknown_field"),
+            ("/nonsense".to_string(), "unknown_field"),
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_types() {
+    let config = json!({
+        "localization": { "hostname": 1234 },
+        "mounts": { "eth": { "enable": "yes", "wantedBy": "multi-user.target" } },
+        "consensus": { "lighthouse": { "extraOptions": ["--a", 1], "slasher": { "historyLength": 1.5 } } },
+    });
+    let errors = schema().validate(&config);
+    let pointers: Vec<&str> = errors.iter().map(|e| e.pointer.as_str()).collect();
+    assert_eq!(
+        pointers,
+        vec![
+            "/consensus/lighthouse/extraOptions/1",
+            "/consensus/lighthouse/slasher/historyLength",
+            "/localization/hostname",
+            "/mounts/eth/enable",
+            "/mounts/eth/wantedBy",
+        ]
+    );
+    assert!(errors.iter().all(|e| e.code == "invalid_type"));
+    assert_eq!(
+        errors[2].message,
+        "The option 'localization.hostname' must be a string, but is an integer"
+    );
+}
+
+#[test]
+fn test_integer_ranges() {
+    let config = json!({ "metrics": { "port": -1, "retention": 0 } });
+    assert_eq!(
+        problems(&config),
+        vec![
+            ("/metrics/port".to_string(), "invalid_value"),
+            ("/metrics/retention".to_string(), "invalid_value"),
+        ]
+    );
+}
+
+#[test]
+fn test_group_must_be_object() {
+    assert_eq!(
+        problems(&json!({ "localization": "example" })),
+        vec![("/localization".to_string(), "invalid_type")]
+    );
+}
+
+#[test]
+fn test_load_rejects_non_object() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("options.json");
+    std::fs::write(&path, "[]").unwrap();
+    assert!(OptionsSchema::load(&path).is_err());
+    std::fs::write(&path, json!({ "a": option("bool") }).to_string()).unwrap();
+    assert!(OptionsSchema::load(&path).is_ok());
+}
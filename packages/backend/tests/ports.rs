@@ -0,0 +1,257 @@
+use backend::ports::{listeners, port_conflicts, Endpoint, Protocol};
+use backend::schema_types::Config;
+use backend::validation::semantic_errors;
+use serde_json::{json, Value};
+
+/// A node running geth, lighthouse and MEV-Boost with their data on a persistent mount.
+fn node() -> Value {
+    json!({
+        "localization": { "hostname": "example" },
+        "ssh": { "authorizedKeys": [] },
+        "execution": {
+            "geth": {
+                "enable": true,
+                "endpoint": "http://127.0.0.1:8551",
+                "dataDir": "/mnt/eth/geth"
+            }
+        },
+        "consensus": {
+            "lighthouse": {
+                "enable": true,
+                "endpoint": "http://127.0.0.1:5052",
+                "execEndpoint": "http://127.0.0.1:8551",
+                "dataDir": "/mnt/eth/lighthouse"
+            }
+        },
+        "addons": {
+            "mev-boost": { "enable": true, "endpoint": "http://127.0.0.1:18550" }
+        },
+        "mounts": {
+            "eth": { "enable": true, "type": "ext4", "what": "/dev/sda1", "where": "/mnt/eth" }
+        }
+    })
+}
+
+/// The pointers and codes of the problems with `value`.
+fn problems(value: Value) -> Vec<(String, &'static str)> {
+    let config: Config = serde_json::from_value(value).expect("Config should deserialize");
+    semantic_errors(&config)
+        .into_iter()
+        .map(|e| (e.pointer, e.code))
+        .collect()
+}
+
+fn options(items: &[&str]) -> Vec<String> {
+    items.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn test_parse_endpoint() {
+    let endpoint = Endpoint::parse("http://LOCALHOST:8551/path").unwrap();
+    assert_eq!(endpoint.host, "localhost");
+    assert_eq!(endpoint.port, 8551);
+    assert_eq!(Endpoint::parse("http://10.0.0.1:80").unwrap().port, 80);
+    assert!(endpoint.same_host_and_port(&Endpoint::parse("https://127.0.0.1:8551").unwrap()));
+    assert!(!endpoint.same_host_and_port(&Endpoint::parse("http://127.0.0.1:8552").unwrap()));
+}
+
+#[test]
+fn test_parse_endpoint_without_scheme() {
+    // The module's parseEndpoint makes the scheme optional.
+    let endpoint = Endpoint::parse("localhost:8551").unwrap();
+    assert_eq!(endpoint.host, "localhost");
+    assert_eq!(endpoint.port, 8551);
+    assert_eq!(endpoint.url.scheme(), "http");
+    let endpoint = Endpoint::parse("192.168.1.5:5052/eth").unwrap();
+    assert_eq!(
+        (endpoint.host.as_str(), endpoint.port),
+        ("192.168.1.5", 5052)
+    );
+    assert!(Endpoint::parse("127.0.0.1")
+        .unwrap_err()
+        .starts_with("must include a port"));
+}
+
+#[test]
+fn test_parse_invalid_endpoint() {
+    for (endpoint, problem) in [
+        ("127.0.0.1:", "must include a port"),
+        ("http://", "is not a valid URL"),
+        ("http://127.0.0.1:99999", "is not a valid URL"),
+        ("ws://127.0.0.1:8546", "must use the http or https scheme"),
+        ("http://[::1]:8551", "must not use an IPv6 address"),
+        ("http://127.0.0.1", "must include a port"),
+    ] {
+        let error = Endpoint::parse(endpoint).expect_err(endpoint);
+        assert!(error.starts_with(problem), "{endpoint}: {error}");
+    }
+}
+
+#[test]
+fn test_default_listeners() {
+    let endpoint = Endpoint::parse("http://192.168.1.5:8551").unwrap();
+    let geth: Vec<(&str, Protocol, String, u16)> =
+        listeners("execution.geth", Some(&endpoint), &[])
+            .into_iter()
+            .map(|l| (l.purpose, l.protocol, l.address, l.port))
+            .collect();
+    assert_eq!(
+        geth,
+        vec![
+            ("engine API", Protocol::Tcp, "192.168.1.5".to_string(), 8551),
+            ("JSON-RPC", Protocol::Tcp, "192.168.1.5".to_string(), 8545),
+            ("P2P", Protocol::Tcp, "0.0.0.0".to_string(), 30303),
+            ("discovery", Protocol::Udp, "0.0.0.0".to_string(), 30303),
+        ]
+    );
+    // Without a valid endpoint, only the sockets that do not depend on it are known.
+    assert_eq!(listeners("execution.geth", None, &[]).len(), 2);
+}
+
+#[test]
+fn test_listeners_from_extra_options() {
+    let endpoint = Endpoint::parse("http://127.0.0.1:8551").unwrap();
+    let extra = options(&[
+        "--http.port=8645",
+        "--port 30313",
+        "--metrics.addr",
+        "0.0.0.0",
+        "--discovery.port=30400",
+    ]);
+    let geth = listeners("execution.geth", Some(&endpoint), &extra);
+    let port = |purpose: &str| {
+        let listener = geth.iter().find(|l| l.purpose == purpose).unwrap();
+        (listener.port, listener.pointer.as_str())
+    };
+    assert_eq!(port("JSON-RPC"), (8645, "/execution/geth/extraOptions/0"));
+    assert_eq!(port("P2P"), (30313, "/execution/geth/extraOptions/1"));
+    assert_eq!(port("discovery"), (30400, "/execution/geth/extraOptions/4"));
+    assert_eq!(port("metrics"), (6060, "/execution/geth/enable"));
+    assert_eq!(port("engine API"), (8551, "/execution/geth/endpoint"));
+
+    let endpoint = Endpoint::parse("http://127.0.0.1:18550").unwrap();
+    let mev_boost = listeners(
+        "addons.mev-boost",
+        Some(&endpoint),
+        &options(&["-addr 0.0.0.0:18551"]),
+    );
+    assert_eq!(mev_boost.len(), 1);
+    assert_eq!(mev_boost[0].address, "0.0.0.0");
+    assert_eq!(mev_boost[0].port, 18551);
+}
+
+#[test]
+fn test_no_conflicts() {
+    assert!(problems(node()).is_empty());
+}
+
+#[test]
+fn test_invalid_endpoints() {
+    let mut node = node();
+    node["execution"]["geth"]["endpoint"] = json!("ws://127.0.0.1:8551");
+    node["consensus"]["lighthouse"]["execEndpoint"] = json!("http://127.0.0.1");
+    node["addons"]["mev-boost"]["endpoint"] = json!("http://[::1]:18550");
+    assert_eq!(
+        problems(node),
+        vec![
+            ("/execution/geth/endpoint".to_string(), "invalid_url"),
+            ("/addons/mev-boost/endpoint".to_string(), "invalid_url"),
+            (
+                "/consensus/lighthouse/execEndpoint".to_string(),
+                "invalid_url"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_endpoint_on_default_port() {
+    let mut node = node();
+    node["consensus"]["lighthouse"]["endpoint"] = json!("http://0.0.0.0:8545");
+    let config: Config = serde_json::from_value(node).unwrap();
+    let errors = semantic_errors(&config);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/consensus/lighthouse/endpoint");
+    assert_eq!(errors[0].code, "port_conflict");
+    assert_eq!(
+        errors[0].message,
+        "'consensus.lighthouse' would bind TCP port 8545 for HTTP API, which 'execution.geth' \
+         already binds for JSON-RPC"
+    );
+}
+
+#[test]
+fn test_different_addresses_do_not_conflict() {
+    let mut node = node();
+    node["addons"]["mev-boost"]["endpoint"] = json!("http://192.168.1.5:8545");
+    assert!(problems(node).is_empty());
+}
+
+#[test]
+fn test_default_ports_conflict() {
+    let mut node = node();
+    node["consensus"]["teku"] = json!({
+        "enable": true,
+        "endpoint": "http://127.0.0.1:5051",
+        "execEndpoint": "http://127.0.0.1:8551",
+        "dataDir": "/mnt/eth/teku"
+    });
+    // TCP and UDP on port 9000 are reported once.
+    assert_eq!(
+        problems(node),
+        vec![("/consensus/teku/enable".to_string(), "port_conflict")]
+    );
+}
+
+#[test]
+fn test_extra_options_conflict() {
+    let mut node = node();
+    node["consensus"]["lighthouse"]["extraOptions"] = json!(["--metrics-port", "8551"]);
+    assert_eq!(
+        problems(node),
+        vec![(
+            "/consensus/lighthouse/extraOptions/0".to_string(),
+            "port_conflict"
+        )]
+    );
+
+    // Moving the conflicting socket out of the way resolves it.
+    let mut node = self::node();
+    node["consensus"]["teku"] = json!({
+        "enable": true,
+        "endpoint": "http://127.0.0.1:5051",
+        "execEndpoint": "http://127.0.0.1:8551",
+        "dataDir": "/mnt/eth/teku",
+        "extraOptions": ["--p2p-port=9100"]
+    });
+    assert!(problems(node).is_empty());
+}
+
+#[test]
+fn test_conflict_within_a_client() {
+    let mut node = node();
+    node["execution"]["geth"]["endpoint"] = json!("http://127.0.0.1:8545");
+    node["consensus"]["lighthouse"]["execEndpoint"] = json!("http://127.0.0.1:8545");
+    let config: Config = serde_json::from_value(node).unwrap();
+    let errors = semantic_errors(&config);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/execution/geth/enable");
+    assert_eq!(
+        errors[0].message,
+        "'execution.geth' would bind TCP port 8545 for JSON-RPC, which it already binds for \
+         engine API"
+    );
+}
+
+#[test]
+fn test_port_conflicts_ignore_protocol() {
+    let listener = |protocol| backend::ports::Listener {
+        service: "execution.geth".to_string(),
+        purpose: "P2P",
+        protocol,
+        address: "0.0.0.0".to_string(),
+        port: 30303,
+        pointer: "/execution/geth/enable".to_string(),
+    };
+    assert!(port_conflicts(&[listener(Protocol::Tcp), listener(Protocol::Udp)]).is_empty());
+}
@@ -0,0 +1,135 @@
+use backend::jobs::{BuildJob, BuildStatus, JobStore};
+use backend::retention::{collect_garbage, dir_size, select_expired, RetentionPolicy};
+use backend::targets::BuildTarget;
+use backend::workspace::Workspace;
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn finished_build(build_id: &str, hostname: &str, finished_at: u64) -> BuildJob {
+    let mut build = BuildJob::new(build_id, hostname, "hash", build_id, BuildTarget::Kexec);
+    build.status = BuildStatus::Succeeded;
+    build.created_at = finished_at;
+    build.updated_at = finished_at;
+    build
+}
+
+#[test]
+fn test_max_age() {
+    let now = 100 * DAY;
+    let builds = vec![
+        finished_build("old", "host", now - 10 * DAY),
+        finished_build("new", "host", now - DAY),
+    ];
+    let policy = RetentionPolicy {
+        max_age: Some(Duration::from_secs(7 * DAY)),
+        ..RetentionPolicy::default()
+    };
+    assert_eq!(
+        select_expired(&builds, &HashMap::new(), &policy, now),
+        vec!["old"]
+    );
+}
+
+#[test]
+fn test_keep_per_host() {
+    let builds = vec![
+        finished_build("a1", "alpha", 1),
+        finished_build("a2", "alpha", 2),
+        finished_build("a3", "alpha", 3),
+        finished_build("b1", "beta", 1),
+    ];
+    let policy = RetentionPolicy {
+        keep_per_host: Some(2),
+        ..RetentionPolicy::default()
+    };
+    assert_eq!(
+        select_expired(&builds, &HashMap::new(), &policy, 10),
+        vec!["a1"]
+    );
+}
+
+#[test]
+fn test_max_total_size_removes_oldest_first() {
+    let builds = vec![
+        finished_build("first", "host", 1),
+        finished_build("second", "host", 2),
+        finished_build("third", "host", 3),
+    ];
+    let sizes: HashMap<String, u64> = [("first", 100), ("second", 100), ("third", 100)]
+        .into_iter()
+        .map(|(id, size)| (id.to_string(), size))
+        .collect();
+    let policy = RetentionPolicy {
+        max_total_size: Some(150),
+        ..RetentionPolicy::default()
+    };
+    assert_eq!(
+        select_expired(&builds, &sizes, &policy, 10),
+        vec!["first", "second"]
+    );
+}
+
+#[test]
+fn test_running_builds_are_kept() {
+    let mut running = finished_build("running", "host", 1);
+    running.status = BuildStatus::Building;
+    let policy = RetentionPolicy {
+        max_age: Some(Duration::ZERO),
+        keep_per_host: Some(0),
+        max_total_size: Some(0),
+    };
+    assert!(select_expired(&[running], &HashMap::new(), &policy, 100 * DAY).is_empty());
+}
+
+#[test]
+fn test_collect_garbage() -> Result<(), Box<dyn std::error::Error>> {
+    let workspace = Workspace::new()?;
+    let jobs = JobStore::new();
+
+    // Two finished builds of the same host, each with an artifact and a GC root.
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let build = workspace.new_build_workspace("testhost")?;
+        fs::write(build.output_dir.join("bzImage"), "artifact content")?;
+        std::os::unix::fs::symlink(&build.output_dir, &build.gc_root)?;
+        jobs.insert(BuildJob::new(
+            &build.uuid,
+            "testhost",
+            "hash",
+            &build.uuid,
+            BuildTarget::Kexec,
+        ));
+        jobs.succeed(&build.uuid, Vec::new());
+        ids.push(build.uuid.clone());
+        std::thread::sleep(Duration::from_millis(1100));
+    }
+
+    let policy = RetentionPolicy {
+        keep_per_host: Some(1),
+        ..RetentionPolicy::default()
+    };
+    let removed = collect_garbage(&workspace, &jobs, &policy, u64::MAX)?;
+    assert_eq!(removed, vec![ids[0].clone()]);
+
+    // The expired build is gone from disk and from the index; the newer one is untouched.
+    assert!(!workspace.output_dir(&ids[0])?.exists());
+    assert!(!workspace.gc_root(&ids[0])?.is_symlink());
+    assert!(jobs.get(&ids[0]).is_none());
+    assert!(workspace.output_dir(&ids[1])?.exists());
+    assert!(workspace.gc_root(&ids[1])?.is_symlink());
+    assert!(jobs.get(&ids[1]).is_some());
+    Ok(())
+}
+
+#[test]
+fn test_dir_size() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a"), "12345")?;
+    fs::create_dir(dir.path().join("sub"))?;
+    fs::write(dir.path().join("sub").join("b"), "123")?;
+    assert_eq!(dir_size(dir.path())?, 8);
+    Ok(())
+}
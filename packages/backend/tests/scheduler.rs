@@ -0,0 +1,181 @@
+use backend::scheduler::{client_address, Scheduler};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Occupies every worker of the scheduler until the returned sender is dropped.
+fn block_workers(scheduler: &Scheduler, workers: usize) -> Vec<mpsc::Sender<()>> {
+    let (started_tx, started_rx) = mpsc::channel();
+    let mut releases = Vec::new();
+    for i in 0..workers {
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let started_tx = started_tx.clone();
+        scheduler.submit(
+            "blocker",
+            &format!("blocker-{i}"),
+            Box::new(move || {
+                started_tx.send(()).unwrap();
+                let _ = release_rx.recv();
+            }),
+        );
+        releases.push(release_tx);
+    }
+    for _ in 0..workers {
+        started_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+    releases
+}
+
+#[test]
+fn test_clients_take_turns() {
+    let scheduler = Scheduler::start(1, |_| {});
+    let releases = block_workers(&scheduler, 1);
+
+    // One client submits three builds before another client submits one.
+    let order = Arc::new(Mutex::new(Vec::new()));
+    for id in ["a1", "a2", "a3"] {
+        let order = Arc::clone(&order);
+        scheduler.submit(
+            "alice",
+            id,
+            Box::new(move || order.lock().unwrap().push(id)),
+        );
+    }
+    let (done_tx, done_rx) = mpsc::channel();
+    {
+        let order = Arc::clone(&order);
+        scheduler.submit(
+            "bob",
+            "b1",
+            Box::new(move || {
+                order.lock().unwrap().push("b1");
+                done_tx.send(()).unwrap();
+            }),
+        );
+    }
+
+    // Bob does not have to wait for all of Alice's builds.
+    assert_eq!(scheduler.position("a1"), Some(1));
+    assert_eq!(scheduler.position("b1"), Some(2));
+    assert_eq!(scheduler.position("a2"), Some(3));
+    assert_eq!(scheduler.position("a3"), Some(4));
+
+    drop(releases);
+    done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(order.lock().unwrap()[..2], ["a1", "b1"]);
+}
+
+#[test]
+fn test_concurrency_limit() {
+    let scheduler = Scheduler::start(2, |_| {});
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let (done_tx, done_rx) = mpsc::channel();
+
+    for i in 0..6 {
+        let running = Arc::clone(&running);
+        let peak = Arc::clone(&peak);
+        let done_tx = done_tx.clone();
+        scheduler.submit(
+            &format!("client-{}", i % 3),
+            &format!("build-{i}"),
+            Box::new(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                done_tx.send(()).unwrap();
+            }),
+        );
+    }
+    for _ in 0..6 {
+        done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+    assert!(peak.load(Ordering::SeqCst) <= 2);
+}
+
+#[test]
+fn test_remove_queued_task() {
+    let scheduler = Scheduler::start(1, |_| {});
+    let releases = block_workers(&scheduler, 1);
+
+    scheduler.submit("alice", "a1", Box::new(|| {}));
+    scheduler.submit("alice", "a2", Box::new(|| {}));
+    assert!(scheduler.remove("a1").is_some());
+    assert!(scheduler.remove("a1").is_none());
+    assert_eq!(scheduler.position("a1"), None);
+    assert_eq!(scheduler.position("a2"), Some(1));
+
+    drop(releases);
+}
+
+#[test]
+fn test_panicking_task_is_reported() {
+    let (panicked_tx, panicked_rx) = mpsc::channel();
+    let panicked_tx = Mutex::new(panicked_tx);
+    let scheduler = Scheduler::start(1, move |build_id| {
+        panicked_tx
+            .lock()
+            .unwrap()
+            .send(build_id.to_string())
+            .unwrap();
+    });
+
+    scheduler.submit("alice", "a1", Box::new(|| panic!("build failed badly")));
+    let panicked = panicked_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(panicked, "a1");
+
+    // The worker keeps running tasks.
+    let (done_tx, done_rx) = mpsc::channel();
+    scheduler.submit("alice", "a2", Box::new(move || done_tx.send(()).unwrap()));
+    done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+}
+
+#[test]
+fn test_client_address() {
+    let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+    let client: IpAddr = "203.0.113.7".parse().unwrap();
+    let trusted = [proxy];
+
+    // Without a trusted proxy, the headers are ignored.
+    assert_eq!(
+        client_address(client, None, Some("198.51.100.1"), &trusted),
+        "203.0.113.7"
+    );
+    assert_eq!(client_address(proxy, None, None, &trusted), "127.0.0.1");
+
+    // A spoofed leftmost entry does not change the bucket of the client behind the proxy.
+    let real = client_address(proxy, None, Some("203.0.113.7"), &trusted);
+    let spoofed = client_address(proxy, None, Some("198.51.100.1, 203.0.113.7"), &trusted);
+    assert_eq!(real, "203.0.113.7");
+    assert_eq!(spoofed, real);
+
+    // Trusted proxies further along the chain are skipped.
+    let trusted = [proxy, "10.0.0.2".parse().unwrap()];
+    assert_eq!(
+        client_address(
+            proxy,
+            None,
+            Some("198.51.100.1, 203.0.113.7, 10.0.0.2"),
+            &trusted
+        ),
+        "203.0.113.7"
+    );
+
+    // The Forwarded header takes precedence, with ports and brackets around IPv6 addresses.
+    assert_eq!(
+        client_address(
+            proxy,
+            Some("for=198.51.100.1, for=\"[2001:db8::1]:4711\";proto=https"),
+            Some("192.0.2.1"),
+            &trusted
+        ),
+        "2001:db8::1"
+    );
+    assert_eq!(
+        client_address(proxy, Some("for=_hidden, for=10.0.0.2:80"), None, &trusted),
+        "_hidden"
+    );
+}
@@ -0,0 +1,155 @@
+use backend::signing::{write_manifest, SigningKey, MANIFEST_FILE, SIGNATURE_FILE};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde_json::Value;
+use std::fs;
+use tempfile::tempdir;
+
+/// Decodes a minisign public key file into its key id and verifying key.
+fn parse_public_key(public_key: &str) -> ([u8; 8], VerifyingKey) {
+    let blob = BASE64.decode(public_key.lines().nth(1).unwrap()).unwrap();
+    assert_eq!(blob.len(), 42);
+    assert_eq!(&blob[..2], b"Ed");
+    let key_id: [u8; 8] = blob[2..10].try_into().unwrap();
+    let key = VerifyingKey::from_bytes(&blob[10..].try_into().unwrap()).unwrap();
+    (key_id, key)
+}
+
+/// Verifies a minisign signature file the way `minisign -V` does and returns the trusted comment.
+fn verify(public_key: &str, data: &[u8], signature_file: &str) -> String {
+    let (key_id, key) = parse_public_key(public_key);
+    let lines: Vec<&str> = signature_file.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("untrusted comment: "));
+
+    let blob = BASE64.decode(lines[1]).unwrap();
+    assert_eq!(blob.len(), 74);
+    assert_eq!(&blob[..2], b"ED");
+    assert_eq!(&blob[2..10], &key_id);
+    let signature = Signature::from_slice(&blob[10..]).unwrap();
+    key.verify(&Blake2b512::digest(data), &signature)
+        .expect("Signature does not match the data");
+
+    let trusted_comment = lines[2].strip_prefix("trusted comment: ").unwrap();
+    let mut global = blob[10..].to_vec();
+    global.extend_from_slice(trusted_comment.as_bytes());
+    let global_signature = Signature::from_slice(&BASE64.decode(lines[3]).unwrap()).unwrap();
+    key.verify(&global, &global_signature)
+        .expect("Global signature does not match the trusted comment");
+    trusted_comment.to_string()
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let key = SigningKey::from_seed(&[7; 32]);
+    let signature = key.sign(b"hello", "build_id:1");
+    assert_eq!(
+        verify(&key.public_key(), b"hello", &signature),
+        "build_id:1"
+    );
+}
+
+#[test]
+fn test_signature_rejects_other_data() {
+    let key = SigningKey::from_seed(&[7; 32]);
+    let signature = key.sign(b"hello", "comment");
+    let (_, verifying_key) = parse_public_key(&key.public_key());
+    let blob = BASE64.decode(signature.lines().nth(1).unwrap()).unwrap();
+    let signature = Signature::from_slice(&blob[10..]).unwrap();
+    assert!(verifying_key
+        .verify(&Blake2b512::digest(b"tampered"), &signature)
+        .is_err());
+}
+
+#[test]
+fn test_trusted_comment_is_single_line() {
+    let key = SigningKey::from_seed(&[7; 32]);
+    let signature = key.sign(b"data", "first\nsecond");
+    assert_eq!(
+        verify(&key.public_key(), b"data", &signature),
+        "first second"
+    );
+}
+
+#[test]
+fn test_key_id_is_stable() {
+    let key = SigningKey::from_seed(&[1; 32]);
+    let other = SigningKey::from_seed(&[2; 32]);
+    assert_eq!(key.key_id(), SigningKey::from_seed(&[1; 32]).key_id());
+    assert_ne!(key.key_id(), other.key_id());
+    assert_eq!(key.key_id().len(), 16);
+    assert!(key.public_key().starts_with(&format!(
+        "untrusted comment: minisign public key {}\n",
+        key.key_id()
+    )));
+
+    // The displayed id is the little-endian key id from the public key blob.
+    let (key_id, _) = parse_public_key(&key.public_key());
+    assert_eq!(format!("{:016X}", u64::from_le_bytes(key_id)), key.key_id());
+}
+
+#[test]
+fn test_load_or_generate() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("keys/signing.key");
+
+    let generated = SigningKey::load_or_generate(&path).unwrap();
+    assert!(path.exists());
+    let loaded = SigningKey::load_or_generate(&path).unwrap();
+    assert_eq!(generated.public_key(), loaded.public_key());
+
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn test_load_invalid_key() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("signing.key");
+    fs::write(&path, "not a key").unwrap();
+    assert!(SigningKey::load_or_generate(&path).is_err());
+    fs::write(&path, BASE64.encode([0u8; 16])).unwrap();
+    assert!(SigningKey::load_or_generate(&path).is_err());
+}
+
+#[test]
+fn test_write_signed_manifest() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("initrd.zst"), "initrd").unwrap();
+    fs::write(dir.path().join("bzImage"), "kernel!").unwrap();
+    fs::create_dir(dir.path().join("subdir")).unwrap();
+
+    let key = SigningKey::from_seed(&[3; 32]);
+    write_manifest(dir.path(), "build-1", "homestaker", Some(&key)).unwrap();
+    // Writing again must not list the manifest itself.
+    write_manifest(dir.path(), "build-1", "homestaker", Some(&key)).unwrap();
+
+    let manifest = fs::read_to_string(dir.path().join(MANIFEST_FILE)).unwrap();
+    let parsed: Value = serde_json::from_str(&manifest).unwrap();
+    assert_eq!(parsed["build_id"], "build-1");
+    assert_eq!(parsed["hostname"], "homestaker");
+    let files = parsed["files"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0]["file"], "bzImage");
+    assert_eq!(files[0]["size"], 7);
+    assert_eq!(files[1]["file"], "initrd.zst");
+    assert_eq!(files[1]["sha256"].as_str().unwrap().len(), 64);
+
+    let signature = fs::read_to_string(dir.path().join(SIGNATURE_FILE)).unwrap();
+    assert_eq!(
+        verify(&key.public_key(), manifest.as_bytes(), &signature),
+        "build_id:build-1\thostname:homestaker\tfile:manifest.json"
+    );
+}
+
+#[test]
+fn test_write_unsigned_manifest() {
+    let dir = tempdir().unwrap();
+    fs::write(dir.path().join("bzImage"), "kernel").unwrap();
+    write_manifest(dir.path(), "build-1", "homestaker", None).unwrap();
+    assert!(dir.path().join(MANIFEST_FILE).exists());
+    assert!(!dir.path().join(SIGNATURE_FILE).exists());
+}
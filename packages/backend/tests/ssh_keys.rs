@@ -0,0 +1,135 @@
+use backend::ssh_keys::parse_authorized_key;
+
+// Generated with `ssh-keygen -C user@host`; the fingerprints are from `ssh-keygen -l`.
+const ED25519: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKhrzD3JMfXTSO1vK0qFvEwtfCeVH+i6Upq8VksFXY++ user@host";
+const ECDSA_P256: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBBkcfK1oAiyrTzUVpFZ0+WMfQdgasLJ4ek4yBbXMPjlJKpT2pFPh/pW4QN0I10go4wTl3Ndop1PWKC2/v30mxso= user@host";
+const ECDSA_P384: &str = "ecdsa-sha2-nistp384 AAAAE2VjZHNhLXNoYTItbmlzdHAzODQAAAAIbmlzdHAzODQAAABhBDHP1MSCbPMf4wi0/iuEx7u+BZ3wCi+zfhEW7i/xhk1sAkeLeX+QCl4xowWwDWjv5J3Azg8wGatuOG/uSUarnS+XyqiyRGIr/CzGDlSABcv1az4ZEcR8fLOeZeRzgncYyQ== user@host";
+const RSA_2048: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAABAQCgGpa7QESrCfs5I1GC1gJwVPpBuG/1N8zUtXKqdvbdnyya+H4Wy9yJfa4FV5mf4IL0LzM53IkkoiaW2UMltjLW3GfMm51sZaFFwBPEFlo4nAXWpu7N+3lzXLEOuu/eDOuf6I6xkFRC9Lq2atDZkKe93QOQ4rQKIBRHD3o9Z1fWIFJDtiNryAk8Li0UV2FALOrAdbnpMRz8Z1w5mm1abf2xjiNM73W02ql6Q1wF6Maa5kYP6mVD3FWH3RrCIPlnWM73EvPHOw6Q0NUaditYJjedWy4AWRNL4XfkH2ux0QWI7u+An75VmVitkxCQb6J/Zt9KRNcTW/0/plMHpKsTYD5p user@host";
+const RSA_1024: &str = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQDC51R1fbKsbRDkCyN32PEFeva+/QdYMre8E5i5O1Quh0Y1wdhuNsgp940g2zKnL37QMnNSIS7KRVyNpTuzOqT7HQ940HJ2q1fHwa1Gbi31qKEzUjJLRCK0oDsJ6O5Xe15MYHZHRl+OjBBYAZ+/JEhbD1Cx6TTCR8cZeCiKF8Yotw== user@host";
+const SK_ED25519: &str = "sk-ssh-ed25519@openssh.com AAAAGnNrLXNzaC1lZDI1NTE5QG9wZW5zc2guY29tAAAAIAABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fAAAABHNzaDo= yubikey";
+
+#[test]
+fn test_fingerprints() {
+    let cases = [
+        (
+            ED25519,
+            "ssh-ed25519",
+            "SHA256:1NC4kkIytWddSb64pAGTVTRg0tyTluqG9CQupLna10Y",
+        ),
+        (
+            ECDSA_P256,
+            "ecdsa-sha2-nistp256",
+            "SHA256:11e7Z3syH5hqYJ/QL1ThvmhfdHg5Q7q57Fl63FlhvdQ",
+        ),
+        (
+            ECDSA_P384,
+            "ecdsa-sha2-nistp384",
+            "SHA256:3NNeSt22OKZY/kZCQ/6Q99Mrip1xe3HT6nGUo3lcbzo",
+        ),
+        (
+            RSA_2048,
+            "ssh-rsa",
+            "SHA256:qXsH8Z8R4zjrFXQv4F5p8HJ7vOJMaE3iie4lvJFuxlk",
+        ),
+        (
+            RSA_1024,
+            "ssh-rsa",
+            "SHA256:o2OWgjcfZEkAFJjicovt3yt6iTfRJZQEsyxvrotImM0",
+        ),
+        (
+            SK_ED25519,
+            "sk-ssh-ed25519@openssh.com",
+            "SHA256:/p0CbeE3dk2SyW1OXXsThGc12ezDVD8eGw2/vtztDfk",
+        ),
+    ];
+    for (line, algorithm, fingerprint) in cases {
+        let key = parse_authorized_key(line).unwrap_or_else(|e| panic!("{line}: {e}"));
+        assert_eq!(key.algorithm, algorithm);
+        assert_eq!(key.fingerprint, fingerprint);
+    }
+}
+
+#[test]
+fn test_comment_and_options() {
+    let key = parse_authorized_key(ED25519).unwrap();
+    assert_eq!(key.comment.as_deref(), Some("user@host"));
+
+    let line = format!(r#"command="echo \"hi there\"",no-pty {ED25519} laptop"#);
+    let key = parse_authorized_key(&line).unwrap();
+    assert_eq!(key.algorithm, "ssh-ed25519");
+    assert_eq!(key.comment.as_deref(), Some("user@host laptop"));
+
+    let no_comment = ED25519.trim_end_matches(" user@host");
+    assert_eq!(parse_authorized_key(no_comment).unwrap().comment, None);
+}
+
+#[test]
+fn test_sk_ecdsa_structure() {
+    // Not a point on the curve, but structurally a valid security key blob.
+    let line = "sk-ecdsa-sha2-nistp256@openssh.com AAAAInNrLWVjZHNhLXNoYTItbmlzdHAyNTZAb3BlbnNzaC5jb20AAAAIbmlzdHAyNTYAAABBBAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0+P0AAAAAEc3NoOg==";
+    assert!(parse_authorized_key(line).is_ok());
+}
+
+fn error(line: &str) -> String {
+    parse_authorized_key(line)
+        .expect_err("The key should be rejected")
+        .to_string()
+}
+
+#[test]
+fn test_truncated_paste() {
+    let truncated = &ED25519[..60];
+    assert!(
+        error(truncated).contains("truncated"),
+        "{}",
+        error(truncated)
+    );
+    assert!(error("ssh-rsa AAAAB3Nza...").contains("base64"));
+    assert!(error("ssh-ed25519").contains("missing"));
+}
+
+#[test]
+fn test_type_mismatch() {
+    let blob = ED25519.split_whitespace().nth(1).unwrap();
+    let message = error(&format!("ssh-rsa {blob}"));
+    assert!(message.contains("'ssh-ed25519'"), "{message}");
+}
+
+#[test]
+fn test_unsupported_types() {
+    assert!(error("ssh-dss AAAAB3NzaC1kc3M=").contains("DSA"));
+    assert!(error("not-a-key AAAA").contains("Unsupported key type 'not-a-key'"));
+    assert!(error(r#"command="unterminated ssh-ed25519 AAAA"#).contains("quote"));
+}
+
+#[test]
+fn test_weak_rsa_key() {
+    // A 512-bit modulus.
+    let mut blob = Vec::new();
+    for part in [&b"ssh-rsa"[..], &[1, 0, 1], &[0xc0; 64]] {
+        blob.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        blob.extend_from_slice(part);
+    }
+    use base64::Engine;
+    let line = format!(
+        "ssh-rsa {}",
+        base64::engine::general_purpose::STANDARD.encode(&blob)
+    );
+    assert!(error(&line).contains("512 bits"), "{}", error(&line));
+}
+
+#[test]
+fn test_trailing_bytes() {
+    let mut blob = base64::Engine::decode(
+        &base64::engine::general_purpose::STANDARD,
+        ED25519.split_whitespace().nth(1).unwrap(),
+    )
+    .unwrap();
+    blob.push(0);
+    let line = format!(
+        "ssh-ed25519 {}",
+        base64::Engine::encode(&base64::engine::general_purpose::STANDARD, &blob)
+    );
+    assert!(error(&line).contains("trailing"));
+}
@@ -0,0 +1,66 @@
+use backend::jobs::BuildJob;
+use backend::targets::BuildTarget;
+
+#[test]
+fn test_parse_targets() {
+    for target in BuildTarget::ALL {
+        assert_eq!(target.name().parse::<BuildTarget>().unwrap(), target);
+        assert_eq!(target.to_string(), target.name());
+    }
+    assert_eq!(
+        "raw-image".parse::<BuildTarget>().unwrap(),
+        BuildTarget::RawImage
+    );
+
+    let err = "floppy".parse::<BuildTarget>().unwrap_err().to_string();
+    assert!(err.contains("floppy"));
+    assert!(err.contains("kexec, netboot, iso, raw-image, vm"));
+}
+
+#[test]
+fn test_target_serialization() {
+    assert_eq!(
+        serde_json::to_value(BuildTarget::RawImage).unwrap(),
+        "raw-image"
+    );
+    assert_eq!(
+        serde_json::from_str::<BuildTarget>("\"vm\"").unwrap(),
+        BuildTarget::Vm
+    );
+}
+
+#[test]
+fn test_flake_attribute() {
+    assert_eq!(
+        BuildTarget::Iso.flake_attribute("homestaker"),
+        "buildTargets.homestaker.iso"
+    );
+    assert_eq!(
+        BuildTarget::RawImage.flake_attribute("homestaker"),
+        "buildTargets.homestaker.raw-image"
+    );
+}
+
+#[test]
+fn test_whitelists() {
+    assert!(BuildTarget::Kexec.whitelist().contains(&"kexec-boot"));
+    assert!(!BuildTarget::Netboot.whitelist().contains(&"kexec-boot"));
+    assert!(BuildTarget::Netboot.whitelist().contains(&"netboot.ipxe"));
+    assert_eq!(BuildTarget::Iso.whitelist(), &["nixos.iso"]);
+    // The VM is shipped as an image, as a runner script from the store would not work elsewhere.
+    assert_eq!(BuildTarget::Vm.whitelist(), &["nixos.qcow2", "run-vm.sh"]);
+    for target in BuildTarget::ALL {
+        assert!(!target.whitelist().is_empty());
+    }
+}
+
+#[test]
+fn test_jobs_without_target_are_kexec_builds() {
+    // Build indexes written before targets were selectable have no target field.
+    let job: BuildJob = serde_json::from_str(
+        r#"{"build_id": "a", "hostname": "h", "config_hash": "c", "cache_key": "k",
+            "created_at": 1, "updated_at": 1, "status": "succeeded", "artifacts": []}"#,
+    )
+    .unwrap();
+    assert_eq!(job.target, BuildTarget::Kexec);
+}
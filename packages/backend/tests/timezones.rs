@@ -0,0 +1,66 @@
+use backend::schema_types::Config;
+use backend::timezones::{suggest_timezones, timezone_names, validate_timezone};
+use backend::validate_config;
+use serde_json::json;
+
+#[test]
+fn test_known_timezones() {
+    for timezone in ["Europe/Helsinki", "UTC", "America/New_York", "US/Eastern"] {
+        assert_eq!(validate_timezone(timezone), Ok(()), "{timezone}");
+    }
+}
+
+#[test]
+fn test_timezone_names() {
+    let names = timezone_names();
+    assert!(names.contains(&"Europe/Helsinki"));
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn test_misspelled_timezone() {
+    let error = validate_timezone("Europe/Helsiki").unwrap_err();
+    assert!(error.starts_with("The timezone 'Europe/Helsiki' is not in the tz database"));
+    assert!(error.ends_with("; did you mean 'Europe/Helsinki'?"));
+}
+
+#[test]
+fn test_suggestions() {
+    assert_eq!(
+        suggest_timezones("europe/helsinki"),
+        vec!["Europe/Helsinki"]
+    );
+    assert_eq!(suggest_timezones("utc"), vec!["UTC"]);
+    assert_eq!(suggest_timezones("Helsinki"), vec!["Europe/Helsinki"]);
+    assert_eq!(suggest_timezones("new york"), vec!["America/New_York"]);
+    assert_eq!(suggest_timezones("Europa/Berlin"), vec!["Europe/Berlin"]);
+    assert!(suggest_timezones("Mars/Olympus").is_empty());
+    assert!(suggest_timezones("").is_empty());
+}
+
+#[test]
+fn test_unknown_timezone_without_suggestions() {
+    let error = validate_timezone("Mars/Olympus").unwrap_err();
+    assert!(!error.contains("did you mean"));
+}
+
+#[test]
+fn test_validate_config_timezone() {
+    let config = |timezone: &str| -> Config {
+        serde_json::from_value(json!({
+            "localization": { "hostname": "example", "timezone": timezone },
+            "ssh": {
+                "authorizedKeys": [
+                    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKhrzD3JMfXTSO1vK0qFvEwtfCeVH+i6Upq8VksFXY++ user@host"
+                ]
+            }
+        }))
+        .unwrap()
+    };
+    assert!(validate_config(&config("Europe/Helsinki")).is_ok());
+
+    let errors = validate_config(&config("Europe/Helsiki")).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/localization/timezone");
+    assert_eq!(errors[0].code, "invalid_timezone");
+}
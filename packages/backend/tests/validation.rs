@@ -0,0 +1,373 @@
+use backend::options_schema::OptionsSchema;
+use backend::schema_types::Config;
+use backend::validate_config;
+use backend::validation::{
+    is_persistent_path, parse_config, semantic_errors, validate_hostname, ValidationError,
+};
+use serde_json::{json, Value};
+
+/// A node running geth and lighthouse with their data on a persistent mount.
+fn valid_node() -> Value {
+    json!({
+        "localization": { "hostname": "example" },
+        "ssh": {
+            "authorizedKeys": [
+                "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKhrzD3JMfXTSO1vK0qFvEwtfCeVH+i6Upq8VksFXY++ user@host"
+            ]
+        },
+        "execution": {
+            "geth": {
+                "enable": true,
+                "endpoint": "http://127.0.0.1:8551",
+                "dataDir": "/mnt/eth/geth",
+                "jwtSecretFile": "/mnt/eth/jwt.hex"
+            }
+        },
+        "consensus": {
+            "lighthouse": {
+                "enable": true,
+                "endpoint": "http://127.0.0.1:5052",
+                "execEndpoint": "http://127.0.0.1:8551",
+                "dataDir": "/mnt/eth/lighthouse",
+                "jwtSecretFile": "/mnt/eth/jwt.hex"
+            }
+        },
+        "mounts": {
+            "eth": {
+                "enable": true,
+                "type": "ext4",
+                "what": "/dev/sda1",
+                "where": "/mnt/eth"
+            }
+        }
+    })
+}
+
+fn problems(value: Value) -> Vec<ValidationError> {
+    let config: Config = serde_json::from_value(value).expect("Config should deserialize");
+    semantic_errors(&config)
+}
+
+fn errors(value: Value) -> Vec<String> {
+    problems(value).into_iter().map(|e| e.message).collect()
+}
+
+/// The options of the module that `valid_node` uses, in the shape of the `.#schema` export.
+fn schema_json() -> Value {
+    let option = |type_name: &str| json!({ "type": type_name, "default": null });
+    let client = json!({
+        "enable": option("bool"),
+        "endpoint": option("str"),
+        "execEndpoint": option("str"),
+        "dataDir": option("path"),
+        "jwtSecretFile": option("path"),
+    });
+    let mut mounts = option("attrsOf (submodule)");
+    mounts["options"] = json!({
+        "enable": option("bool"),
+        "type": option("str"),
+        "what": option("str"),
+        "where": option("str"),
+    });
+    json!({
+        "localization": { "hostname": option("str"), "timezone": option("str") },
+        "ssh": { "authorizedKeys": option("listOf (str)") },
+        "execution": { "geth": client.clone() },
+        "consensus": { "lighthouse": client },
+        "mounts": mounts,
+    })
+}
+
+fn schema() -> OptionsSchema {
+    OptionsSchema::from_json(&schema_json()).unwrap()
+}
+
+/// The pointers and codes of the problems found while parsing `value`.
+fn parse_problems(value: &Value) -> Vec<(String, &'static str)> {
+    parse_config(&value.to_string(), Some(&schema()))
+        .expect_err("Parsing should fail")
+        .into_iter()
+        .map(|e| (e.pointer, e.code))
+        .collect()
+}
+
+#[test]
+fn test_valid_node() {
+    assert!(errors(valid_node()).is_empty());
+    let config: Config = serde_json::from_value(valid_node()).unwrap();
+    assert!(validate_config(&config).is_ok());
+}
+
+#[test]
+fn test_no_clients() {
+    let mut node = valid_node();
+    node.as_object_mut().unwrap().remove("execution");
+    node.as_object_mut().unwrap().remove("consensus");
+    assert!(errors(node).is_empty());
+}
+
+#[test]
+fn test_consensus_without_execution() {
+    let mut node = valid_node();
+    node["execution"]["geth"]["enable"] = json!(false);
+    assert_eq!(
+        errors(node),
+        vec!["The 'consensus.lighthouse' client needs an enabled execution client"]
+    );
+}
+
+#[test]
+fn test_exec_endpoint_mismatch() {
+    let mut node = valid_node();
+    node["consensus"]["lighthouse"]["execEndpoint"] = json!("http://127.0.0.1:8552");
+    let errors = errors(node);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("The 'consensus.lighthouse.execEndpoint'"));
+    assert!(errors[0].contains("'http://127.0.0.1:8551' (execution.geth)"));
+}
+
+#[test]
+fn test_exec_endpoint_ignores_scheme_and_path() {
+    let mut node = valid_node();
+    node["consensus"]["lighthouse"]["execEndpoint"] = json!("https://127.0.0.1:8551/");
+    assert!(errors(node).is_empty());
+}
+
+#[test]
+fn test_jwt_secret_mismatch() {
+    let mut node = valid_node();
+    node["consensus"]["lighthouse"]["jwtSecretFile"] = json!("/mnt/eth/other.hex");
+    assert_eq!(
+        errors(node),
+        vec![
+            "The 'consensus.lighthouse.jwtSecretFile' '/mnt/eth/other.hex' differs from \
+             'execution.geth.jwtSecretFile' '/mnt/eth/jwt.hex'"
+        ]
+    );
+}
+
+#[test]
+fn test_jwt_secret_default() {
+    // A client without a jwtSecretFile uses the module's default.
+    let mut node = valid_node();
+    node["execution"]["geth"]
+        .as_object_mut()
+        .unwrap()
+        .remove("jwtSecretFile");
+    node["consensus"]["lighthouse"]["jwtSecretFile"] = json!("/mnt/secrets/jwt.hex");
+    assert!(errors(node).is_empty());
+}
+
+#[test]
+fn test_data_dir_not_persistent() {
+    let mut node = valid_node();
+    node["consensus"]["lighthouse"]["dataDir"] = json!("/var/lib/lighthouse");
+    assert_eq!(
+        errors(node),
+        vec!["The 'consensus.lighthouse.dataDir' '/var/lib/lighthouse' is not on an enabled persistent mount"]
+    );
+}
+
+#[test]
+fn test_data_dir_on_disabled_or_volatile_mount() {
+    let mut node = valid_node();
+    node["mounts"]["eth"]["enable"] = json!(false);
+    assert_eq!(errors(node).len(), 2);
+
+    let mut node = valid_node();
+    node["mounts"]["eth"]["type"] = json!("tmpfs");
+    assert_eq!(errors(node).len(), 2);
+}
+
+#[test]
+fn test_ssv_node_data_dir() {
+    let mut node = valid_node();
+    node["addons"] = json!({ "ssv-node": { "dataDir": "/var/lib/ssv" } });
+    assert_eq!(
+        errors(node.clone()),
+        vec!["The 'addons.ssv-node.dataDir' '/var/lib/ssv' is not on an enabled persistent mount"]
+    );
+
+    // Without clients the SSV node is not started, so its dataDir does not matter.
+    node["consensus"]["lighthouse"]["enable"] = json!(false);
+    node["execution"]["geth"]["enable"] = json!(false);
+    assert!(errors(node).is_empty());
+}
+
+#[test]
+fn test_validate_config_reports_all_errors() {
+    let mut node = valid_node();
+    node["consensus"]["lighthouse"]["jwtSecretFile"] = json!("/mnt/eth/other.hex");
+    node["execution"]["geth"]["dataDir"] = json!("/tmp/geth");
+    let config: Config = serde_json::from_value(node).unwrap();
+    let pointers: Vec<String> = validate_config(&config)
+        .unwrap_err()
+        .into_iter()
+        .map(|e| e.pointer)
+        .collect();
+    assert_eq!(
+        pointers,
+        vec![
+            "/consensus/lighthouse/jwtSecretFile",
+            "/execution/geth/dataDir"
+        ]
+    );
+}
+
+#[test]
+fn test_semantic_error_pointers_and_codes() {
+    let mut node = valid_node();
+    node["consensus"]["lighthouse"]["execEndpoint"] = json!("http://127.0.0.1:8552");
+    let mismatch = problems(node);
+    assert_eq!(mismatch[0].pointer, "/consensus/lighthouse/execEndpoint");
+    assert_eq!(mismatch[0].code, "exec_endpoint_mismatch");
+
+    let mut node = valid_node();
+    node["execution"]["geth"]["enable"] = json!(false);
+    let missing = problems(node);
+    assert_eq!(missing[0].pointer, "/consensus/lighthouse/enable");
+    assert_eq!(missing[0].code, "missing_execution_client");
+}
+
+#[test]
+fn test_invalid_ssh_key() {
+    let mut node = valid_node();
+    node["ssh"]["authorizedKeys"]
+        .as_array_mut()
+        .unwrap()
+        .push(json!("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIKhrzD3J"));
+    let config: Config = serde_json::from_value(node).unwrap();
+    let errors = validate_config(&config).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/ssh/authorizedKeys/1");
+    assert_eq!(errors[0].code, "invalid_ssh_key");
+}
+
+#[test]
+fn test_validate_hostname() {
+    for hostname in ["example", "node-1", "a", "0xdead", &"a".repeat(63)] {
+        assert!(validate_hostname(hostname).is_ok(), "{hostname:?}");
+    }
+    for hostname in [
+        "",
+        "-node",
+        "node-",
+        "node.example.com",
+        "node_1",
+        "../../etc",
+        "nöde",
+        &"a".repeat(64),
+    ] {
+        assert!(validate_hostname(hostname).is_err(), "{hostname:?}");
+    }
+}
+
+#[test]
+fn test_invalid_hostname() {
+    let mut node = valid_node();
+    node["localization"]["hostname"] = json!("../escape");
+    let config: Config = serde_json::from_value(node).unwrap();
+    let errors = validate_config(&config).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/localization/hostname");
+    assert_eq!(errors[0].code, "invalid_hostname");
+}
+
+#[test]
+fn test_parse_valid_config() {
+    assert!(parse_config(&valid_node().to_string(), None).is_ok());
+    assert!(parse_config(&valid_node().to_string(), Some(&schema())).is_ok());
+}
+
+#[test]
+fn test_parse_new_module_option() {
+    // An option that the config types do not model is checked against the schema and, if the
+    // module has it, kept for the Nix expression.
+    let mut node = valid_node();
+    node["localization"]["keyMap"] = json!("fi");
+    assert_eq!(
+        parse_problems(&node),
+        vec![("/localization/keyMap".to_string(), "unknown_field")]
+    );
+
+    // Without a schema, nothing vouches for the option.
+    let errors = parse_config(&node.to_string(), None).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/localization/keyMap");
+    assert_eq!(errors[0].code, "unknown_field");
+
+    let mut options = schema_json();
+    options["localization"]["keyMap"] = json!({ "type": "str" });
+    let schema = OptionsSchema::from_json(&options).unwrap();
+    let config = parse_config(&node.to_string(), Some(&schema)).unwrap();
+    assert_eq!(config.localization.extra["keyMap"], json!("fi"));
+
+    node["localization"]["keyMap"] = json!(1);
+    let errors = parse_config(&node.to_string(), Some(&schema)).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "/localization/keyMap");
+    assert_eq!(errors[0].code, "invalid_type");
+}
+
+#[test]
+fn test_parse_invalid_json() {
+    let errors = parse_config("{ \"localization\": ", None).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].pointer, "");
+    assert_eq!(errors[0].code, "invalid_json");
+}
+
+#[test]
+fn test_parse_collects_all_problems() {
+    let mut node = valid_node();
+    node["consensus"]["lighthouse"]["execEndpoint"] = json!(8551);
+    node["mounts"]["eth"]["extra"] = json!(true);
+    node["localization"]["timezone"] = json!(["Europe/Helsinki"]);
+    let mut problems = parse_problems(&node);
+    problems.sort();
+    assert_eq!(
+        problems,
+        vec![
+            (
+                "/consensus/lighthouse/execEndpoint".to_string(),
+                "invalid_type"
+            ),
+            ("/localization/timezone".to_string(), "invalid_type"),
+            ("/mounts/eth/extra".to_string(), "unknown_field"),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_missing_field() {
+    let mut node = valid_node();
+    node["localization"]
+        .as_object_mut()
+        .unwrap()
+        .remove("hostname");
+    assert_eq!(
+        parse_problems(&node),
+        vec![("/localization/hostname".to_string(), "missing_field")]
+    );
+}
+
+#[test]
+fn test_parse_escapes_pointer_tokens() {
+    let mut node = valid_node();
+    node["mounts"]["a/b~c"] = json!({ "enable": "yes" });
+    assert_eq!(
+        parse_problems(&node)[0],
+        ("/mounts/a~1b~0c/enable".to_string(), "invalid_type")
+    );
+}
+
+#[test]
+fn test_is_persistent_path_whole_components() {
+    let mut node = valid_node();
+    node["mounts"]["eth"]["where"] = json!("/mnt/e");
+    let config: Config = serde_json::from_value(node).unwrap();
+    assert!(!is_persistent_path(config.mounts.as_ref(), "/mnt/eth/geth"));
+    assert!(is_persistent_path(config.mounts.as_ref(), "/mnt/e/geth"));
+    assert!(is_persistent_path(config.mounts.as_ref(), "/mnt/e"));
+    assert!(!is_persistent_path(None, "/mnt/e"));
+}
@@ -5,8 +5,35 @@ fn test_global_workspace_creation() -> Result<(), Box<dyn std::error::Error>> {
     let workspace = Workspace::new()?;
 
     // Verify that the workspace directories exists.
-    assert!(workspace.base_dir.path().exists());
-    assert!(workspace.base_dir.path().join("builds").exists());
+    assert!(workspace.base_dir.exists());
+    assert!(workspace.base_dir.join("builds").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_state_dir_workspace() -> Result<(), Box<dyn std::error::Error>> {
+    let state_dir = tempfile::tempdir()?;
+    let workspace = Workspace::with_state_dir(state_dir.path())?;
+    assert!(state_dir.path().join("builds").exists());
+
+    // The output directory of a build outlives both the build and the workspace.
+    let output_dir = workspace
+        .new_build_workspace("testhost")?
+        .output_dir
+        .clone();
+    drop(workspace);
+    assert!(output_dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_temporary_workspace_cleanup() -> Result<(), Box<dyn std::error::Error>> {
+    let workspace = Workspace::new()?;
+    let base_dir = workspace.base_dir.clone();
+    drop(workspace);
+    assert!(!base_dir.exists());
 
     Ok(())
 }
@@ -28,6 +55,21 @@ fn test_build_workspace_creation() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_host_dir() -> Result<(), Box<dyn std::error::Error>> {
+    let workspace = Workspace::new()?;
+    let build_ws = workspace.new_build_workspace("testhost")?;
+
+    // Other hosts of a cluster go next to the built host.
+    let host_dir = build_ws.host_dir("otherhost")?;
+    assert!(host_dir.is_dir());
+    assert_eq!(host_dir.parent(), build_ws.hostname_dir.parent());
+    assert_eq!(build_ws.host_dir("testhost")?, build_ws.hostname_dir);
+    assert!(build_ws.host_dir("../escape").is_err());
+
+    Ok(())
+}
+
 #[test]
 fn test_cleanup() -> Result<(), Box<dyn std::error::Error>> {
     let workspace = Workspace::new()?;
@@ -47,3 +89,36 @@ fn test_cleanup() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_discard() -> Result<(), Box<dyn std::error::Error>> {
+    let workspace = Workspace::new()?;
+    let build_ws = workspace.new_build_workspace("testhost")?;
+    let working_dir = build_ws.working_dir.clone();
+    let output_dir = build_ws.output_dir.clone();
+
+    // Discarding a build removes its partial output too.
+    build_ws.discard();
+    assert!(!working_dir.exists());
+    assert!(!output_dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_build_workspace_rejects_path_traversal() -> Result<(), Box<dyn std::error::Error>> {
+    let workspace = Workspace::new()?;
+    for hostname in ["../../etc", "..", ".", "a/b", "/etc", ""] {
+        assert!(
+            workspace.new_build_workspace(hostname).is_err(),
+            "{hostname:?} should be rejected"
+        );
+    }
+    assert!(workspace.output_dir("../escape").is_err());
+    assert!(workspace.gc_root("..").is_err());
+
+    let build = workspace.new_build_workspace("testhost")?;
+    assert!(build.hostname_dir.starts_with(&workspace.base_dir));
+    assert_eq!(workspace.output_dir(&build.uuid)?, build.output_dir);
+    Ok(())
+}
@@ -5,6 +5,7 @@ export interface Artifact {
   download_url: string;
   file: string;
   sha256: string;
+  target?: string;
 }
 
 const ArtifactsList = ({ artifacts }: { artifacts: Artifact[] }) => {
@@ -254,6 +254,10 @@ export const ConfigurationForm = () => {
   const loader: any = useLoaderData();
   const [_, s]: any = useOutletContext();
   const [artifacts, setArtifacts] = useState<Artifact[]>([]);
+  const [buildStatus, setBuildStatus] = useState<string | null>(null);
+  const [buildLog, setBuildLog] = useState<string[]>([]);
+  const [buildTarget, setBuildTarget] = useState("kexec");
+  const [authorizedKeys, setAuthorizedKeys] = useState<{ algorithm: string, fingerprint: string, comment?: string }[]>([]);
 
   let props = {
     schema: s.value,
@@ -270,9 +274,56 @@ export const ConfigurationForm = () => {
     return obj
   }
 
+  const waitForBuild = async (backendUrl: String, buildId: string) => {
+    // Follow the nix log while the build runs
+    const logSource = new EventSource(`${backendUrl}/builds/${buildId}/logs`);
+    logSource.addEventListener('log', (e: MessageEvent) => {
+      setBuildLog((lines) => [...lines, e.data].slice(-20));
+    });
+    logSource.addEventListener('end', () => logSource.close());
+    try {
+      return await pollBuild(backendUrl, buildId);
+    } finally {
+      logSource.close();
+    }
+  }
+
+  const pollBuild = async (backendUrl: String, buildId: string) => {
+    while (true) {
+      const response = await fetch(`${backendUrl}/builds/${buildId}/status`, { mode: 'cors' });
+      if (!response.ok) {
+        throw new Error(`HTTP error! Status: ${response.status}`);
+      }
+      const build = await response.json();
+      setBuildStatus(build.queue_position ? `${build.status} (position ${build.queue_position} in queue)` : build.status);
+      if (["succeeded", "failed", "cancelled"].includes(build.status)) {
+        return build;
+      }
+      await new Promise((resolve) => setTimeout(resolve, 5000));
+    }
+  }
+
+  // Marks the form fields that the backend rejected, given their JSON pointers
+  const highlightFields = (form: HTMLFormElement, errors: { pointer: string }[]) => {
+    errors.forEach(({ pointer }) => {
+      const tokens = pointer
+        .split('/')
+        .slice(1)
+        .map((t) => t.replace(/~1/g, '/').replace(/~0/g, '~'))
+        .map((t) => (/^\d+$/.test(t) ? parseInt(t) : t))
+      if (tokens.length === 0) return
+      for (const root of ['schema', 'nodes']) {
+        const field = form.elements.namedItem(jp.stringify([root, ...tokens]))
+        if (field instanceof Element) field.setAttribute('aria-invalid', 'true')
+      }
+    })
+  }
+
   const handleSubmit = async (e: React.FormEvent<HTMLFormElement>, backendUrl: String) => {
     e.preventDefault()
     setArtifacts([])
+    const form = e.target as HTMLFormElement
+    form.querySelectorAll('[aria-invalid="true"]').forEach((el) => el.removeAttribute('aria-invalid'))
     const result = recursiveReplace(structuredClone(props.schema))
     const formData = new FormData(e.target as HTMLFormElement)
     const formDataJson = Object.fromEntries(formData.entries())
@@ -376,9 +427,12 @@ export const ConfigurationForm = () => {
       }
     })
     setIsLoading(true);
+    setBuildStatus(null);
+    setBuildLog([]);
+    setAuthorizedKeys([]);
     setError(null);
     try {
-      const response = await fetch(`${backendUrl}/nixosConfig`, {
+      const response = await fetch(`${backendUrl}/nixosConfig?target=${buildTarget}`, {
         method: 'POST',
         headers: {
           'Access-Control-Allow-Origin': '*',
@@ -391,16 +445,33 @@ export const ConfigurationForm = () => {
       if (!response.ok) {
         const errorData = await response.json();
         let errorMessage = ""
-        if (errorData.message && errorData.error)
+        if (Array.isArray(errorData.errors)) {
+          // Validation problems point at the offending fields
+          highlightFields(form, errorData.errors)
+          errorMessage = `${errorData.message}:\n` + errorData.errors
+            .map((err: { pointer: string, message: string }) => `${err.pointer || '/'}: ${err.message}`)
+            .join('\n')
+        }
+        else if (errorData.message && errorData.error)
           errorMessage = `${errorData.message}:\n ${errorData.error}`
         else
           errorMessage = `HTTP error! Status: ${response.status}`
         throw new Error(errorMessage);
       }
       const responseData = await response.json();
-      if (responseData.status === "ok" && responseData.artifacts) {
+      if (responseData.status !== "ok" || !responseData.build_id) {
+        throw new Error("Error: No build id returned.");
+      }
+      setAuthorizedKeys(responseData.authorized_keys ?? []);
+
+      // The build runs in the background, so poll its status until it finishes
+      const build = await waitForBuild(backendUrl, responseData.build_id);
+      if (build.status === "failed" || build.status === "cancelled") {
+        throw new Error(build.error ?? `Build ${build.status}`);
+      }
+      if (build.artifacts && build.artifacts.length > 0) {
         // Artifact links are relative so we add the backend url as prefix
-        let afrtifacts: Artifact[] = responseData.artifacts;
+        let afrtifacts: Artifact[] = build.artifacts;
         afrtifacts.forEach((a) => {
           a.download_url = `${backendUrl}${a.download_url}`;
         });
@@ -454,12 +525,12 @@ export const ConfigurationForm = () => {
             </Link>
           </Flex>
           <OrderedList>
-            <ListItem>Select features below</ListItem>
+            <ListItem>Select features and the build target below</ListItem>
             <ListItem>Click on #BUIDL</ListItem>
             <ListItem>Build artifact links will appear for download</ListItem>
             <ListItem>
               Execute the <a href="https://en.wikipedia.org/wiki/Kexec">kexec</a> script on an existing Linux distribution
-              to boot
+              to boot, or write the ISO or disk image onto a USB stick or disk
             </ListItem>
           </OrderedList>
         </Box>
@@ -473,6 +544,14 @@ export const ConfigurationForm = () => {
             <Text fontSize="xl" fontWeight="bold" color="gray.600">
               BUIDL in progress!
             </Text>
+            {buildStatus && (
+              <Text color="gray.600">Status: {buildStatus}</Text>
+            )}
+            {buildLog.length > 0 && (
+              <Box as="pre" fontSize="xs" maxWidth="100%" overflowX="auto" p={2} borderWidth="1px" borderRadius="md">
+                {buildLog.join('\n')}
+              </Box>
+            )}
           </VStack>
         )}
         <VStack spacing={4} align="stretch" maxWidth="600px" margin="auto" mb={10}>
@@ -480,14 +559,33 @@ export const ConfigurationForm = () => {
             <Alert status="error">
               <AlertIcon />
               <AlertTitle>Error!</AlertTitle>
-              <AlertDescription>{error}</AlertDescription>
+              <AlertDescription whiteSpace="pre-line">{error}</AlertDescription>
             </Alert>
           )}
 
           {artifacts.length > 0 && (
             <ArtifactsList artifacts={artifacts} />
           )}
+
+          {authorizedKeys.length > 0 && (
+            <Box borderWidth="1px" borderRadius="lg" p={4}>
+              <Text fontWeight="bold" mb={2}>SSH access is granted to these keys:</Text>
+              {authorizedKeys.map((key) => (
+                <Text key={key.fingerprint} fontSize="sm" fontFamily="monospace">
+                  {key.fingerprint} {key.comment ?? ''} ({key.algorithm})
+                </Text>
+              ))}
+            </Box>
+          )}
         </VStack>
+        {/* Not a named form field, so it is not mistaken for a config option */}
+        <Select value={buildTarget} onChange={e => setBuildTarget(e.target.value)} mb={4}>
+          <option value="kexec">kexec tree (boot from an existing Linux)</option>
+          <option value="netboot">Netboot / PXE tree</option>
+          <option value="iso">Bootable ISO image</option>
+          <option value="raw-image">Raw disk image</option>
+          <option value="vm">QEMU VM runner (for testing)</option>
+        </Select>
         <Button w="100%" type="submit">
           #BUIDL
         </Button>